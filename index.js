@@ -7,7 +7,6 @@ start_game('canvas');
 
 document.getElementById('play').onclick = function() {
     document.getElementById('instructions').remove();
-    document.getElementById('background-music').play();
 }
 
 window.addEventListener('message', function (message) {
@@ -15,6 +14,28 @@ window.addEventListener('message', function (message) {
 
     let state = message.data;
 
+    if (state.loading_progress != null) {
+        document.getElementById('loading-progress').innerText = `${Math.round(state.loading_progress * 100)}`;
+    }
+
+    if (state.loading_errors) {
+        let list = document.getElementById('loading-errors');
+        list.innerHTML = '';
+        for (let error of state.loading_errors) {
+            let item = document.createElement('li');
+            item.innerText = error;
+            list.appendChild(item);
+        }
+    }
+
+    // Game state is only posted once the match has started
+    if (state.current_player != null) {
+        let loading = document.getElementById('loading');
+        if (loading) {
+            loading.remove();
+        }
+    }
+
     if (state.current_player != null) {
         document.getElementById('player').innerText = (state.current_player + 1) + "";
     }
@@ -27,9 +48,13 @@ window.addEventListener('message', function (message) {
         document.getElementById('power').innerText = `${state.cannon_power}`;
     }
 
-    if (state.game_over) {
-        document.getElementById('winner').style = `color: ${state.player_color}`;
-        document.getElementById('winner-id').innerText = (state.current_player + 1) + "";
-        document.getElementById('game-over').style = '';
+    // The title, setup and results screens are drawn by the game itself
+    if (state.phase) {
+        document.getElementById('round-ui').style.visibility = state.phase === 'in_round' ? 'visible' : 'hidden';
+    }
+
+    if (state.round) {
+        document.getElementById('round').innerText = `${state.round}`;
+        document.getElementById('rounds').innerText = `${state.rounds}`;
     }
 });
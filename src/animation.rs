@@ -0,0 +1,225 @@
+use crate::{atlas::AtlasManifest, sprite::Sprite};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaybackMode {
+    Loop,
+    // Stops on the last frame
+    Once,
+    // Plays forwards then backwards, forever
+    PingPong,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationFrame {
+    pub uv: [f32; 4],
+    pub duration: f32,
+}
+
+#[derive(Clone)]
+pub struct Animation {
+    frames: Vec<AnimationFrame>,
+    mode: PlaybackMode,
+    current: usize,
+    forwards: bool,
+    elapsed: f32,
+    finished: bool,
+}
+
+impl Animation {
+    // Frames that take no time would never let `update` move on
+    pub fn new(frames: Vec<AnimationFrame>, mode: PlaybackMode) -> Result<Animation, String> {
+        if frames.is_empty() {
+            return Err(String::from("An animation needs at least one frame"));
+        }
+        if let Some(i) = frames.iter().position(|frame| frame.duration.is_nan() || frame.duration <= 0.0) {
+            return Err(format!("Animation frame {} has a duration of {}", i, frames[i].duration));
+        }
+
+        Ok(Animation {
+            frames,
+            mode,
+            current: 0,
+            forwards: true,
+            elapsed: 0.0,
+            finished: false,
+        })
+    }
+
+    /*
+     * Builds an animation from the atlas frames `<name>_0.png` to
+     * `<name>_<count - 1>.png`. Frames without a duration in the
+     * manifest are shown for `default_duration` seconds.
+     */
+    pub fn from_atlas(
+        manifest: &AtlasManifest,
+        name: &str,
+        count: usize,
+        default_duration: f32,
+        mode: PlaybackMode,
+    ) -> Result<Animation, String> {
+        let frames = (0..count)
+            .map(|i| {
+                let frame_name = format!("{}_{}.png", name, i);
+                let duration = manifest
+                    .frame(&frame_name)
+                    .and_then(|frame| frame.duration)
+                    .unwrap_or(default_duration);
+
+                Ok(AnimationFrame {
+                    uv: manifest.uv(&frame_name)?,
+                    duration,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Animation::new(frames, mode).map_err(|e| format!("Animation '{}': {}", name, e))
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.finished {
+            return;
+        }
+
+        self.elapsed += dt;
+
+        while self.elapsed >= self.frames[self.current].duration {
+            self.elapsed -= self.frames[self.current].duration;
+            self.advance();
+
+            if self.finished {
+                self.elapsed = 0.0;
+                return;
+            }
+        }
+    }
+
+    pub fn uv(&self) -> [f32; 4] {
+        self.frames[self.current].uv
+    }
+
+    // Only animations played once ever finish
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn advance(&mut self) {
+        let last = self.frames.len() - 1;
+
+        match self.mode {
+            PlaybackMode::Loop => self.current = if self.current == last { 0 } else { self.current + 1 },
+            PlaybackMode::Once => {
+                if self.current == last {
+                    self.finished = true;
+                } else {
+                    self.current += 1;
+                }
+            }
+            PlaybackMode::PingPong => {
+                if last == 0 {
+                    return;
+                }
+
+                if self.forwards && self.current == last {
+                    self.forwards = false;
+                } else if !self.forwards && self.current == 0 {
+                    self.forwards = true;
+                }
+
+                if self.forwards {
+                    self.current += 1;
+                } else {
+                    self.current -= 1;
+                }
+            }
+        }
+    }
+}
+
+// A sprite whose texture coordinates follow an animation
+pub struct AnimatedSprite {
+    pub sprite: Sprite,
+    pub animation: Animation,
+}
+
+impl AnimatedSprite {
+    pub fn new(mut sprite: Sprite, animation: Animation) -> AnimatedSprite {
+        sprite.uv = animation.uv();
+        AnimatedSprite { sprite, animation }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.animation.update(dt);
+        self.sprite.uv = self.animation.uv();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Animation, AnimationFrame, PlaybackMode};
+
+    fn frames(durations: &[f32]) -> Vec<AnimationFrame> {
+        durations
+            .iter()
+            .enumerate()
+            .map(|(i, &duration)| AnimationFrame {
+                uv: [i as f32, 0.0, 1.0, 1.0],
+                duration,
+            })
+            .collect()
+    }
+
+    fn sequence(animation: &mut Animation, steps: usize, dt: f32) -> Vec<usize> {
+        (0..steps)
+            .map(|_| {
+                animation.update(dt);
+                // Test frames store their index in u
+                animation.uv()[0] as usize
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loop_wraps_around() {
+        let mut animation = Animation::new(frames(&[1.0, 1.0, 1.0]), PlaybackMode::Loop).unwrap();
+        assert_eq!(vec![1, 2, 0, 1], sequence(&mut animation, 4, 1.0));
+        assert!(!animation.is_finished());
+    }
+
+    #[test]
+    fn once_holds_last_frame() {
+        let mut animation = Animation::new(frames(&[1.0, 1.0, 1.0]), PlaybackMode::Once).unwrap();
+        assert_eq!(vec![1, 2, 2, 2], sequence(&mut animation, 4, 1.0));
+        assert!(animation.is_finished());
+    }
+
+    #[test]
+    fn ping_pong_reverses_at_the_ends() {
+        let mut animation = Animation::new(frames(&[1.0, 1.0, 1.0]), PlaybackMode::PingPong).unwrap();
+        assert_eq!(vec![1, 2, 1, 0, 1, 2], sequence(&mut animation, 6, 1.0));
+    }
+
+    #[test]
+    fn per_frame_durations() {
+        let mut animation = Animation::new(frames(&[0.1, 0.5, 0.1]), PlaybackMode::Loop).unwrap();
+        assert_eq!(vec![1, 1, 1, 1, 1, 2], sequence(&mut animation, 6, 0.1));
+        assert_eq!([2.0, 0.0, 1.0, 1.0], animation.uv());
+    }
+
+    #[test]
+    fn large_steps_skip_frames() {
+        let mut animation = Animation::new(frames(&[0.1, 0.1, 0.1, 0.1]), PlaybackMode::Loop).unwrap();
+        animation.update(0.25);
+        assert_eq!(2.0, animation.uv()[0]);
+    }
+
+    #[test]
+    fn frames_must_take_time() {
+        assert!(Animation::new(Vec::new(), PlaybackMode::Loop).is_err());
+        assert_eq!(
+            Err(String::from("Animation frame 1 has a duration of 0")),
+            Animation::new(frames(&[0.1, 0.0]), PlaybackMode::Loop).map(|_| ())
+        );
+        assert!(Animation::new(frames(&[-0.1]), PlaybackMode::Once).is_err());
+    }
+}
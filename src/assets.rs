@@ -0,0 +1,434 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use serde::Deserialize;
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{AudioBuffer, AudioContext, WebGl2RenderingContext, XmlHttpRequest, XmlHttpRequestResponseType};
+
+use crate::resources::Texture;
+use crate::texture::{load_image_as_texture, TextureOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetKind {
+    Texture,
+    Audio,
+    // Text files such as atlas manifests and font descriptors
+    Data,
+}
+
+fn required_by_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AssetEntry {
+    pub name: String,
+    pub kind: AssetKind,
+    pub path: String,
+    // The match does not start until every required asset has loaded
+    #[serde(default = "required_by_default")]
+    pub required: bool,
+    // Only used by textures
+    #[serde(default)]
+    pub options: TextureOptions,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssetManifest {
+    pub assets: Vec<AssetEntry>,
+}
+
+impl AssetManifest {
+    pub fn parse(json: &str) -> Result<AssetManifest, String> {
+        let manifest: AssetManifest =
+            serde_json::from_str(json).map_err(|e| format!("Invalid asset manifest: {}", e))?;
+
+        for (i, entry) in manifest.assets.iter().enumerate() {
+            if manifest.assets[..i].iter().any(|other| other.name == entry.name) {
+                return Err(format!("Asset '{}' is listed twice", entry.name));
+            }
+        }
+
+        Ok(manifest)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssetStatus {
+    Pending,
+    Loaded,
+    Failed(String),
+}
+
+struct TrackedAsset {
+    name: String,
+    required: bool,
+    status: AssetStatus,
+}
+
+// Load state of every asset in a manifest
+pub struct AssetTracker {
+    assets: Vec<TrackedAsset>,
+}
+
+impl AssetTracker {
+    pub fn new(manifest: &AssetManifest) -> AssetTracker {
+        AssetTracker {
+            assets: manifest
+                .assets
+                .iter()
+                .map(|entry| TrackedAsset {
+                    name: entry.name.clone(),
+                    required: entry.required,
+                    status: AssetStatus::Pending,
+                })
+                .collect(),
+        }
+    }
+
+    // Only the first result for an asset counts, later events are ignored
+    pub fn finish(&mut self, name: &str, result: Result<(), String>) {
+        if let Some(asset) = self.assets.iter_mut().find(|asset| asset.name == name) {
+            if asset.status == AssetStatus::Pending {
+                asset.status = match result {
+                    Ok(()) => AssetStatus::Loaded,
+                    Err(e) => AssetStatus::Failed(e),
+                };
+            }
+        }
+    }
+
+    // Fraction of assets that have either loaded or failed
+    pub fn progress(&self) -> f32 {
+        if self.assets.is_empty() {
+            return 1.0;
+        }
+
+        let settled = self
+            .assets
+            .iter()
+            .filter(|asset| asset.status != AssetStatus::Pending)
+            .count();
+        settled as f32 / self.assets.len() as f32
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.assets
+            .iter()
+            .filter(|asset| asset.required)
+            .all(|asset| asset.status == AssetStatus::Loaded)
+    }
+
+    // True once a required asset failed, the match can never start
+    pub fn has_failed(&self) -> bool {
+        self.assets
+            .iter()
+            .any(|asset| asset.required && matches!(asset.status, AssetStatus::Failed(_)))
+    }
+
+    pub fn errors(&self) -> Vec<String> {
+        self.assets
+            .iter()
+            .filter_map(|asset| match &asset.status {
+                AssetStatus::Failed(e) => Some(format!("{}: {}", asset.name, e)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/*
+ * Starts loading every asset of a manifest and keeps track of them.
+ * Textures are usable straight away (they show a placeholder), data
+ * files once they have loaded. Sounds are decoded into audio buffers
+ * and added to the shared sound map as they finish.
+ */
+pub struct AssetManager {
+    tracker: Rc<RefCell<AssetTracker>>,
+    textures: HashMap<String, Rc<Texture>>,
+    sounds: Rc<RefCell<HashMap<String, AudioBuffer>>>,
+    data: Rc<RefCell<HashMap<String, String>>>,
+}
+
+impl AssetManager {
+    pub fn load(
+        gl: &WebGl2RenderingContext,
+        audio: &AudioContext,
+        manifest: &AssetManifest,
+    ) -> Result<AssetManager, JsValue> {
+        let mut manager = AssetManager {
+            tracker: Rc::new(RefCell::new(AssetTracker::new(manifest))),
+            textures: HashMap::new(),
+            sounds: Rc::new(RefCell::new(HashMap::new())),
+            data: Rc::new(RefCell::new(HashMap::new())),
+        };
+
+        for entry in &manifest.assets {
+            match entry.kind {
+                AssetKind::Texture => manager.load_texture(gl, entry)?,
+                AssetKind::Audio => manager.load_sound(audio, entry)?,
+                AssetKind::Data => manager.load_data(entry)?,
+            }
+        }
+
+        Ok(manager)
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.tracker.borrow().progress()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.tracker.borrow().is_ready()
+    }
+
+    pub fn has_failed(&self) -> bool {
+        self.tracker.borrow().has_failed()
+    }
+
+    pub fn errors(&self) -> Vec<String> {
+        self.tracker.borrow().errors()
+    }
+
+    pub fn texture(&self, name: &str) -> Result<Rc<Texture>, JsValue> {
+        self.textures
+            .get(name)
+            .cloned()
+            .ok_or_else(|| JsValue::from(format!("No texture asset named '{}'", name)))
+    }
+
+    // Keeps filling up after the match has started, optional sounds may still be loading
+    pub fn sounds(&self) -> Rc<RefCell<HashMap<String, AudioBuffer>>> {
+        self.sounds.clone()
+    }
+
+    pub fn data(&self, name: &str) -> Result<String, JsValue> {
+        self.data
+            .borrow()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| JsValue::from(format!("Data asset '{}' has not loaded", name)))
+    }
+
+    fn load_texture(&mut self, gl: &WebGl2RenderingContext, entry: &AssetEntry) -> Result<(), JsValue> {
+        let tracker = self.tracker.clone();
+        let name = entry.name.clone();
+        let texture = load_image_as_texture(gl, &entry.path, &entry.options, move |result| {
+            tracker.borrow_mut().finish(&name, result);
+        })?;
+
+        self.textures.insert(entry.name.clone(), texture);
+        Ok(())
+    }
+
+    fn load_sound(&mut self, audio: &AudioContext, entry: &AssetEntry) -> Result<(), JsValue> {
+        let request = Rc::new(XmlHttpRequest::new()?);
+        request.open("GET", &entry.path)?;
+        request.set_response_type(XmlHttpRequestResponseType::Arraybuffer);
+
+        {
+            let tracker = self.tracker.clone();
+            let sounds = self.sounds.clone();
+            let audio = audio.clone();
+            let name = entry.name.clone();
+            let loaded_request = request.clone();
+            let on_load_callback = Closure::wrap(Box::new(move || {
+                let status = loaded_request.status().unwrap_or(0);
+                let data = loaded_request
+                    .response()
+                    .ok()
+                    .and_then(|response| response.dyn_into::<js_sys::ArrayBuffer>().ok());
+
+                let data = match data {
+                    Some(data) if (200..300).contains(&status) => data,
+                    _ => {
+                        tracker
+                            .borrow_mut()
+                            .finish(&name, Err(format!("Request failed with status {}", status)));
+                        return;
+                    }
+                };
+
+                if let Err(e) = decode_sound(&audio, &data, &name, tracker.clone(), sounds.clone()) {
+                    tracker
+                        .borrow_mut()
+                        .finish(&name, Err(format!("Could not decode audio: {:?}", e)));
+                }
+            }) as Box<dyn FnMut()>);
+            request.set_onload(Some(on_load_callback.as_ref().unchecked_ref()));
+            on_load_callback.forget();
+        }
+
+        {
+            let tracker = self.tracker.clone();
+            let name = entry.name.clone();
+            let on_error_callback = Closure::wrap(Box::new(move || {
+                tracker
+                    .borrow_mut()
+                    .finish(&name, Err(String::from("Could not reach the server")));
+            }) as Box<dyn FnMut()>);
+            request.set_onerror(Some(on_error_callback.as_ref().unchecked_ref()));
+            on_error_callback.forget();
+        }
+
+        request.send()?;
+        Ok(())
+    }
+
+    fn load_data(&mut self, entry: &AssetEntry) -> Result<(), JsValue> {
+        let request = Rc::new(XmlHttpRequest::new()?);
+        request.open("GET", &entry.path)?;
+
+        {
+            let tracker = self.tracker.clone();
+            let data = self.data.clone();
+            let name = entry.name.clone();
+            let loaded_request = request.clone();
+            let on_load_callback = Closure::wrap(Box::new(move || {
+                let status = loaded_request.status().unwrap_or(0);
+                let text = loaded_request.response_text().ok().flatten();
+
+                let result = match text {
+                    Some(text) if (200..300).contains(&status) => {
+                        data.borrow_mut().insert(name.clone(), text);
+                        Ok(())
+                    }
+                    _ => Err(format!("Request failed with status {}", status)),
+                };
+                tracker.borrow_mut().finish(&name, result);
+            }) as Box<dyn FnMut()>);
+            request.set_onload(Some(on_load_callback.as_ref().unchecked_ref()));
+            on_load_callback.forget();
+        }
+
+        {
+            let tracker = self.tracker.clone();
+            let name = entry.name.clone();
+            let on_error_callback = Closure::wrap(Box::new(move || {
+                tracker
+                    .borrow_mut()
+                    .finish(&name, Err(String::from("Could not reach the server")));
+            }) as Box<dyn FnMut()>);
+            request.set_onerror(Some(on_error_callback.as_ref().unchecked_ref()));
+            on_error_callback.forget();
+        }
+
+        request.send()?;
+        Ok(())
+    }
+}
+
+fn decode_sound(
+    audio: &AudioContext,
+    data: &js_sys::ArrayBuffer,
+    name: &str,
+    tracker: Rc<RefCell<AssetTracker>>,
+    sounds: Rc<RefCell<HashMap<String, AudioBuffer>>>,
+) -> Result<(), JsValue> {
+    let promise = audio.decode_audio_data(data)?;
+
+    let decoded_tracker = tracker.clone();
+    let decoded_name = name.to_string();
+    let on_decoded_callback = Closure::wrap(Box::new(move |buffer: JsValue| {
+        let result = match buffer.dyn_into::<AudioBuffer>() {
+            Ok(buffer) => {
+                sounds.borrow_mut().insert(decoded_name.clone(), buffer);
+                Ok(())
+            }
+            Err(_) => Err(String::from("Decoding did not produce an audio buffer")),
+        };
+        decoded_tracker.borrow_mut().finish(&decoded_name, result);
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let failed_name = name.to_string();
+    let on_failed_callback = Closure::wrap(Box::new(move |_: JsValue| {
+        tracker
+            .borrow_mut()
+            .finish(&failed_name, Err(String::from("Could not decode audio")));
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let _ = promise.then2(&on_decoded_callback, &on_failed_callback);
+    on_decoded_callback.forget();
+    on_failed_callback.forget();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{AssetKind, AssetManifest, AssetTracker};
+
+    const MANIFEST: &str = r#"{
+        "assets": [
+            { "name": "ground", "kind": "texture", "path": "assets/ground.jpg", "options": { "mipmaps": true } },
+            { "name": "font", "kind": "data", "path": "assets/font.fnt" },
+            { "name": "launch", "kind": "audio", "path": "assets/launch.wav", "required": false }
+        ]
+    }"#;
+
+    #[test]
+    fn parse_manifest() {
+        let manifest = AssetManifest::parse(MANIFEST).unwrap();
+
+        assert_eq!(3, manifest.assets.len());
+        assert_eq!(AssetKind::Data, manifest.assets[1].kind);
+        assert!(manifest.assets[0].required);
+        assert!(!manifest.assets[2].required);
+        assert!(manifest.assets[0].options.mipmaps);
+    }
+
+    #[test]
+    fn shipped_images_are_mipmapped() {
+        let manifest = AssetManifest::parse(include_str!("../assets/manifest.json")).unwrap();
+        let textures = manifest.assets.iter().filter(|asset| asset.kind == AssetKind::Texture);
+
+        // Everything is drawn scaled down when zoomed out
+        for texture in textures {
+            assert!(texture.options.mipmaps, "{} is not mipmapped", texture.name);
+        }
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let result = AssetManifest::parse(
+            r#"{ "assets": [
+                { "name": "a", "kind": "texture", "path": "a.png" },
+                { "name": "a", "kind": "audio", "path": "a.wav" }
+            ] }"#,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn ready_once_required_assets_load() {
+        let mut tracker = AssetTracker::new(&AssetManifest::parse(MANIFEST).unwrap());
+        assert_eq!(0.0, tracker.progress());
+        assert!(!tracker.is_ready());
+
+        tracker.finish("ground", Ok(()));
+        tracker.finish("font", Ok(()));
+
+        // The optional sound is still loading
+        assert!(tracker.is_ready());
+        assert!((tracker.progress() - 2.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn required_failure_is_reported() {
+        let mut tracker = AssetTracker::new(&AssetManifest::parse(MANIFEST).unwrap());
+
+        tracker.finish("ground", Err(String::from("Could not load image")));
+        tracker.finish("ground", Ok(()));
+        tracker.finish("launch", Err(String::from("Could not load audio")));
+
+        assert!(tracker.has_failed());
+        assert!(!tracker.is_ready());
+        assert_eq!(
+            vec!["ground: Could not load image", "launch: Could not load audio"],
+            tracker.errors()
+        );
+    }
+}
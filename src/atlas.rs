@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use serde::Deserialize;
+use wasm_bindgen::JsValue;
+
+use crate::resources::Texture;
+
+#[derive(Deserialize)]
+struct ManifestRect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+struct ManifestSize {
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+struct ManifestFrame {
+    #[serde(default)]
+    filename: Option<String>,
+    frame: ManifestRect,
+    #[serde(default)]
+    rotated: bool,
+    // Milliseconds, an optional extra on TexturePacker frames
+    #[serde(default)]
+    duration: Option<f32>,
+}
+
+// TexturePacker exports frames either keyed by name or as a list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ManifestFrames {
+    Hash(HashMap<String, ManifestFrame>),
+    Array(Vec<ManifestFrame>),
+}
+
+#[derive(Deserialize)]
+struct ManifestMeta {
+    size: ManifestSize,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    frames: ManifestFrames,
+    meta: ManifestMeta,
+}
+
+pub struct AtlasFrame {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    // Seconds to show the frame for when it is part of an animation
+    pub duration: Option<f32>,
+}
+
+/*
+ * Frame layout of a packed texture, read from a TexturePacker
+ * JSON (hash or array) export. Rotated frames are not supported.
+ */
+pub struct AtlasManifest {
+    pub width: f32,
+    pub height: f32,
+    frames: HashMap<String, AtlasFrame>,
+}
+
+impl AtlasManifest {
+    pub fn parse(json: &str) -> Result<AtlasManifest, String> {
+        let manifest: Manifest =
+            serde_json::from_str(json).map_err(|e| format!("Invalid atlas manifest: {}", e))?;
+
+        let entries: Vec<(String, ManifestFrame)> = match manifest.frames {
+            ManifestFrames::Hash(frames) => frames.into_iter().collect(),
+            ManifestFrames::Array(frames) => frames
+                .into_iter()
+                .map(|frame| {
+                    let name = frame
+                        .filename
+                        .clone()
+                        .ok_or_else(|| String::from("Atlas frame is missing a filename"))?;
+                    Ok((name, frame))
+                })
+                .collect::<Result<_, String>>()?,
+        };
+
+        let mut frames = HashMap::new();
+        for (name, frame) in entries {
+            if frame.rotated {
+                return Err(format!("Atlas frame '{}' is rotated", name));
+            }
+
+            frames.insert(
+                name,
+                AtlasFrame {
+                    x: frame.frame.x,
+                    y: frame.frame.y,
+                    width: frame.frame.w,
+                    height: frame.frame.h,
+                    duration: frame.duration.map(|milliseconds| milliseconds / 1000.0),
+                },
+            );
+        }
+
+        Ok(AtlasManifest {
+            width: manifest.meta.size.w,
+            height: manifest.meta.size.h,
+            frames,
+        })
+    }
+
+    pub fn frame(&self, name: &str) -> Option<&AtlasFrame> {
+        self.frames.get(name)
+    }
+
+    // Texture coordinates of a frame as [u, v, width, height]
+    pub fn uv(&self, name: &str) -> Result<[f32; 4], String> {
+        let frame = self
+            .frame(name)
+            .ok_or_else(|| format!("Atlas has no frame named '{}'", name))?;
+
+        Ok([
+            frame.x / self.width,
+            frame.y / self.height,
+            frame.width / self.width,
+            frame.height / self.height,
+        ])
+    }
+}
+
+pub struct TextureAtlas {
+    pub manifest: AtlasManifest,
+    texture: Rc<Texture>,
+}
+
+impl TextureAtlas {
+    // `texture` holds the image referenced by the manifest
+    pub fn new(manifest: &str, texture: Rc<Texture>) -> Result<TextureAtlas, JsValue> {
+        let manifest = AtlasManifest::parse(manifest)?;
+        Ok(TextureAtlas { manifest, texture })
+    }
+
+    pub fn texture(&self) -> Rc<Texture> {
+        self.texture.clone()
+    }
+
+    pub fn uv(&self, name: &str) -> Result<[f32; 4], JsValue> {
+        Ok(self.manifest.uv(name)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::AtlasManifest;
+
+    #[test]
+    fn parse_hash_manifest() {
+        let manifest = AtlasManifest::parse(
+            r#"{
+                "frames": {
+                    "tank.png": { "frame": {"x":64,"y":0,"w":32,"h":16}, "rotated": false }
+                },
+                "meta": { "image": "sheet.png", "size": {"w":128,"h":64} }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!([0.5, 0.0, 0.25, 0.25], manifest.uv("tank.png").unwrap());
+        assert!(manifest.uv("missing.png").is_err());
+    }
+
+    #[test]
+    fn parse_array_manifest() {
+        let manifest = AtlasManifest::parse(
+            r#"{
+                "frames": [
+                    { "filename": "a.png", "frame": {"x":0,"y":32,"w":16,"h":16}, "duration": 250 }
+                ],
+                "meta": { "image": "sheet.png", "size": {"w":64,"h":64} }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(32.0, manifest.frame("a.png").unwrap().y);
+        assert_eq!(Some(0.25), manifest.frame("a.png").unwrap().duration);
+    }
+
+    #[test]
+    fn rotated_frames_are_rejected() {
+        let result = AtlasManifest::parse(
+            r#"{
+                "frames": { "a.png": { "frame": {"x":0,"y":0,"w":1,"h":1}, "rotated": true } },
+                "meta": { "image": "sheet.png", "size": {"w":1,"h":1} }
+            }"#,
+        );
+
+        assert!(result.is_err());
+    }
+}
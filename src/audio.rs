@@ -0,0 +1,609 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use wasm_bindgen::JsValue;
+use web_sys::{console, AudioBuffer, AudioBufferSourceNode, AudioContext, AudioParam, AudioScheduledSourceNode, GainNode};
+
+use crate::settings::AudioSettings;
+
+/*
+ * Sound playback on top of the Web Audio API.
+ *
+ * Every play starts a new voice, so a sound can overlap itself.
+ * A voice is routed through the gain of its category:
+ *
+ *   buffer source (pitch) -> voice gain -> stereo panner -> category gain -> speakers
+ *
+ * `AudioEngine` makes the mixing decisions (gains, panning, pitch,
+ * voice limits, fades, ducking) and drives an `AudioBackend`, which
+ * only has to make the sounds. `WebAudioBackend` is the one used in
+ * the browser. Times are kept on the clock of the backend, which
+ * stands still while the browser keeps audio suspended.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoundCategory {
+    Music,
+    Sfx,
+    Ui,
+}
+
+const CATEGORIES: [SoundCategory; 3] = [SoundCategory::Music, SoundCategory::Sfx, SoundCategory::Ui];
+
+// The same sound played more often than this cuts off its oldest voice
+const MAX_VOICES_PER_SOUND: usize = 4;
+// Beyond this a sound plays at zero or negative speed
+const MAX_PITCH_VARIATION: f32 = 0.5;
+// How far a sound at the edge of the screen is panned, full panning sounds odd on headphones
+const STEREO_WIDTH: f32 = 0.75;
+// How fast ducked music comes back, in gain per second
+const DUCK_RECOVERY: f32 = 0.8;
+// Time constant of category gain changes, avoids clicks when ducking
+const GAIN_SMOOTHING: f64 = 0.05;
+
+pub type VoiceId = u32;
+
+// What the backend needs to start a voice, worked out by the engine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Voice {
+    pub category: SoundCategory,
+    pub gain: f32,
+    // -1 is fully left, 1 fully right
+    pub pan: f32,
+    // 1 plays at the recorded pitch and speed
+    pub playback_rate: f32,
+    // Seconds to ramp up from silence
+    pub fade_in: f32,
+    pub looping: bool,
+}
+
+pub trait AudioBackend {
+    // Seconds on the audio clock
+    fn now(&self) -> f64;
+    // Returns the length of the sound in seconds, or None when it cannot play (yet)
+    fn start(&mut self, id: VoiceId, sound: &str, voice: &Voice) -> Option<f32>;
+    // Also called once a voice has played out, so the backend can let go of it
+    fn stop(&mut self, id: VoiceId);
+    // Ramps the gain of a playing voice over `seconds`
+    fn fade_voice(&mut self, id: VoiceId, gain: f32, seconds: f32);
+    fn set_category_gain(&mut self, category: SoundCategory, gain: f32);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayOptions {
+    pub gain: f32,
+    pub pan: f32,
+    // Largest random change of the playback rate, 0.1 plays between 0.9 and 1.1, at most 0.5
+    pub pitch_variation: f32,
+    pub fade_in: f32,
+    // Looping voices play until they are stopped
+    pub looping: bool,
+}
+
+impl Default for PlayOptions {
+    fn default() -> Self {
+        PlayOptions {
+            gain: 1.0,
+            pan: 0.0,
+            pitch_variation: 0.0,
+            fade_in: 0.0,
+            looping: false,
+        }
+    }
+}
+
+// Pan for a sound at `screen_x` on a screen `screen_width` wide
+pub fn stereo_pan(screen_x: f32, screen_width: f32) -> f32 {
+    if screen_width <= 0.0 {
+        return 0.0;
+    }
+
+    ((screen_x / screen_width) * 2.0 - 1.0).clamp(-1.0, 1.0) * STEREO_WIDTH
+}
+
+// `random` is between 0 and 1
+pub fn playback_rate(pitch_variation: f32, random: f32) -> f32 {
+    1.0 + pitch_variation.clamp(0.0, MAX_PITCH_VARIATION) * (2.0 * random - 1.0)
+}
+
+// `ducking` lowers the music only, from 0 (untouched) to 1 (silent)
+pub fn category_gain(settings: &AudioSettings, category: SoundCategory, ducking: f32) -> f32 {
+    if settings.muted {
+        return 0.0;
+    }
+
+    let volume = match category {
+        SoundCategory::Music => settings.music_volume * (1.0 - ducking.clamp(0.0, 1.0)),
+        SoundCategory::Sfx => settings.sfx_volume,
+        SoundCategory::Ui => settings.ui_volume,
+    };
+    (settings.volume * volume).clamp(0.0, 1.0)
+}
+
+struct ActiveVoice {
+    id: VoiceId,
+    sound: String,
+    // On the backend clock, infinite for looping voices
+    ends_at: f64,
+}
+
+pub struct AudioEngine<B: AudioBackend> {
+    backend: B,
+    settings: AudioSettings,
+    voices: Vec<ActiveVoice>,
+    next_id: VoiceId,
+    ducking: f32,
+}
+
+impl<B: AudioBackend> AudioEngine<B> {
+    pub fn new(backend: B, settings: &AudioSettings) -> AudioEngine<B> {
+        let mut engine = AudioEngine {
+            backend,
+            settings: *settings,
+            voices: Vec::new(),
+            next_id: 0,
+            ducking: 0.0,
+        };
+        engine.apply_settings();
+        engine
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn settings(&self) -> &AudioSettings {
+        &self.settings
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.settings.muted = muted;
+        self.apply_settings();
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.settings.volume = volume.clamp(0.0, 1.0);
+        self.apply_settings();
+    }
+
+    pub fn play(&mut self, sound: &str, category: SoundCategory, options: &PlayOptions) -> Option<VoiceId> {
+        let voice = Voice {
+            category,
+            gain: options.gain.max(0.0),
+            pan: options.pan.clamp(-1.0, 1.0),
+            playback_rate: playback_rate(options.pitch_variation, rand::random::<f32>()),
+            fade_in: options.fade_in.max(0.0),
+            looping: options.looping,
+        };
+
+        let id = self.next_id;
+        let duration = self.backend.start(id, sound, &voice)?;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        // Only once the new voice plays, so a sound that fails to start does not silence itself
+        let playing: Vec<VoiceId> = self
+            .voices
+            .iter()
+            .filter(|voice| voice.sound == sound)
+            .map(|voice| voice.id)
+            .collect();
+        if playing.len() >= MAX_VOICES_PER_SOUND {
+            // Voices are kept in the order they started
+            self.stop(playing[0]);
+        }
+
+        let ends_at = if voice.looping {
+            f64::INFINITY
+        } else {
+            self.backend.now() + (duration / voice.playback_rate) as f64
+        };
+        self.voices.push(ActiveVoice {
+            id,
+            sound: sound.to_string(),
+            ends_at,
+        });
+        Some(id)
+    }
+
+    // Seconds until the voice has played out, None once it has
+    pub fn remaining(&self, id: VoiceId) -> Option<f32> {
+        let now = self.backend.now();
+        self.voices
+            .iter()
+            .find(|voice| voice.id == id)
+            .map(|voice| (voice.ends_at - now).max(0.0) as f32)
+    }
+
+    // Fades the voice to silence and stops it
+    pub fn fade_out(&mut self, id: VoiceId, seconds: f32) {
+        let now = self.backend.now();
+        if let Some(voice) = self.voices.iter_mut().find(|voice| voice.id == id) {
+            voice.ends_at = voice.ends_at.min(now + seconds.max(0.0) as f64);
+            self.backend.fade_voice(id, 0.0, seconds);
+        }
+    }
+
+    // Lowers the music by `amount`, it comes back up on its own
+    pub fn duck_music(&mut self, amount: f32) {
+        if amount > self.ducking {
+            self.ducking = amount.min(1.0);
+            self.apply_settings();
+        }
+    }
+
+    pub fn stop(&mut self, id: VoiceId) {
+        if let Some(index) = self.voices.iter().position(|voice| voice.id == id) {
+            self.voices.remove(index);
+            self.backend.stop(id);
+        }
+    }
+
+    // Forgets the voices that have played out and lets ducked music recover
+    pub fn update(&mut self, dt: f32) {
+        if self.ducking > 0.0 {
+            self.ducking = (self.ducking - DUCK_RECOVERY * dt).max(0.0);
+            self.apply_settings();
+        }
+
+        let now = self.backend.now();
+        let backend = &mut self.backend;
+        self.voices.retain(|voice| {
+            let playing = voice.ends_at > now;
+            if !playing {
+                backend.stop(voice.id);
+            }
+            playing
+        });
+    }
+
+    fn apply_settings(&mut self) {
+        for category in CATEGORIES {
+            let gain = category_gain(&self.settings, category, self.ducking);
+            self.backend.set_category_gain(category, gain);
+        }
+    }
+}
+
+/*
+ * Plays decoded buffers through an `AudioContext`. Buffers are decoded
+ * by the asset manager and show up in `buffers` as they finish, a sound
+ * played before that is skipped.
+ */
+pub struct WebAudioBackend {
+    context: AudioContext,
+    buffers: Rc<RefCell<HashMap<String, AudioBuffer>>>,
+    categories: HashMap<SoundCategory, GainNode>,
+    sources: HashMap<VoiceId, (AudioBufferSourceNode, GainNode)>,
+}
+
+impl WebAudioBackend {
+    pub fn new(
+        context: &AudioContext,
+        buffers: Rc<RefCell<HashMap<String, AudioBuffer>>>,
+    ) -> Result<WebAudioBackend, JsValue> {
+        let mut categories = HashMap::new();
+        for category in CATEGORIES {
+            let gain = context.create_gain()?;
+            gain.connect_with_audio_node(&context.destination())?;
+            categories.insert(category, gain);
+        }
+
+        Ok(WebAudioBackend {
+            context: context.clone(),
+            buffers,
+            categories,
+            sources: HashMap::new(),
+        })
+    }
+
+    // Browsers keep the context suspended until the page gets a click or a key press
+    pub fn resume(&self) {
+        if let Err(e) = self.context.resume() {
+            console::log_2(&"Could not resume audio:".into(), &e);
+        }
+    }
+
+    fn start_source(
+        &self,
+        buffer: &AudioBuffer,
+        voice: &Voice,
+    ) -> Result<(AudioBufferSourceNode, GainNode), JsValue> {
+        let source = self.context.create_buffer_source()?;
+        source.set_buffer(Some(buffer));
+        source.set_loop(voice.looping);
+        source.playback_rate().set_value(voice.playback_rate);
+
+        let gain = self.context.create_gain()?;
+        if voice.fade_in > 0.0 {
+            gain.gain().set_value(0.0);
+            ramp(&gain.gain(), voice.gain, self.now(), voice.fade_in)?;
+        } else {
+            gain.gain().set_value(voice.gain);
+        }
+
+        let panner = self.context.create_stereo_panner()?;
+        panner.pan().set_value(voice.pan);
+
+        source.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&panner)?;
+        panner.connect_with_audio_node(&self.categories[&voice.category])?;
+
+        let scheduled: &AudioScheduledSourceNode = &source;
+        scheduled.start()?;
+        Ok((source, gain))
+    }
+}
+
+// Linear ramp from the current value, replacing any ramp in progress
+fn ramp(param: &AudioParam, value: f32, now: f64, seconds: f32) -> Result<(), JsValue> {
+    param.cancel_scheduled_values(now)?;
+    param.set_value_at_time(param.value(), now)?;
+    param.linear_ramp_to_value_at_time(value, now + seconds.max(0.0) as f64)?;
+    Ok(())
+}
+
+impl AudioBackend for WebAudioBackend {
+    fn now(&self) -> f64 {
+        self.context.current_time()
+    }
+
+    fn start(&mut self, id: VoiceId, sound: &str, voice: &Voice) -> Option<f32> {
+        let buffer = self.buffers.borrow().get(sound).cloned()?;
+
+        match self.start_source(&buffer, voice) {
+            Ok(nodes) => {
+                self.sources.insert(id, nodes);
+                Some(buffer.duration() as f32)
+            }
+            Err(e) => {
+                console::log_2(&format!("Could not play {}:", sound).into(), &e);
+                None
+            }
+        }
+    }
+
+    fn stop(&mut self, id: VoiceId) {
+        if let Some((source, _)) = self.sources.remove(&id) {
+            // Stopping a source that has already ended does nothing
+            let scheduled: &AudioScheduledSourceNode = &source;
+            let _ = scheduled.stop();
+        }
+    }
+
+    fn fade_voice(&mut self, id: VoiceId, gain: f32, seconds: f32) {
+        if let Some((_, voice_gain)) = self.sources.get(&id) {
+            if let Err(e) = ramp(&voice_gain.gain(), gain, self.now(), seconds) {
+                console::log_2(&"Could not fade voice:".into(), &e);
+            }
+        }
+    }
+
+    fn set_category_gain(&mut self, category: SoundCategory, gain: f32) {
+        let param = self.categories[&category].gain();
+        if let Err(e) = param.set_target_at_time(gain, self.now(), GAIN_SMOOTHING) {
+            console::log_2(&"Could not set volume:".into(), &e);
+        }
+    }
+}
+
+// Records what the engine asks for
+#[cfg(test)]
+pub struct RecordingBackend {
+    // Shared so tests can move the clock while the engine owns the backend
+    pub time: Rc<std::cell::Cell<f64>>,
+    // Every sound lasts this many seconds
+    pub sound_length: f32,
+    // While set every start fails, as for a sound that is still decoding
+    pub refusing: Rc<std::cell::Cell<bool>>,
+    pub playing: Vec<(VoiceId, String, Voice)>,
+    pub fades: Vec<(VoiceId, f32, f32)>,
+    pub gains: HashMap<SoundCategory, f32>,
+}
+
+#[cfg(test)]
+impl Default for RecordingBackend {
+    fn default() -> Self {
+        RecordingBackend {
+            time: Rc::default(),
+            sound_length: 1.0,
+            refusing: Rc::default(),
+            playing: Vec::new(),
+            fades: Vec::new(),
+            gains: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl RecordingBackend {
+    pub fn is_playing(&self, sound: &str) -> bool {
+        self.playing.iter().any(|(_, playing, _)| playing == sound)
+    }
+}
+
+#[cfg(test)]
+impl AudioBackend for RecordingBackend {
+    fn now(&self) -> f64 {
+        self.time.get()
+    }
+
+    fn start(&mut self, id: VoiceId, sound: &str, voice: &Voice) -> Option<f32> {
+        if sound == "missing" || self.refusing.get() {
+            return None;
+        }
+        self.playing.push((id, sound.to_string(), *voice));
+        Some(self.sound_length)
+    }
+
+    fn stop(&mut self, id: VoiceId) {
+        self.playing.retain(|(playing, _, _)| *playing != id);
+    }
+
+    fn fade_voice(&mut self, id: VoiceId, gain: f32, seconds: f32) {
+        self.fades.push((id, gain, seconds));
+    }
+
+    fn set_category_gain(&mut self, category: SoundCategory, gain: f32) {
+        self.gains.insert(category, gain);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{category_gain, playback_rate, stereo_pan, AudioEngine, PlayOptions, RecordingBackend, SoundCategory};
+    use crate::settings::AudioSettings;
+
+    #[test]
+    fn categories_are_scaled_by_the_master_volume() {
+        let settings = AudioSettings {
+            volume: 0.5,
+            music_volume: 0.4,
+            sfx_volume: 1.0,
+            ui_volume: 0.0,
+            muted: false,
+        };
+        assert!((category_gain(&settings, SoundCategory::Music, 0.0) - 0.2).abs() < 1e-6);
+        assert_eq!(0.5, category_gain(&settings, SoundCategory::Sfx, 0.0));
+        assert_eq!(0.0, category_gain(&settings, SoundCategory::Ui, 0.0));
+
+        let mut engine = AudioEngine::new(RecordingBackend::default(), &settings);
+        assert_eq!(Some(&0.5), engine.backend().gains.get(&SoundCategory::Sfx));
+
+        engine.set_muted(true);
+        assert!(engine.backend().gains.values().all(|gain| *gain == 0.0));
+    }
+
+    #[test]
+    fn voices_overlap_up_to_a_limit() {
+        let backend = RecordingBackend::default();
+        let clock = backend.time.clone();
+        let mut engine = AudioEngine::new(backend, &AudioSettings::default());
+
+        let first = engine.play("boom", SoundCategory::Sfx, &PlayOptions::default());
+        for _ in 0..3 {
+            engine.play("boom", SoundCategory::Sfx, &PlayOptions::default());
+        }
+        engine.play("click", SoundCategory::Ui, &PlayOptions::default());
+        assert_eq!(5, engine.backend().playing.len());
+
+        // The oldest voice of the sound makes room
+        engine.play("boom", SoundCategory::Sfx, &PlayOptions::default());
+        assert_eq!(5, engine.backend().playing.len());
+        assert!(engine.backend().playing.iter().all(|(id, _, _)| Some(*id) != first));
+
+        assert_eq!(None, engine.play("missing", SoundCategory::Sfx, &PlayOptions::default()));
+        assert_eq!(5, engine.backend().playing.len());
+
+        clock.set(1.5);
+        engine.update(1.5);
+        assert!(engine.backend().playing.is_empty());
+    }
+
+    #[test]
+    fn looping_voices_play_until_faded_out() {
+        let backend = RecordingBackend::default();
+        let clock = backend.time.clone();
+        let mut engine = AudioEngine::new(backend, &AudioSettings::default());
+
+        let options = PlayOptions {
+            looping: true,
+            fade_in: 2.0,
+            ..PlayOptions::default()
+        };
+        let music = engine.play("theme", SoundCategory::Music, &options).unwrap();
+
+        clock.set(60.0);
+        engine.update(60.0);
+        assert!(engine.backend().is_playing("theme"));
+
+        engine.fade_out(music, 1.5);
+        assert_eq!(Some(1.5), engine.remaining(music));
+        assert_eq!(vec![(music, 0.0, 1.5)], engine.backend().fades);
+
+        clock.set(61.5);
+        engine.update(1.5);
+        assert_eq!(None, engine.remaining(music));
+        assert!(!engine.backend().is_playing("theme"));
+    }
+
+    #[test]
+    fn explosions_duck_the_music_for_a_moment() {
+        let settings = AudioSettings {
+            volume: 1.0,
+            music_volume: 1.0,
+            ..AudioSettings::default()
+        };
+        let mut engine = AudioEngine::new(RecordingBackend::default(), &settings);
+        let music = |engine: &AudioEngine<RecordingBackend>| engine.backend().gains[&SoundCategory::Music];
+
+        engine.duck_music(0.6);
+        engine.duck_music(0.2);
+        assert!((music(&engine) - 0.4).abs() < 1e-6);
+        assert_eq!(1.0, engine.backend().gains[&SoundCategory::Sfx]);
+
+        engine.update(0.25);
+        assert!((music(&engine) - 0.6).abs() < 1e-6);
+
+        engine.update(1.0);
+        assert_eq!(1.0, music(&engine));
+    }
+
+    #[test]
+    fn sounds_are_panned_by_screen_position() {
+        assert_eq!(0.0, stereo_pan(400.0, 800.0));
+        assert_eq!(-0.75, stereo_pan(0.0, 800.0));
+        assert_eq!(0.75, stereo_pan(2000.0, 800.0));
+
+        let mut engine = AudioEngine::new(RecordingBackend::default(), &AudioSettings::default());
+        let options = PlayOptions {
+            pan: stereo_pan(600.0, 800.0),
+            pitch_variation: 0.1,
+            ..PlayOptions::default()
+        };
+        engine.play("boom", SoundCategory::Sfx, &options);
+
+        let voice = engine.backend().playing[0].2;
+        assert_eq!(0.375, voice.pan);
+        assert!(voice.playback_rate >= 0.9 && voice.playback_rate <= 1.1);
+    }
+
+    #[test]
+    fn pitch_varies_around_the_recorded_pitch() {
+        assert_eq!(1.0, playback_rate(0.0, 0.9));
+        assert!((playback_rate(0.1, 0.0) - 0.9).abs() < 1e-6);
+        assert!((playback_rate(0.1, 1.0) - 1.1).abs() < 1e-6);
+
+        // Too much variation is held back
+        assert_eq!(0.5, playback_rate(3.0, 0.0));
+        assert_eq!(1.5, playback_rate(3.0, 1.0));
+        assert_eq!(1.0, playback_rate(-0.2, 0.0));
+    }
+
+    #[test]
+    fn a_voice_that_fails_to_start_leaves_the_others_playing() {
+        let backend = RecordingBackend::default();
+        let refusing = backend.refusing.clone();
+        let mut engine = AudioEngine::new(backend, &AudioSettings::default());
+
+        for _ in 0..4 {
+            engine.play("boom", SoundCategory::Sfx, &PlayOptions::default());
+        }
+
+        refusing.set(true);
+        assert_eq!(None, engine.play("boom", SoundCategory::Sfx, &PlayOptions::default()));
+        assert_eq!(4, engine.backend().playing.len());
+
+        refusing.set(false);
+        let options = PlayOptions {
+            pitch_variation: 2.0,
+            ..PlayOptions::default()
+        };
+        let id = engine.play("boom", SoundCategory::Sfx, &options).unwrap();
+        assert_eq!(4, engine.backend().playing.len());
+
+        let voice = engine.backend().playing.iter().find(|(playing, _, _)| *playing == id).unwrap().2;
+        assert!(voice.playback_rate >= 0.5 && voice.playback_rate <= 1.5);
+    }
+}
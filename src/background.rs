@@ -0,0 +1,359 @@
+use std::rc::Rc;
+
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::{
+    camera::Camera,
+    render_queue::{BlendMode, RenderLayer},
+    resources::Texture,
+    settings::{HillLayer, MapSettings, TimeOfDay, Weather},
+    sprite::Sprite,
+    texture::{create_rgba_texture_from_u8_array, TextureOptions},
+    vector::Vec3,
+};
+
+const SKY_PARALLAX: f32 = 0.1;
+const CLOUD_PARALLAX: f32 = 0.2;
+// World units per second a cloud moves for each unit of wind
+const CLOUD_DRIFT: f32 = 0.8;
+
+const HILL_TEXTURE_WIDTH: u32 = 1024;
+const HILL_TEXTURE_HEIGHT: u32 = 256;
+const CLOUD_TEXTURE_WIDTH: u32 = 128;
+const CLOUD_TEXTURE_HEIGHT: u32 = 64;
+
+// Multiplied into the color of the sky, hills, clouds and terrain
+pub fn map_tint(time_of_day: TimeOfDay, weather: Weather) -> [f32; 4] {
+    let light = match time_of_day {
+        TimeOfDay::Day => [1.0, 1.0, 1.0],
+        TimeOfDay::Dusk => [1.0, 0.78, 0.62],
+        TimeOfDay::Night => [0.35, 0.4, 0.6],
+    };
+    let sky = match weather {
+        Weather::Clear => [1.0, 1.0, 1.0],
+        Weather::Overcast => [0.8, 0.82, 0.85],
+        Weather::Storm => [0.6, 0.62, 0.68],
+    };
+
+    [light[0] * sky[0], light[1] * sky[1], light[2] * sky[2], 1.0]
+}
+
+fn tinted(color: [f32; 4], tint: [f32; 4]) -> [f32; 4] {
+    [color[0] * tint[0], color[1] * tint[1], color[2] * tint[2], color[3] * tint[3]]
+}
+
+/*
+ * Top left and size of a layer scrolling at `parallax` times the speed
+ * of the camera. The layer is just big enough that its edges line up
+ * with the view at one end of the world and with the world at the other.
+ */
+pub fn parallax_rect(camera_position: Vec3, view: (f32, f32), world: (f32, f32), parallax: f32) -> (Vec3, Vec3) {
+    let top_left = camera_position.scaled(1.0 - parallax);
+    let size = Vec3::new(
+        view.0 + (world.0 - view.0).max(0.0) * parallax,
+        view.1 + (world.1 - view.1).max(0.0) * parallax,
+        1.0,
+    );
+    (top_left, size)
+}
+
+// Moves `x` by `dx`, wrapping an object of `width` around a strip `length` wide
+pub fn wrap_drift(x: f32, width: f32, dx: f32, length: f32) -> f32 {
+    let x = x + dx;
+    if x > length {
+        x - length - width
+    } else if x + width < 0.0 {
+        x + length + width
+    } else {
+        x
+    }
+}
+
+// Height of the ridge in each column, as a fraction of the texture height
+pub fn hill_ridge(columns: usize, hills: &HillLayer, phases: [f32; 3]) -> Vec<f32> {
+    (0..columns)
+        .map(|column| {
+            let x = column as f32 / columns as f32 * std::f32::consts::TAU;
+            // Broad swells with finer bumps on top, both within -1..1
+            let swell = (x * 2.0 + phases[0]).sin() * 0.6 + (x * 3.0 + phases[1]).sin() * 0.4;
+            let bumps = (x * 17.0 + phases[2]).sin() * 0.5 + (x * 31.0 + phases[0]).sin() * 0.5;
+            let shape = swell * (1.0 - hills.roughness) + bumps * hills.roughness;
+            hills.height * (0.7 + 0.3 * shape)
+        })
+        .collect()
+}
+
+// White below the ridge, transparent above it, with a one pixel soft edge
+fn hill_pixels(ridge: &[f32], height: u32) -> Vec<u8> {
+    let width = ridge.len();
+    let mut pixels = vec![0u8; width * height as usize * 4];
+
+    for y in 0..height as usize {
+        for (x, ridge_height) in ridge.iter().enumerate() {
+            let ridge_y = (1.0 - ridge_height) * height as f32;
+            let coverage = (y as f32 + 1.0 - ridge_y).clamp(0.0, 1.0);
+            let i = (y * width + x) * 4;
+            pixels[i..i + 4].copy_from_slice(&[255, 255, 255, (coverage * 255.0) as u8]);
+        }
+    }
+
+    pixels
+}
+
+// A soft white puff made of a few overlapping blobs
+pub fn cloud_pixels(width: u32, height: u32) -> Vec<u8> {
+    let blobs = [(0.3, 0.6, 0.25), (0.5, 0.45, 0.32), (0.7, 0.6, 0.24), (0.5, 0.7, 0.28)];
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            let u = (x as f32 + 0.5) / width as f32;
+            let v = (y as f32 + 0.5) / height as f32;
+            let density = blobs
+                .iter()
+                .map(|(bx, by, radius)| {
+                    // Vertical distances count half, the texture is twice as wide as high
+                    let distance = ((u - bx).powi(2) + ((v - by) * 0.5).powi(2)).sqrt() / (radius * 0.5);
+                    (1.0 - distance).clamp(0.0, 1.0)
+                })
+                .fold(0.0f32, f32::max);
+            let alpha = density * density * (3.0 - 2.0 * density);
+            pixels.extend_from_slice(&[255, 255, 255, (alpha * 255.0) as u8]);
+        }
+    }
+
+    pixels
+}
+
+struct ParallaxLayer {
+    sprite: Sprite,
+    parallax: f32,
+}
+
+struct Cloud {
+    sprite: Sprite,
+    // Position inside the cloud layer
+    offset: Vec3,
+    // Nearer clouds look faster
+    speed: f32,
+}
+
+/*
+ * Everything behind the terrain: the sky image, ranges of hills and
+ * clouds. Layers scroll slower than the camera the farther away they
+ * are, clouds also drift with the wind.
+ */
+pub struct Background {
+    layers: Vec<ParallaxLayer>,
+    clouds: Vec<Cloud>,
+    // Clouds are spread over the cloud layer on the first update, once its width is known
+    clouds_placed: bool,
+    cloud_size: (f32, f32),
+    world: (f32, f32),
+}
+
+impl Background {
+    pub fn new(
+        gl: &WebGl2RenderingContext,
+        sky: Rc<Texture>,
+        white: Rc<Texture>,
+        map: &MapSettings,
+        world_width: f32,
+        world_height: f32,
+    ) -> Result<Background, JsValue> {
+        let tint = map_tint(map.time_of_day, map.weather);
+
+        let mut sky_sprite = Sprite::new_with_mask(sky, white.clone())?;
+        sky_sprite.layer = RenderLayer::Background;
+        sky_sprite.blend = BlendMode::Opaque;
+        sky_sprite.color = tint;
+
+        let mut layers = vec![ParallaxLayer {
+            sprite: sky_sprite,
+            parallax: SKY_PARALLAX,
+        }];
+
+        for (i, hills) in map.hills.iter().enumerate() {
+            let phases = [
+                rand::random::<f32>() * std::f32::consts::TAU,
+                rand::random::<f32>() * std::f32::consts::TAU,
+                rand::random::<f32>() * std::f32::consts::TAU,
+            ];
+            let ridge = hill_ridge(HILL_TEXTURE_WIDTH as usize, hills, phases);
+            let texture = create_rgba_texture_from_u8_array(
+                gl,
+                HILL_TEXTURE_WIDTH,
+                HILL_TEXTURE_HEIGHT,
+                &hill_pixels(&ridge, HILL_TEXTURE_HEIGHT),
+                &TextureOptions::default(),
+            )?;
+
+            let mut sprite = Sprite::new_with_mask(texture, white.clone())?;
+            sprite.layer = RenderLayer::Background;
+            // In front of the sky and clouds, nearer ranges in front
+            sprite.z_order = 2.0 + i as f32;
+            sprite.color = tinted(hills.color, tint);
+            layers.push(ParallaxLayer {
+                sprite,
+                parallax: hills.parallax,
+            });
+        }
+
+        let cloud_count = match (map.clouds, map.weather) {
+            (false, _) => 0,
+            (true, Weather::Clear) => 4,
+            (true, Weather::Overcast) => 8,
+            (true, Weather::Storm) => 10,
+        };
+        let cloud_color = match map.weather {
+            Weather::Clear => [1.0, 1.0, 1.0, 0.85],
+            Weather::Overcast => [0.85, 0.86, 0.88, 0.9],
+            Weather::Storm => [0.55, 0.56, 0.6, 0.95],
+        };
+
+        let cloud_texture = create_rgba_texture_from_u8_array(
+            gl,
+            CLOUD_TEXTURE_WIDTH,
+            CLOUD_TEXTURE_HEIGHT,
+            &cloud_pixels(CLOUD_TEXTURE_WIDTH, CLOUD_TEXTURE_HEIGHT),
+            &TextureOptions::default(),
+        )?;
+        let cloud_size = (world_width * 0.12, world_width * 0.06);
+
+        let mut clouds = Vec::with_capacity(cloud_count);
+        for i in 0..cloud_count {
+            let scale = 0.7 + 0.6 * rand::random::<f32>();
+            let mut sprite = Sprite::new_with_mask(cloud_texture.clone(), white.clone())?;
+            sprite.layer = RenderLayer::Background;
+            sprite.z_order = 1.0;
+            sprite.color = tinted(cloud_color, tint);
+            sprite.global_scale = Vec3::new(cloud_size.0 * scale, cloud_size.1 * scale, 1.0);
+
+            // Spread out over the width, in the upper part of the sky; x is a fraction of the width until placed
+            let x = (i as f32 + rand::random::<f32>()) / cloud_count as f32;
+            let y = rand::random::<f32>() * world_height * 0.3;
+            clouds.push(Cloud {
+                sprite,
+                offset: Vec3::new(x, y, 0.0),
+                speed: scale,
+            });
+        }
+
+        Ok(Background {
+            layers,
+            clouds,
+            clouds_placed: false,
+            cloud_size,
+            world: (world_width, world_height),
+        })
+    }
+
+    pub fn update(&mut self, camera: &Camera, wind: f32, dt: f32) {
+        let view = (camera.view_width(), camera.view_height());
+
+        for layer in &mut self.layers {
+            let (top_left, size) = parallax_rect(camera.position, view, self.world, layer.parallax);
+            layer.sprite.global_position = top_left;
+            layer.sprite.global_scale = size;
+            layer.sprite.update();
+        }
+
+        let (top_left, size) = parallax_rect(camera.position, view, self.world, CLOUD_PARALLAX);
+        // The same width the drift wraps at, so no cloud starts out of range and jumps back
+        if !self.clouds_placed {
+            for cloud in &mut self.clouds {
+                cloud.offset = Vec3::new(cloud.offset.x() * size.x(), cloud.offset.y(), 0.0);
+            }
+            self.clouds_placed = true;
+        }
+
+        for cloud in &mut self.clouds {
+            let dx = wind * CLOUD_DRIFT * cloud.speed * dt;
+            let x = wrap_drift(cloud.offset.x(), self.cloud_size.0 * cloud.speed, dx, size.x());
+            cloud.offset = Vec3::new(x, cloud.offset.y(), 0.0);
+
+            cloud.sprite.global_position = top_left + cloud.offset;
+            cloud.sprite.update();
+        }
+    }
+
+    pub fn sprites(&self) -> impl Iterator<Item = &Sprite> {
+        self.layers
+            .iter()
+            .map(|layer| &layer.sprite)
+            .chain(self.clouds.iter().map(|cloud| &cloud.sprite))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{cloud_pixels, hill_ridge, map_tint, parallax_rect, wrap_drift};
+    use crate::settings::{HillLayer, TimeOfDay, Weather};
+    use crate::vector::Vec3;
+
+    #[test]
+    fn parallax_layers_line_up_at_both_ends_of_the_world() {
+        let view = (800.0, 1000.0);
+        let world = (2400.0, 1000.0);
+
+        let (top_left, size) = parallax_rect(Vec3::new(0.0, 0.0, 0.0), view, world, 0.25);
+        assert_eq!(0.0, top_left.x());
+        assert_eq!(1200.0, size.x());
+        assert_eq!(1000.0, size.y());
+
+        // At the far right the right edges of the layer and the world meet
+        let (top_left, size) = parallax_rect(Vec3::new(1600.0, 0.0, 0.0), view, world, 0.25);
+        assert_eq!(2400.0, top_left.x() + size.x());
+
+        // A layer that does not scroll matches the view
+        let (top_left, size) = parallax_rect(Vec3::new(1600.0, 0.0, 0.0), view, world, 0.0);
+        assert_eq!(1600.0, top_left.x());
+        assert_eq!(800.0, size.x());
+    }
+
+    #[test]
+    fn clouds_in_range_stay_put() {
+        assert_eq!(300.0, wrap_drift(300.0, 50.0, 0.0, 1000.0));
+        assert_eq!(1000.0, wrap_drift(1000.0, 50.0, 0.0, 1000.0));
+        assert_eq!(-50.0, wrap_drift(-50.0, 50.0, 0.0, 1000.0));
+    }
+
+    #[test]
+    fn clouds_wrap_around() {
+        assert_eq!(110.0, wrap_drift(100.0, 50.0, 10.0, 1000.0));
+        assert_eq!(-45.0, wrap_drift(995.0, 50.0, 10.0, 1000.0));
+        assert_eq!(995.0, wrap_drift(-45.0, 50.0, -10.0, 1000.0));
+    }
+
+    #[test]
+    fn ridges_stay_below_the_layer_height() {
+        let hills = HillLayer {
+            height: 0.4,
+            roughness: 0.8,
+            ..HillLayer::default()
+        };
+        let ridge = hill_ridge(256, &hills, [0.3, 1.2, 2.5]);
+
+        assert_eq!(256, ridge.len());
+        assert!(ridge.iter().all(|height| *height > 0.0 && *height <= 0.4));
+    }
+
+    #[test]
+    fn tint_combines_time_of_day_and_weather() {
+        assert_eq!([1.0, 1.0, 1.0, 1.0], map_tint(TimeOfDay::Day, Weather::Clear));
+
+        let night_storm = map_tint(TimeOfDay::Night, Weather::Storm);
+        assert!(night_storm[0] < 0.25 && night_storm[2] > night_storm[0]);
+    }
+
+    #[test]
+    fn cloud_edges_are_transparent() {
+        let pixels = cloud_pixels(32, 16);
+        let alpha = |x: usize, y: usize| pixels[(y * 32 + x) * 4 + 3];
+
+        assert_eq!(0, alpha(0, 0));
+        assert!(alpha(16, 8) > 200);
+    }
+}
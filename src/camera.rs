@@ -0,0 +1,166 @@
+use crate::{matrix::Mat4, vector::Vec3};
+
+/*
+ * Maps world units onto the canvas.
+ *
+ * The camera is zoomed so that the full height of the world fits in
+ * the viewport, and scrolls horizontally over the rest of it.
+ * `position` is the top left corner of the visible area in world units.
+ */
+pub struct Camera {
+    pub position: Vec3,
+    world_width: f32,
+    world_height: f32,
+    viewport_width: f32,
+    viewport_height: f32,
+    zoom: f32,
+}
+
+impl Camera {
+    pub fn new(world_width: f32, world_height: f32, viewport_width: f32, viewport_height: f32) -> Camera {
+        let mut camera = Camera {
+            position: Vec3::new(0.0, 0.0, 0.0),
+            world_width,
+            world_height,
+            viewport_width,
+            viewport_height,
+            zoom: 1.0,
+        };
+        camera.set_viewport(viewport_width, viewport_height);
+        camera
+    }
+
+    pub fn set_viewport(&mut self, viewport_width: f32, viewport_height: f32) {
+        self.viewport_width = viewport_width;
+        self.viewport_height = viewport_height;
+        self.zoom = viewport_height / self.world_height;
+        self.position = self.clamped(self.position);
+    }
+
+    pub fn viewport_width(&self) -> f32 {
+        self.viewport_width
+    }
+
+    pub fn viewport_height(&self) -> f32 {
+        self.viewport_height
+    }
+
+    // Size of the visible area in world units
+    pub fn view_width(&self) -> f32 {
+        self.viewport_width / self.zoom
+    }
+
+    pub fn view_height(&self) -> f32 {
+        self.viewport_height / self.zoom
+    }
+
+    // Point in world units under a point in viewport pixels
+    pub fn screen_to_world(&self, x: f32, y: f32) -> Vec3 {
+        self.position + Vec3::new(x / self.zoom, y / self.zoom, 0.0)
+    }
+
+    pub fn look_at(&mut self, target: Vec3) {
+        let top_left = target - Vec3::new(self.view_width() / 2.0, self.view_height() / 2.0, 0.0);
+        self.position = self.clamped(top_left);
+    }
+
+    // Ease towards the target instead of snapping to it
+    pub fn follow(&mut self, target: Vec3, dt: f32) {
+        let speed = 4.0;
+        let t = (speed * dt).min(1.0);
+
+        let current = self.position;
+        self.look_at(target);
+        let desired = self.position;
+
+        self.position = current + (desired - current).scaled(t);
+    }
+
+    pub fn projection(&self) -> Mat4 {
+        Mat4::orthographic(
+            self.position.x(),
+            self.position.x() + self.view_width(),
+            self.position.y() + self.view_height(),
+            self.position.y(),
+            -1.0,
+            1.0,
+        )
+    }
+
+    // Projection used for overlays drawn in viewport pixels
+    pub fn screen_projection(&self) -> Mat4 {
+        Mat4::orthographic(0.0, self.viewport_width, self.viewport_height, 0.0, -1.0, 1.0)
+    }
+
+    fn clamped(&self, top_left: Vec3) -> Vec3 {
+        let clamp_axis = |value: f32, view: f32, world: f32| {
+            if view >= world {
+                // The world is smaller than the view, so center it
+                (world - view) / 2.0
+            } else {
+                value.clamp(0.0, world - view)
+            }
+        };
+
+        Vec3::new(
+            clamp_axis(top_left.x(), self.view_width(), self.world_width),
+            clamp_axis(top_left.y(), self.view_height(), self.world_height),
+            0.0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Camera;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn zoom_fits_world_height() {
+        let camera = Camera::new(2000.0, 1000.0, 800.0, 500.0);
+        assert_eq!(1600.0, camera.view_width());
+        assert_eq!(1000.0, camera.view_height());
+    }
+
+    #[test]
+    fn look_at_clamps_to_world() {
+        let mut camera = Camera::new(2000.0, 1000.0, 800.0, 500.0);
+
+        camera.look_at(Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(0.0, camera.position.x());
+        assert_eq!(0.0, camera.position.y());
+
+        camera.look_at(Vec3::new(5000.0, 500.0, 0.0));
+        assert_eq!(400.0, camera.position.x());
+        assert_eq!(0.0, camera.position.y());
+    }
+
+    #[test]
+    fn screen_points_map_into_the_view() {
+        let mut camera = Camera::new(2000.0, 1000.0, 800.0, 500.0);
+        camera.look_at(Vec3::new(1000.0, 500.0, 0.0));
+
+        assert_eq!(Vec3::new(200.0, 0.0, 0.0), camera.screen_to_world(0.0, 0.0));
+        assert_eq!(Vec3::new(1000.0, 500.0, 0.0), camera.screen_to_world(400.0, 250.0));
+    }
+
+    #[test]
+    fn world_smaller_than_view_is_centered() {
+        let mut camera = Camera::new(1000.0, 1000.0, 2000.0, 1000.0);
+        camera.look_at(Vec3::new(800.0, 500.0, 0.0));
+        assert_eq!(-500.0, camera.position.x());
+    }
+
+    #[test]
+    fn set_viewport_keeps_view_in_world() {
+        let mut camera = Camera::new(2000.0, 1000.0, 800.0, 500.0);
+        camera.look_at(Vec3::new(2000.0, 500.0, 0.0));
+        assert_eq!(400.0, camera.position.x());
+
+        // A wider viewport shows more of the world
+        camera.set_viewport(1200.0, 500.0);
+        assert_eq!(2400.0, camera.view_width());
+        assert_eq!(-200.0, camera.position.x());
+    }
+}
@@ -1,6 +1,5 @@
-use std::convert::TryInto;
 use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
-use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
+use web_sys::{HtmlCanvasElement, Storage, WebGl2RenderingContext};
 
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
@@ -21,6 +20,11 @@ pub fn document() -> web_sys::Document {
     web_sys::window().unwrap().document().unwrap()
 }
 
+// None when the browser blocks storage, for example in private windows
+pub fn local_storage() -> Option<Storage> {
+    window().local_storage().ok().flatten()
+}
+
 pub fn get_canvas(canvas_id: &str) -> Result<HtmlCanvasElement, JsValue> {
     let canvas = document().get_element_by_id(canvas_id).unwrap();
     let canvas = canvas.dyn_into::<HtmlCanvasElement>()?;
@@ -32,8 +36,7 @@ pub fn get_rendering_context(
     canvas: &HtmlCanvasElement,
 ) -> Result<WebGl2RenderingContext, JsValue> {
     // set per MDN docs
-    canvas.set_width(canvas.client_width().try_into().unwrap());
-    canvas.set_height(canvas.client_height().try_into().unwrap());
+    resize_canvas_to_display_size(canvas);
 
     let gl = canvas
         .get_context("webgl2")?
@@ -56,6 +59,23 @@ pub fn get_rendering_context(
     Ok(gl)
 }
 
+// Sizes the drawing buffer to the displayed size of the canvas in device
+// pixels, so the game stays sharp on high-DPI displays. Returns true when
+// the drawing buffer changed size.
+pub fn resize_canvas_to_display_size(canvas: &HtmlCanvasElement) -> bool {
+    let device_pixel_ratio = window().device_pixel_ratio();
+    let width = (canvas.client_width() as f64 * device_pixel_ratio).round() as u32;
+    let height = (canvas.client_height() as f64 * device_pixel_ratio).round() as u32;
+
+    if canvas.width() == width && canvas.height() == height {
+        return false;
+    }
+
+    canvas.set_width(width);
+    canvas.set_height(height);
+    true
+}
+
 pub fn request_animation_frame(f: &Closure<dyn FnMut(&JsValue)>) {
     window()
         .request_animation_frame(f.as_ref().unchecked_ref())
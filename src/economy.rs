@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::scoring::PlayerStats;
+
+// Money for every point of health taken from an opponent
+const MONEY_PER_DAMAGE: f32 = 2.0;
+const KILL_REWARD: u32 = 150;
+// World units every tank can drive before buying fuel
+const STARTING_FUEL: f32 = 150.0;
+const MAX_SHIELD: f32 = 100.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Weapon {
+    // Rockets in one purchase, unused for the default weapon
+    #[serde(default)]
+    pub ammo: u32,
+    pub direct_damage: f32,
+    pub splash_damage: f32,
+    pub splash_radius: f32,
+    pub crater_radius: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ItemKind {
+    Weapon(Weapon),
+    // Soaks up this much damage before the tank takes any
+    Shield { strength: f32 },
+    // World units of driving
+    Fuel { amount: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogueItem {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub price: u32,
+    #[serde(flatten)]
+    pub kind: ItemKind,
+}
+
+impl CatalogueItem {
+    // Bought weapons need ammo, the default one never runs out
+    fn validate(&self, for_sale: bool) -> Result<(), String> {
+        let positive = |value: f32| value > 0.0 && value.is_finite();
+        let non_negative = |value: f32| value >= 0.0 && value.is_finite();
+        let invalid = |what: &str| Err(format!("Item '{}' has an invalid {}", self.id, what));
+
+        match &self.kind {
+            ItemKind::Weapon(weapon) => {
+                if for_sale && weapon.ammo == 0 {
+                    return invalid("ammo");
+                }
+                if !non_negative(weapon.direct_damage) {
+                    return invalid("direct_damage");
+                }
+                if !non_negative(weapon.splash_damage) {
+                    return invalid("splash_damage");
+                }
+                if !positive(weapon.splash_radius) {
+                    return invalid("splash_radius");
+                }
+                if !positive(weapon.crater_radius) {
+                    return invalid("crater_radius");
+                }
+            }
+            ItemKind::Shield { strength } if !positive(*strength) => return invalid("strength"),
+            ItemKind::Fuel { amount } if !positive(*amount) => return invalid("amount"),
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/*
+ * Everything the shop sells, read from `assets/catalogue.json`. The
+ * default weapon is not for sale, every tank fires it for free once
+ * it runs out of everything else.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Catalogue {
+    pub default_weapon: CatalogueItem,
+    pub items: Vec<CatalogueItem>,
+}
+
+impl Catalogue {
+    pub fn parse(json: &str) -> Result<Catalogue, String> {
+        let catalogue: Catalogue =
+            serde_json::from_str(json).map_err(|e| format!("Invalid catalogue: {}", e))?;
+
+        if !matches!(catalogue.default_weapon.kind, ItemKind::Weapon(_)) {
+            return Err(format!("Default weapon '{}' is not a weapon", catalogue.default_weapon.id));
+        }
+        catalogue.default_weapon.validate(false)?;
+
+        for (i, item) in catalogue.items.iter().enumerate() {
+            if item.id == catalogue.default_weapon.id || catalogue.items[..i].iter().any(|other| other.id == item.id) {
+                return Err(format!("Item '{}' is listed twice", item.id));
+            }
+            item.validate(true)?;
+        }
+
+        Ok(catalogue)
+    }
+
+    pub fn item(&self, id: &str) -> Option<&CatalogueItem> {
+        std::iter::once(&self.default_weapon)
+            .chain(&self.items)
+            .find(|item| item.id == id)
+    }
+
+    // Falls back to the default weapon for anything that is not one
+    pub fn weapon(&self, id: Option<&str>) -> (&str, &Weapon) {
+        let item = id.and_then(|id| self.item(id)).unwrap_or(&self.default_weapon);
+        match &item.kind {
+            ItemKind::Weapon(weapon) => (&item.name, weapon),
+            _ => self.weapon(None),
+        }
+    }
+}
+
+// Carried from round to round for the whole match
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Inventory {
+    // Rockets left for every bought weapon
+    pub ammo: BTreeMap<String, u32>,
+    pub shield: f32,
+    pub fuel: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    pub money: u32,
+    pub inventory: Inventory,
+    // None for the default weapon
+    pub weapon: Option<String>,
+}
+
+impl Account {
+    pub fn new() -> Account {
+        Account {
+            money: 0,
+            inventory: Inventory {
+                fuel: STARTING_FUEL,
+                ..Inventory::default()
+            },
+            weapon: None,
+        }
+    }
+
+    pub fn buy(&mut self, item: &CatalogueItem) -> Result<(), String> {
+        if item.price > self.money {
+            return Err(format!("Not enough money for {}", item.name));
+        }
+
+        let inventory = &mut self.inventory;
+        match &item.kind {
+            ItemKind::Weapon(weapon) => *inventory.ammo.entry(item.id.clone()).or_insert(0) += weapon.ammo,
+            ItemKind::Shield { strength } => {
+                if inventory.shield >= MAX_SHIELD {
+                    return Err(String::from("The shield is already at full strength"));
+                }
+                inventory.shield = (inventory.shield + strength).min(MAX_SHIELD);
+            }
+            ItemKind::Fuel { amount } => inventory.fuel += amount,
+        }
+
+        self.money -= item.price;
+        Ok(())
+    }
+
+    // Goes through the weapons with rockets left in catalogue order, then the default one
+    pub fn next_weapon(&mut self, catalogue: &Catalogue) {
+        let owned: Vec<&str> = catalogue
+            .items
+            .iter()
+            .filter(|item| self.inventory.ammo.get(&item.id).copied().unwrap_or(0) > 0)
+            .map(|item| item.id.as_str())
+            .collect();
+
+        let next = match &self.weapon {
+            None => owned.first(),
+            Some(current) => owned.iter().skip_while(|id| **id != current).nth(1),
+        };
+        self.weapon = next.map(|id| id.to_string());
+    }
+
+    // Uses up one rocket of the selected weapon and returns it
+    pub fn fire(&mut self, catalogue: &Catalogue) -> Weapon {
+        let weapon = catalogue.weapon(self.weapon.as_deref()).1.clone();
+
+        if let Some(id) = self.weapon.clone() {
+            let ammo = self.inventory.ammo.entry(id.clone()).or_insert(0);
+            *ammo = ammo.saturating_sub(1);
+            if *ammo == 0 {
+                self.inventory.ammo.remove(&id);
+                self.weapon = None;
+            }
+        }
+
+        weapon
+    }
+
+    // Returns the damage that gets through the shield
+    pub fn absorb(&mut self, damage: f32) -> f32 {
+        let absorbed = damage.min(self.inventory.shield);
+        self.inventory.shield -= absorbed;
+        damage - absorbed
+    }
+
+    // False when the tank does not have enough fuel to go that far
+    pub fn burn_fuel(&mut self, distance: f32) -> bool {
+        if self.inventory.fuel < distance {
+            return false;
+        }
+        self.inventory.fuel -= distance;
+        true
+    }
+}
+
+pub fn earnings(stats: &PlayerStats) -> u32 {
+    (stats.damage_dealt * MONEY_PER_DAMAGE).round() as u32 + stats.kills * KILL_REWARD
+}
+
+/*
+ * Players take turns at the shop between rounds. The cursor goes
+ * over the catalogue and a last entry to leave the shop.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Shop {
+    pub shopper: usize,
+    pub selected: usize,
+}
+
+impl Shop {
+    // `entries` counts the leave entry as well
+    pub fn move_selection(&mut self, step: i32, entries: usize) {
+        self.selected = (self.selected as i32 + step).rem_euclid(entries as i32) as usize;
+    }
+
+    // Returns false once everybody has been
+    pub fn next_shopper(&mut self, player_count: usize) -> bool {
+        self.shopper += 1;
+        self.selected = 0;
+        self.shopper < player_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{earnings, Account, Catalogue, ItemKind, Shop};
+    use crate::scoring::PlayerStats;
+
+    fn catalogue() -> Catalogue {
+        Catalogue::parse(include_str!("../assets/catalogue.json")).unwrap()
+    }
+
+    #[test]
+    fn catalogue_is_read_from_data() {
+        let catalogue = catalogue();
+        assert_eq!("Rocket", catalogue.weapon(None).0);
+        assert_eq!(40.0, catalogue.weapon(Some("fuel")).1.crater_radius);
+        assert!(matches!(catalogue.item("shield").unwrap().kind, ItemKind::Shield { .. }));
+
+        let twice = r#"{
+            "default_weapon": { "id": "rocket", "name": "Rocket", "kind": "weapon", "direct_damage": 1, "splash_damage": 1, "splash_radius": 1, "crater_radius": 1 },
+            "items": [ { "id": "rocket", "name": "Rocket", "price": 1, "kind": "fuel", "amount": 1 } ]
+        }"#;
+        assert_eq!(Err(String::from("Item 'rocket' is listed twice")), Catalogue::parse(twice).map(|_| ()));
+    }
+
+    #[test]
+    fn items_that_would_break_the_game_are_refused() {
+        let parse = |default_weapon: &str, item: &str| {
+            let json = format!(r#"{{ "default_weapon": {}, "items": [ {} ] }}"#, default_weapon, item);
+            Catalogue::parse(&json).map(|_| ())
+        };
+        let rocket = r#"{ "id": "rocket", "name": "Rocket", "kind": "weapon", "direct_damage": 50, "splash_damage": 30, "splash_radius": 80, "crater_radius": 40 }"#;
+        let fuel = r#"{ "id": "fuel", "name": "Fuel", "price": 40, "kind": "fuel", "amount": 100 }"#;
+
+        // The default weapon needs no ammo, bought ones do
+        assert_eq!(Ok(()), parse(rocket, fuel));
+        assert_eq!(
+            Err(String::from("Item 'mortar' has an invalid ammo")),
+            parse(rocket, &rocket.replace("rocket", "mortar").replace("Rocket", "Mortar"))
+        );
+
+        let flat = rocket.replace(r#""crater_radius": 40"#, r#""crater_radius": 0"#);
+        assert_eq!(Err(String::from("Item 'rocket' has an invalid crater_radius")), parse(&flat, fuel));
+        assert!(parse(rocket, &fuel.replace("100", "-100")).is_err());
+        assert!(parse(rocket, r#"{ "id": "shield", "name": "Shield", "kind": "shield", "strength": 0 }"#).is_err());
+    }
+
+    #[test]
+    fn bought_weapons_are_fired_until_they_run_out() {
+        let catalogue = catalogue();
+        let mut account = Account::new();
+        account.money = earnings(&PlayerStats {
+            damage_dealt: 100.0,
+            kills: 1,
+            ..PlayerStats::default()
+        });
+        assert_eq!(350, account.money);
+
+        account.buy(catalogue.item("digger").unwrap()).unwrap();
+        account.buy(catalogue.item("heavy_rocket").unwrap()).unwrap();
+        assert_eq!(120, account.money);
+        assert!(account.buy(catalogue.item("big_bertha").unwrap()).is_err());
+
+        // Heavy rocket, digger, then back to the default
+        account.next_weapon(&catalogue);
+        assert_eq!(Some("heavy_rocket"), account.weapon.as_deref());
+        account.next_weapon(&catalogue);
+        assert_eq!(Some("digger"), account.weapon.as_deref());
+
+        assert_eq!(100.0, account.fire(&catalogue).crater_radius);
+        assert_eq!(100.0, account.fire(&catalogue).crater_radius);
+        assert_eq!(None, account.weapon);
+        assert_eq!(40.0, account.fire(&catalogue).crater_radius);
+        assert_eq!(Some(&3), account.inventory.ammo.get("heavy_rocket"));
+    }
+
+    #[test]
+    fn shields_soak_damage_and_fuel_runs_out() {
+        let catalogue = catalogue();
+        let mut account = Account::new();
+        account.money = 1000;
+
+        account.buy(catalogue.item("shield").unwrap()).unwrap();
+        assert_eq!(0.0, account.absorb(30.0));
+        assert_eq!(20.0, account.absorb(30.0));
+        assert_eq!(0.0, account.inventory.shield);
+
+        assert!(account.burn_fuel(150.0));
+        assert!(!account.burn_fuel(1.0));
+        account.buy(catalogue.item("fuel").unwrap()).unwrap();
+        assert!(account.burn_fuel(1.0));
+    }
+
+    #[test]
+    fn players_shop_in_turn() {
+        let mut shop = Shop::default();
+        shop.move_selection(-1, 6);
+        assert_eq!(5, shop.selected);
+
+        assert!(shop.next_shopper(2));
+        assert_eq!((1, 0), (shop.shopper, shop.selected));
+        assert!(!shop.next_shopper(2));
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use crate::{sprite_batch::Quad, vector::Vec3};
+
+pub struct Glyph {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+    pub x_advance: f32,
+}
+
+/*
+ * A bitmap font described in the AngelCode BMFont text format.
+ * See http://www.angelcode.com/products/bmfont/doc/file_format.html
+ *
+ * Only single page fonts are supported.
+ */
+pub struct BitmapFont {
+    pub line_height: f32,
+    pub base: f32,
+    pub scale_w: f32,
+    pub scale_h: f32,
+    pub page: String,
+    glyphs: HashMap<u32, Glyph>,
+    kernings: HashMap<(u32, u32), f32>,
+}
+
+impl BitmapFont {
+    pub fn parse(descriptor: &str) -> Result<BitmapFont, String> {
+        let mut font = BitmapFont {
+            line_height: 0.0,
+            base: 0.0,
+            scale_w: 0.0,
+            scale_h: 0.0,
+            page: String::new(),
+            glyphs: HashMap::new(),
+            kernings: HashMap::new(),
+        };
+
+        for (line_number, line) in descriptor.lines().enumerate() {
+            let mut tokens = tokenize(line).into_iter();
+            let tag = match tokens.next() {
+                Some(tag) => tag,
+                None => continue,
+            };
+
+            let attributes: HashMap<String, String> = tokens
+                .filter_map(|token| {
+                    let (key, value) = token.split_once('=')?;
+                    Some((key.to_string(), value.to_string()))
+                })
+                .collect();
+
+            let number = |key: &str| -> Result<f32, String> {
+                attributes
+                    .get(key)
+                    .and_then(|value| value.parse::<f32>().ok())
+                    .ok_or_else(|| format!("line {}: missing or invalid '{}'", line_number + 1, key))
+            };
+
+            match tag.as_str() {
+                "common" => {
+                    font.line_height = number("lineHeight")?;
+                    font.base = number("base")?;
+                    font.scale_w = number("scaleW")?;
+                    font.scale_h = number("scaleH")?;
+                    if number("pages")? > 1.0 {
+                        return Err(String::from("Multi-page fonts are not supported"));
+                    }
+                }
+                "page" => {
+                    font.page = attributes
+                        .get("file")
+                        .cloned()
+                        .ok_or_else(|| format!("line {}: missing 'file'", line_number + 1))?;
+                }
+                "char" => {
+                    font.glyphs.insert(
+                        number("id")? as u32,
+                        Glyph {
+                            x: number("x")?,
+                            y: number("y")?,
+                            width: number("width")?,
+                            height: number("height")?,
+                            x_offset: number("xoffset")?,
+                            y_offset: number("yoffset")?,
+                            x_advance: number("xadvance")?,
+                        },
+                    );
+                }
+                "kerning" => {
+                    font.kernings.insert(
+                        (number("first")? as u32, number("second")? as u32),
+                        number("amount")?,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        if font.scale_w <= 0.0 || font.scale_h <= 0.0 {
+            return Err(String::from("Font descriptor is missing the 'common' block"));
+        }
+
+        Ok(font)
+    }
+
+    pub fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&(character as u32))
+            .or_else(|| self.glyphs.get(&('?' as u32)))
+    }
+
+    pub fn kerning(&self, first: char, second: char) -> f32 {
+        *self
+            .kernings
+            .get(&(first as u32, second as u32))
+            .unwrap_or(&0.0)
+    }
+
+    // Width and height of the text block
+    pub fn measure(&self, text: &str, scale: f32) -> (f32, f32) {
+        let mut width: f32 = 0.0;
+        let mut lines = 0;
+
+        for line in text.split('\n') {
+            width = width.max(self.line_width(line));
+            lines += 1;
+        }
+
+        (width * scale, lines as f32 * self.line_height * scale)
+    }
+
+    // Quads for every visible glyph with the top left corner of the text at `origin`
+    pub fn layout(&self, text: &str, origin: Vec3, scale: f32) -> Vec<Quad> {
+        let mut quads = Vec::with_capacity(text.len());
+        let mut cursor_y = origin.y();
+
+        for line in text.split('\n') {
+            let mut cursor_x = origin.x();
+            let mut previous: Option<char> = None;
+
+            for character in line.chars() {
+                let glyph = match self.glyph(character) {
+                    Some(glyph) => glyph,
+                    None => continue,
+                };
+
+                if let Some(previous) = previous {
+                    cursor_x += self.kerning(previous, character) * scale;
+                }
+
+                if glyph.width > 0.0 && glyph.height > 0.0 {
+                    quads.push(Quad {
+                        top_left: Vec3::new(
+                            cursor_x + glyph.x_offset * scale,
+                            cursor_y + glyph.y_offset * scale,
+                            0.0,
+                        ),
+                        width: glyph.width * scale,
+                        height: glyph.height * scale,
+                        uv: [
+                            glyph.x / self.scale_w,
+                            glyph.y / self.scale_h,
+                            glyph.width / self.scale_w,
+                            glyph.height / self.scale_h,
+                        ],
+                    });
+                }
+
+                cursor_x += glyph.x_advance * scale;
+                previous = Some(character);
+            }
+
+            cursor_y += self.line_height * scale;
+        }
+
+        quads
+    }
+
+    fn line_width(&self, line: &str) -> f32 {
+        let mut width = 0.0;
+        let mut previous: Option<char> = None;
+
+        for character in line.chars() {
+            if let Some(glyph) = self.glyph(character) {
+                if let Some(previous) = previous {
+                    width += self.kerning(previous, character);
+                }
+                width += glyph.x_advance;
+                previous = Some(character);
+            }
+        }
+
+        width
+    }
+}
+
+// Splits a descriptor line on whitespace, keeping quoted values together
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for character in line.chars() {
+        match character {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::BitmapFont;
+    use crate::vector::Vec3;
+
+    const DESCRIPTOR: &str = r#"info face="Test Font" size=16
+common lineHeight=20 base=16 scaleW=128 scaleH=64 pages=1 packed=0
+page id=0 file="test font.png"
+chars count=3
+char id=32 x=0 y=0 width=0 height=0 xoffset=0 yoffset=0 xadvance=5 page=0 chnl=15
+char id=65 x=0 y=0 width=10 height=12 xoffset=1 yoffset=4 xadvance=11 page=0 chnl=15
+char id=86 x=16 y=0 width=10 height=12 xoffset=0 yoffset=4 xadvance=10 page=0 chnl=15
+kernings count=1
+kerning first=65 second=86 amount=-2
+"#;
+
+    #[test]
+    fn parse_descriptor() {
+        let font = BitmapFont::parse(DESCRIPTOR).unwrap();
+        assert_eq!(20.0, font.line_height);
+        assert_eq!(16.0, font.base);
+        assert_eq!("test font.png", font.page);
+        assert_eq!(11.0, font.glyph('A').unwrap().x_advance);
+        assert_eq!(-2.0, font.kerning('A', 'V'));
+        assert_eq!(0.0, font.kerning('V', 'A'));
+    }
+
+    #[test]
+    fn parse_requires_common_block() {
+        assert!(BitmapFont::parse("info face=\"Empty\"").is_err());
+    }
+
+    #[test]
+    fn layout_applies_kerning_and_skips_spaces() {
+        let font = BitmapFont::parse(DESCRIPTOR).unwrap();
+        let quads = font.layout("A V\nAV", Vec3::new(100.0, 50.0, 0.0), 2.0);
+
+        assert_eq!(4, quads.len());
+        assert_eq!(102.0, quads[0].top_left.x());
+        assert_eq!(58.0, quads[0].top_left.y());
+        assert_eq!(20.0, quads[0].width);
+        // "A" then a space
+        assert_eq!(100.0 + 2.0 * (11.0 + 5.0), quads[1].top_left.x());
+        // Second line is kerned
+        assert_eq!(100.0 + 2.0 * (11.0 - 2.0), quads[3].top_left.x());
+        assert_eq!(50.0 + 2.0 * 20.0 + 8.0, quads[3].top_left.y());
+        assert_eq!([16.0 / 128.0, 0.0, 10.0 / 128.0, 12.0 / 64.0], quads[3].uv);
+    }
+
+    #[test]
+    fn measure_uses_widest_line() {
+        let font = BitmapFont::parse(DESCRIPTOR).unwrap();
+        assert_eq!((19.0, 40.0), font.measure("AV\nA", 1.0));
+    }
+}
@@ -1,40 +1,87 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use crate::animation::{AnimatedSprite, Animation, PlaybackMode};
+use crate::assets::{AssetManager, AssetManifest};
+use crate::atlas::TextureAtlas;
+use crate::audio::{stereo_pan, AudioEngine, PlayOptions, SoundCategory, WebAudioBackend};
+use crate::background::{map_tint, Background};
+use crate::camera::Camera;
 use crate::dom::window;
+use crate::economy::{earnings, Account, Catalogue, ItemKind, Shop, Weapon};
+use crate::gamepad::{poll_gamepads, GamepadInput};
+use crate::hud::{update_damage_numbers, AimGauge, DamageNumber, Hud, Nameplate};
+use crate::input::{Action, InputState, KeyBindings, AIM_STEP, POWER_STEP};
+use crate::match_flow::{round_survivor, MatchEvent, MatchFlow, MatchPhase};
+use crate::minimap::{Minimap, MinimapMarker};
+use crate::music::MusicPlayer;
 use crate::particle_emitter::ParticleEmitter;
-use crate::particle_shader::ParticleShader;
+use crate::particle_shader::new_particle_shader;
+use crate::pointer::{
+    aim_actions, fire_button, menu_actions, menu_line, menu_title_top, slingshot_aim, PointerInput, Press, ScreenRect,
+};
+use crate::post_process::PostProcessor;
+use crate::render_queue::{BlendMode, Drawable, RenderKey, RenderLayer, RenderQueue};
+use crate::resources::{live_resources, restore_textures, Texture};
+use crate::scoring::Scoreboard;
+use crate::settings::{GraphicsSettings, MatchSettings};
+use crate::shader::Shader;
 use crate::shapes::{Circle, Collides, Rectangle, Shape};
 use crate::sprite::Sprite;
 use crate::sprite_renderer::SpriteRenderer;
-use crate::sprite_shader::SpriteShader;
-use crate::terrain::{generate_terrain_contour, generate_terrain_mask, new_terrain_sprite};
-use crate::texture::load_image_as_texture;
+use crate::sprite_shader::new_sprite_shader;
+use crate::text_renderer::{TextAlign, TextRenderer};
+use crate::terrain::{
+    generate_terrain_contour, generate_terrain_mask, new_terrain_sprite, scorch_crater, surface_pixels,
+};
+use crate::terrain_renderer::TerrainRenderer;
+use crate::texture::{create_rgba_texture_from_u8_array, TextureOptions, TextureWrap};
 use crate::ui::{post_ui_state, Ui};
 use crate::vector::Vec3;
 
-use super::dom::{get_canvas, get_rendering_context, request_animation_frame, set_panic_hook};
+use super::dom::{
+    get_canvas, get_rendering_context, local_storage, request_animation_frame, resize_canvas_to_display_size,
+    set_panic_hook,
+};
 use super::matrix::Mat4;
 
 use js_sys::Float32Array;
 use wasm_bindgen::{prelude::*, JsCast};
 use web_sys::{
-    console, HtmlAudioElement, HtmlCanvasElement, KeyboardEvent, WebGl2RenderingContext,
-    WebGlTexture,
+    console, AudioContext, Event, HtmlCanvasElement, KeyboardEvent, PointerEvent, WebGl2RenderingContext,
 };
 
+const MAX_HEALTH: f32 = 100.0;
+// Repeated explosions and shots sound slightly different each time
+const SFX_PITCH_VARIATION: f32 = 0.08;
+const VOLUME_STEP: f32 = 0.1;
+// World units a tank moves per step
+const MOVE_STEP: u32 = 2;
+const KEY_BINDINGS_STORAGE_KEY: &str = "key_bindings";
+// How much explosions lower the music
+const EXPLOSION_DUCKING: f32 = 0.6;
+// Full length of the wind arrow. Nothing blows yet: rockets do not feel the
+// wind, so it stays calm and the arrow stays hidden
+const MAX_WIND: f32 = 60.0;
+// Power of a full aim gauge and of the longest slingshot drag
+const GAUGE_POWER: u32 = 400;
+
 struct Player {
     id: usize,
     is_alive: bool,
+    health: f32,
     terrain_position: u32,
     carriage_sprite: Sprite,
     cannon_sprite: Sprite,
+    tread: AnimatedSprite,
+    wreck: Option<AnimatedSprite>,
     cannon_angle: f32,
     cannon_power: u32,
 }
 
 struct Rocket {
     player_id: usize,
+    weapon: Weapon,
     sprite: Sprite,
     velocity: Vec3,
 }
@@ -42,94 +89,377 @@ struct Rocket {
 struct GameState {
     timestamp: f64,
     terrain_contour: js_sys::Float32Array,
-    players: [Player; 4],
+    // How burnt each column of the terrain is, from 0 to 1
+    terrain_scorch: Vec<f32>,
+    // Only the ones taking part in the match
+    players: Vec<Player>,
     current_player: usize,
     rocket: Option<Rocket>,
+    wind: f32,
+    damage_numbers: Vec<DamageNumber>,
+    effects: Vec<AnimatedSprite>,
     terrain_dirty: bool,
-    client_width: u32,
-    client_height: u32,
-    exiting: bool,
-    game_over: bool,
+    world_width: u32,
+    world_height: u32,
+    camera: Camera,
+    flow: MatchFlow,
+    scoreboard: Scoreboard,
+    // Money and inventory of every player, kept for the whole match
+    accounts: Vec<Account>,
+    shop: Shop,
+    // Set when a round starts, the next frame rebuilds the field for it
+    round_pending: bool,
+}
+
+// Animations are cloned from here every time an effect is spawned
+struct AnimationLibrary {
+    texture: Rc<Texture>,
+    mask: Rc<Texture>,
+    explosion: Animation,
+    muzzle_flash: Animation,
+    tread: Animation,
+    wreck: Animation,
+    smolder: Animation,
+}
+
+impl AnimationLibrary {
+    fn new(atlas: &TextureAtlas, mask: Rc<Texture>) -> Result<AnimationLibrary, JsValue> {
+        let manifest = &atlas.manifest;
+        Ok(AnimationLibrary {
+            texture: atlas.texture(),
+            mask,
+            explosion: Animation::from_atlas(manifest, "explosion", 8, 0.06, PlaybackMode::Once)?,
+            muzzle_flash: Animation::from_atlas(manifest, "muzzle_flash", 3, 0.04, PlaybackMode::Once)?,
+            tread: Animation::from_atlas(manifest, "tread", 4, 0.08, PlaybackMode::Loop)?,
+            wreck: Animation::from_atlas(manifest, "wreck", 4, 0.15, PlaybackMode::Once)?,
+            smolder: Animation::from_atlas(manifest, "smolder", 3, 0.2, PlaybackMode::PingPong)?,
+        })
+    }
+
+    fn spawn(&self, animation: &Animation, position: Vec3, size: Vec3) -> AnimatedSprite {
+        let mut sprite = Sprite::new_with_mask(self.texture.clone(), self.mask.clone())
+            .expect("Could not create animated sprite");
+        sprite.global_scale = size;
+        sprite.global_position = position;
+        sprite.update();
+        AnimatedSprite::new(sprite, animation.clone())
+    }
+
+    // Centered on `position`
+    fn spawn_explosion(&self, position: Vec3) -> AnimatedSprite {
+        let size = 96.0;
+        let mut effect = self.spawn(&self.explosion, position, Vec3::new(size, size, 1.0));
+        effect.sprite.layer = RenderLayer::Effects;
+        effect.sprite.local_position = Vec3::new(-size / 2.0, -size / 2.0, 0.0);
+        effect.sprite.update();
+        effect
+    }
+
+    // Sits on the tip of the cannon and turns with it
+    fn spawn_muzzle_flash(&self, cannon: &Sprite) -> AnimatedSprite {
+        let size = 28.0;
+        let mut effect = self.spawn(&self.muzzle_flash, cannon.global_position, Vec3::new(size, size, 1.0));
+        effect.sprite.layer = RenderLayer::Effects;
+        effect.sprite.local_position = Vec3::new(-size / 2.0, cannon.local_position.y() - size + 4.0, 0.0);
+        effect.sprite.global_rotation = cannon.global_rotation;
+        effect.sprite.update();
+        effect
+    }
 }
 
 struct TankGameFlyweight {
     foreground_sprite: Sprite,
     foreground_mask_buffer: js_sys::Uint8Array,
-    background_sprite: Sprite,
+    background: Background,
     game_state: GameState,
     sprite_renderer: SpriteRenderer,
-    carriage_texture: Rc<WebGlTexture>,
-    cannon_texture: Rc<WebGlTexture>,
-    rocket_texture: Rc<WebGlTexture>,
-    smoke_texture: Rc<WebGlTexture>,
-    explosion_texture: Rc<WebGlTexture>,
+    terrain_renderer: TerrainRenderer,
+    text_renderer: TextRenderer,
+    atlas: TextureAtlas,
+    animations: AnimationLibrary,
+    catalogue: Catalogue,
+    white_texture: Rc<Texture>,
     smoke_emitter: ParticleEmitter,
     explosion_emitter: ParticleEmitter,
     render_shapes: bool,
-    sprite_shader: Rc<SpriteShader>,
-    particle_shader: Rc<ParticleShader>,
-    launch_sound: HtmlAudioElement,
-    hitcannon_sound: HtmlAudioElement,
-    hitterrain_sound: HtmlAudioElement,
+    graphics: GraphicsSettings,
+    // None when every post-processing effect is switched off
+    post_processor: Option<PostProcessor>,
+    context_lost: bool,
+    hud: Hud,
+    minimap: Option<Minimap>,
+    particle_shader: Rc<Shader>,
+    audio: AudioEngine<WebAudioBackend>,
+    music: MusicPlayer,
+    input: InputState,
+    pointer: PointerInput,
+    gamepads: GamepadInput,
 }
 
 #[wasm_bindgen]
 pub fn start_game(canvas_id: &str) -> Result<(), JsValue> {
+    run_game(canvas_id, MatchSettings::default())
+}
+
+#[wasm_bindgen]
+pub fn start_game_with_settings(canvas_id: &str, settings: JsValue) -> Result<(), JsValue> {
+    let json = String::from(js_sys::JSON::stringify(&settings)?);
+    let settings: MatchSettings =
+        serde_json::from_str(&json).map_err(|e| JsValue::from(e.to_string()))?;
+    settings.validate()?;
+    run_game(canvas_id, settings)
+}
+
+// Bindings saved here replace the ones from the match settings on the next start
+#[wasm_bindgen]
+pub fn save_key_bindings(json: &str) -> Result<(), JsValue> {
+    let bindings = KeyBindings::parse(json)?;
+    let storage = local_storage().ok_or_else(|| JsValue::from("Local storage is not available"))?;
+    storage.set_item(KEY_BINDINGS_STORAGE_KEY, &bindings.to_json())
+}
+
+#[wasm_bindgen]
+pub fn default_key_bindings() -> String {
+    KeyBindings::default().to_json()
+}
+
+// Returns the bindings with `key` moved to `action`, for a settings screen
+#[wasm_bindgen]
+pub fn rebind_key(bindings: &str, action: &str, key: &str) -> Result<String, JsValue> {
+    let mut bindings = KeyBindings::parse(bindings)?;
+    let action: Action = serde_json::from_value(serde_json::Value::from(action))
+        .map_err(|_| JsValue::from(format!("Unknown action '{}'", action)))?;
+    bindings.bind(action, key);
+    Ok(bindings.to_json())
+}
+
+fn load_key_bindings(fallback: &KeyBindings) -> KeyBindings {
+    let saved = local_storage().and_then(|storage| storage.get_item(KEY_BINDINGS_STORAGE_KEY).ok().flatten());
+
+    match saved.map(|json| KeyBindings::parse(&json)) {
+        Some(Ok(bindings)) => bindings,
+        Some(Err(e)) => {
+            console::log_2(&"Ignoring saved key bindings:".into(), &e.into());
+            fallback.clone()
+        }
+        None => fallback.clone(),
+    }
+}
+
+fn run_game(canvas_id: &str, settings: MatchSettings) -> Result<(), JsValue> {
+    set_panic_hook();
+
     let canvas = get_canvas(canvas_id)?;
     let gl = get_rendering_context(&canvas)?;
 
-    let game = Rc::new(RefCell::new(initialize(&canvas, &gl)?));
+    let max_texture_size = gl
+        .get_parameter(WebGl2RenderingContext::MAX_TEXTURE_SIZE)?
+        .as_f64()
+        .ok_or_else(|| JsValue::from("Could not query the maximum texture size"))?;
+    settings.fits_texture(max_texture_size as u32)?;
+
+    let manifest = AssetManifest::parse(include_str!("../assets/manifest.json"))?;
+    let catalogue = Catalogue::parse(include_str!("../assets/catalogue.json"))?;
+    let audio = AudioContext::new()?;
+    let assets = AssetManager::load(&gl, &audio, &manifest)?;
+
+    // Poll the loads every frame, the match starts once every required asset is in.
+    // The manager is dropped afterwards so the game holds the only texture handles.
+    let mut assets = Some(assets);
+    let f = Rc::new(RefCell::new(None));
+    let g = f.clone();
+    let mut last_progress = -1.0;
+    *g.borrow_mut() = Some(Closure::wrap(Box::new(move |t: &JsValue| {
+        let loading = match assets.as_ref() {
+            Some(loading) => loading,
+            None => return,
+        };
+
+        let progress = loading.progress();
+        if progress != last_progress {
+            last_progress = progress;
+            post_loading_state(loading);
+        }
+
+        if loading.has_failed() {
+            console::log_2(&"Could not load assets:".into(), &loading.errors().join(", ").into());
+            assets = None;
+            return;
+        }
+
+        if loading.is_ready() {
+            let timestamp = t.as_f64().unwrap();
+            if let Err(e) = start_match(&canvas, &gl, &audio, &settings, &catalogue, loading, timestamp) {
+                console::log_2(&"Could not start the match:".into(), &e);
+            }
+            assets = None;
+            return;
+        }
+
+        request_animation_frame(f.borrow().as_ref().unwrap());
+    }) as Box<dyn FnMut(&JsValue)>));
+
+    request_animation_frame(g.borrow().as_ref().unwrap());
+
+    Ok(())
+}
+
+fn post_loading_state(assets: &AssetManager) {
+    let errors = assets.errors();
+    post_ui_state(&Ui {
+        loading_progress: Some(assets.progress()),
+        loading_errors: if errors.is_empty() { None } else { Some(errors) },
+        ..Default::default()
+    })
+    .expect("Could not post UI state");
+}
+
+fn start_match(
+    canvas: &HtmlCanvasElement,
+    gl: &WebGl2RenderingContext,
+    audio: &AudioContext,
+    settings: &MatchSettings,
+    catalogue: &Catalogue,
+    assets: &AssetManager,
+    timestamp: f64,
+) -> Result<(), JsValue> {
+    let mut game = initialize(canvas, gl, audio, settings, catalogue, assets)?;
+    // Start the clock now so the loading time does not count as the first frame
+    game.game_state.timestamp = timestamp;
+
+    // The game is taken out on unload, dropping it deletes every GPU object it owns
+    let game = Rc::new(RefCell::new(Some(game)));
+    let canvas = canvas.clone();
+    let gl = gl.clone();
     let keydown_game_clone = game.clone();
     let keydown_callback = Closure::wrap(Box::new(move |e: &KeyboardEvent| {
-        let mut game = keydown_game_clone.borrow_mut();
-        let handled = handle_keyboard_input(&mut *game, e.key().as_str());
+        if let Some(game) = keydown_game_clone.borrow_mut().as_mut() {
+            game.audio.backend().resume();
 
-        if handled {
-            e.prevent_default();
+            // Actions run on the next update
+            if game.input.key_down(e.key().as_str()) {
+                e.prevent_default();
+            }
         }
     }) as Box<dyn FnMut(&KeyboardEvent)>);
     window().set_onkeydown(Some(keydown_callback.as_ref().unchecked_ref()));
     // Give ownership to the browser
     keydown_callback.forget();
 
+    let keyup_game_clone = game.clone();
+    let keyup_callback = Closure::wrap(Box::new(move |e: &KeyboardEvent| {
+        if let Some(game) = keyup_game_clone.borrow_mut().as_mut() {
+            if game.input.key_up(e.key().as_str()) {
+                e.prevent_default();
+            }
+        }
+    }) as Box<dyn FnMut(&KeyboardEvent)>);
+    window().set_onkeyup(Some(keyup_callback.as_ref().unchecked_ref()));
+    keyup_callback.forget();
+
+    add_pointer_listeners(&canvas, &game)?;
+
+    let blur_game_clone = game.clone();
+    let blur_callback = Closure::wrap(Box::new(move || {
+        if let Some(game) = blur_game_clone.borrow_mut().as_mut() {
+            game.input.release_all();
+        }
+    }) as Box<dyn FnMut()>);
+    window().set_onblur(Some(blur_callback.as_ref().unchecked_ref()));
+    blur_callback.forget();
+
+    // Clicks also count as the gesture browsers want before playing audio
+    let pointerdown_game_clone = game.clone();
+    let pointerdown_callback = Closure::wrap(Box::new(move || {
+        if let Some(game) = pointerdown_game_clone.borrow_mut().as_mut() {
+            game.audio.backend().resume();
+        }
+    }) as Box<dyn FnMut()>);
+    window().add_event_listener_with_callback("pointerdown", pointerdown_callback.as_ref().unchecked_ref())?;
+    pointerdown_callback.forget();
+
+    let resize_game_clone = game.clone();
+    let resize_canvas = canvas.clone();
+    let resize_gl = gl.clone();
+    let resize_callback = Closure::wrap(Box::new(move || {
+        if let Some(game) = resize_game_clone.borrow_mut().as_mut() {
+            resize(&resize_canvas, &resize_gl, game);
+        }
+    }) as Box<dyn FnMut()>);
+    window().set_onresize(Some(resize_callback.as_ref().unchecked_ref()));
+    resize_callback.forget();
+
     let beforeunload_game_clone = game.clone();
     let beforeunload_callback = Closure::wrap(Box::new(move || {
-        let gl = get_rendering_context(&canvas).unwrap();
-        let mut game = beforeunload_game_clone.borrow_mut();
-        game.game_state.exiting = true;
-        game.explosion_emitter.delete(&gl);
-        game.smoke_emitter.delete(&gl);
-        game.sprite_renderer.delete(&gl);
-        gl.delete_program(Some(&game.particle_shader.program));
-        gl.delete_program(Some(&game.sprite_shader.program));
-        gl.delete_texture(Some(&game.explosion_texture));
-        gl.delete_texture(Some(&game.smoke_texture));
-        gl.delete_texture(Some(&game.rocket_texture));
-        gl.delete_texture(Some(&game.cannon_texture));
-        gl.delete_texture(Some(&game.carriage_texture));
-        gl.delete_texture(Some(&game.foreground_sprite.texture()));
-        gl.delete_texture(Some(&game.background_sprite.texture()));
+        beforeunload_game_clone.borrow_mut().take();
+
+        let leaked = live_resources();
+        if leaked.total() > 0 {
+            console::log_2(&"GPU resources still alive after unload:".into(), &format!("{:?}", leaked).into());
+        }
     }) as Box<dyn FnMut()>);
     window().set_onbeforeunload(Some(beforeunload_callback.as_ref().unchecked_ref()));
     beforeunload_callback.forget();
 
+    let context_lost_game_clone = game.clone();
+    let context_lost_callback = Closure::wrap(Box::new(move |e: &Event| {
+        // Without this the browser never restores the context
+        e.prevent_default();
+        console::log_1(&"WebGL context lost".into());
+
+        if let Some(game) = context_lost_game_clone.borrow_mut().as_mut() {
+            game.context_lost = true;
+        }
+    }) as Box<dyn FnMut(&Event)>);
+    canvas.add_event_listener_with_callback(
+        "webglcontextlost",
+        context_lost_callback.as_ref().unchecked_ref(),
+    )?;
+    context_lost_callback.forget();
+
+    let context_restored_game_clone = game.clone();
+    let context_restored_gl = gl.clone();
+    let context_restored_callback = Closure::wrap(Box::new(move || {
+        console::log_1(&"WebGL context restored".into());
+
+        if let Some(game) = context_restored_game_clone.borrow_mut().as_mut() {
+            match restore_graphics(&context_restored_gl, game) {
+                Ok(()) => game.context_lost = false,
+                Err(e) => console::log_2(&"Could not restore graphics:".into(), &e),
+            }
+        }
+    }) as Box<dyn FnMut()>);
+    canvas.add_event_listener_with_callback(
+        "webglcontextrestored",
+        context_restored_callback.as_ref().unchecked_ref(),
+    )?;
+    context_restored_callback.forget();
+
     let f = Rc::new(RefCell::new(None));
     let g = f.clone();
     let loop_clone = game.clone();
     *g.borrow_mut() = Some(Closure::wrap(Box::new(move |t: &JsValue| {
         let mut game = loop_clone.borrow_mut();
+        let game = match game.as_mut() {
+            Some(game) => game,
+            None => return,
+        };
+
+        // timestamp is in milliseconds
+        let timestamp = t.as_f64().unwrap();
 
-        if game.game_state.exiting {
+        // The match is paused until the context comes back
+        if game.context_lost {
+            game.game_state.timestamp = timestamp;
+            request_animation_frame(f.borrow().as_ref().unwrap());
             return;
         }
 
-        // timestamp is in milliseconds
-        let timestamp = t.as_f64().unwrap();
         let dt = (timestamp - game.game_state.timestamp) / 1000.0;
 
-        update(&mut *game, dt as f32);
-        prepare_dirty_resources(&gl, &mut *game);
-        render(&gl, &game);
+        update(game, dt as f32);
+        prepare_dirty_resources(&gl, game);
+        render(&gl, game);
 
         game.game_state.timestamp = timestamp;
 
@@ -144,31 +474,51 @@ pub fn start_game(canvas_id: &str) -> Result<(), JsValue> {
 fn initialize(
     canvas: &HtmlCanvasElement,
     gl: &WebGl2RenderingContext,
+    audio: &AudioContext,
+    settings: &MatchSettings,
+    catalogue: &Catalogue,
+    assets: &AssetManager,
 ) -> Result<TankGameFlyweight, JsValue> {
-    set_panic_hook();
     console::log_1(&"Initializing tank game".into());
 
-    let client_width = canvas.client_width() as u32;
-    let client_height = canvas.client_height() as u32;
+    let client_width = canvas.client_width() as f32;
+    let client_height = canvas.client_height() as f32;
+    let world_width = settings.world_width;
+    let world_height = settings.world_height;
 
-    let background_texture = load_image_as_texture(&gl, "assets/background.jpg")?;
-    let foreground_texture = load_image_as_texture(&gl, "assets/ground.jpg")?;
-    let carriage_texture = load_image_as_texture(&gl, "assets/carriage.png")?;
-    let cannon_texture = load_image_as_texture(&gl, "assets/cannon.png")?;
-    let rocket_texture = load_image_as_texture(&gl, "assets/rocket.png")?;
-    let smoke_texture = load_image_as_texture(&gl, "assets/smoke.png")?;
-    let explosion_texture = load_image_as_texture(&gl, "assets/explosion.png")?;
+    let background_texture = assets.texture("background")?;
+    let foreground_texture = assets.texture("ground")?;
+    let atlas = TextureAtlas::new(&assets.data("sprites_manifest")?, assets.texture("sprites")?)?;
+    let explosion_texture = assets.texture("explosion")?;
+    let white_texture = create_rgba_texture_from_u8_array(gl, 1, 1, &[255, 255, 255, 255], &TextureOptions::crisp())?;
 
-    let launch_sound = HtmlAudioElement::new_with_src("assets/launch.wav")?;
-    let hitcannon_sound = HtmlAudioElement::new_with_src("assets/hitcannon.wav")?;
-    let hitterrain_sound = HtmlAudioElement::new_with_src("assets/hitterrain.wav")?;
+    let audio = AudioEngine::new(WebAudioBackend::new(audio, assets.sounds())?, &settings.audio);
 
-    let sprite_shader = Rc::new(SpriteShader::new(gl)?);
+    let sprite_shader = Rc::new(new_sprite_shader(gl)?);
     let sprite_renderer = SpriteRenderer::new(gl, sprite_shader.clone())?;
+    let surface_texture = create_rgba_texture_from_u8_array(
+        gl,
+        256,
+        32,
+        &surface_pixels(256, 32),
+        &TextureOptions {
+            wrap_s: TextureWrap::Repeat,
+            ..TextureOptions::default()
+        },
+    )?;
+    let terrain_renderer = TerrainRenderer::new(gl, surface_texture)?;
+    let text_renderer = TextRenderer::new(
+        gl,
+        sprite_shader.clone(),
+        &assets.data("font_descriptor")?,
+        assets.texture("font")?,
+        white_texture.clone(),
+    )?;
 
-    let particle_shader = Rc::new(ParticleShader::new(gl)?);
+    let particle_shader = Rc::new(new_particle_shader(gl)?);
     let mut smoke_emitter =
-        ParticleEmitter::new(gl, smoke_texture.clone(), particle_shader.clone())?;
+        ParticleEmitter::new(gl, atlas.texture(), particle_shader.clone())?;
+    smoke_emitter.uv = atlas.uv("smoke.png")?;
     smoke_emitter.initial_particle_life_seconds = 2.;
     smoke_emitter.initial_particle_scale = 25.;
     smoke_emitter.initial_particle_color = [0.2, 0.2, 0.2, 1.0];
@@ -177,7 +527,7 @@ fn initialize(
     smoke_emitter.spawn_frequency_hz = 0.;
 
     let mut explosion_emitter =
-        ParticleEmitter::new(gl, explosion_texture.clone(), particle_shader.clone())?;
+        ParticleEmitter::new(gl, explosion_texture, particle_shader.clone())?;
 
     explosion_emitter.emitter_life_seconds = 2.;
     explosion_emitter.initial_particle_life_seconds = 1.;
@@ -187,147 +537,78 @@ fn initialize(
     explosion_emitter.max_particles = 100;
     explosion_emitter.spawn_frequency_hz = 0.;
 
-    let mut terrain_contour = js_sys::Float32Array::new_with_length(client_width as u32);
-    generate_terrain_contour(&mut terrain_contour, client_height as f32);
+    // The title and setup screens show a field with the default match on it
+    let flow = MatchFlow::new(settings.players, settings.rounds);
+    let terrain_contour = new_terrain_contour(world_width, world_height, flow.player_count());
 
-    let player_positions = [
-        (0.15f32 * client_width as f32) as u32,
-        (0.3f32 * client_width as f32) as u32,
-        (0.5f32 * client_width as f32) as u32,
-        (0.75f32 * client_width as f32) as u32,
-    ];
-
-    let player_colors = [
-        [1.0, 0.0, 0.0, 1.0],
-        [0.0, 1.0, 0.0, 1.0],
-        [0.0, 0.0, 1.0, 1.0],
-        [1.0, 0.0, 1.0, 1.0],
-    ];
-
-    // Flatten the terrain under the player positions
-    for position in player_positions {
-        let start = (position - 50).max(0);
-        let end = (position + 50).min(client_width as u32);
-        let height = terrain_contour.get_index(start);
-        for i in start..end {
-            terrain_contour.set_index(i, height);
-        }
-    }
-
-    let buffer_size = (client_width * client_height * 4) as u32;
+    let buffer_size = world_width * world_height * 4;
     let mut foreground_mask_buffer = js_sys::Uint8Array::new_with_length(buffer_size);
 
-    let foreground_sprite = new_terrain_sprite(
+    let terrain_scorch = vec![0.0; world_width as usize];
+
+    let mut foreground_sprite = new_terrain_sprite(
         gl,
         foreground_texture,
         &mut foreground_mask_buffer,
         &terrain_contour,
-        client_width,
-        client_height,
+        &terrain_scorch,
+        world_width,
+        world_height,
     )?;
 
-    let mut background_sprite = Sprite::new(gl, background_texture)?;
-    background_sprite.global_scale = Vec3::new(client_width as f32, client_height as f32, 1.0);
-    background_sprite.update();
+    foreground_sprite.color = map_tint(settings.map.time_of_day, settings.map.weather);
 
-    let projection = Mat4::orthographic(
-        0.0,
-        client_width as f32,
-        client_height as f32,
-        0.0,
-        -1.0,
-        1.0,
-    );
+    let background = Background::new(
+        gl,
+        background_texture,
+        white_texture.clone(),
+        &settings.map,
+        world_width as f32,
+        world_height as f32,
+    )?;
 
-    // preload shaders with projection as it won't change
-    // This is an optimization to avoid a uniform call every frame
-    gl.use_program(Some(&sprite_shader.program));
-    gl.uniform_matrix4fv_with_f32_array(
-        Some(&sprite_shader.projection_matrix_uniform),
-        false,
-        projection.data(),
-    );
-    gl.use_program(None);
+    let hud = Hud::new(white_texture.clone());
+    let animations = AnimationLibrary::new(&atlas, white_texture.clone())?;
+
+    let minimap = if settings.show_minimap {
+        Some(Minimap::new(
+            white_texture.clone(),
+            world_width as f32,
+            world_height as f32,
+        ))
+    } else {
+        None
+    };
 
-    gl.use_program(Some(&particle_shader.program));
+    let players = new_players(gl, &atlas, &animations, flow.player_count(), &terrain_contour)?;
 
-    gl.uniform_matrix4fv_with_f32_array(
-        Some(&particle_shader.projection_matrix_uniform),
-        false,
-        projection.data(),
+    let mut camera = Camera::new(
+        world_width as f32,
+        world_height as f32,
+        client_width,
+        client_height,
     );
-    gl.use_program(None);
-
-    let mut players = [
-        Player {
-            id: 0,
-            is_alive: true,
-            terrain_position: player_positions[0],
-            cannon_angle: 45.0,
-            cannon_sprite: Sprite::new_with_color(gl, cannon_texture.clone(), player_colors[0])?,
-            carriage_sprite: Sprite::new_with_color(
-                gl,
-                carriage_texture.clone(),
-                player_colors[0],
-            )?,
-            cannon_power: 200,
-        },
-        Player {
-            id: 1,
-            is_alive: true,
-            terrain_position: player_positions[1],
-            cannon_angle: 45.0,
-            cannon_sprite: Sprite::new_with_color(gl, cannon_texture.clone(), player_colors[1])?,
-            carriage_sprite: Sprite::new_with_color(
-                gl,
-                carriage_texture.clone(),
-                player_colors[1],
-            )?,
-            cannon_power: 200,
-        },
-        Player {
-            id: 2,
-            is_alive: true,
-            terrain_position: player_positions[2],
-            cannon_angle: 45.0,
-            cannon_sprite: Sprite::new_with_color(gl, cannon_texture.clone(), player_colors[2])?,
-            carriage_sprite: Sprite::new_with_color(
-                gl,
-                carriage_texture.clone(),
-                player_colors[2],
-            )?,
-            cannon_power: 200,
-        },
-        Player {
-            id: 3,
-            is_alive: true,
-            terrain_position: player_positions[3],
-            cannon_angle: 45.0,
-            cannon_sprite: Sprite::new_with_color(gl, cannon_texture.clone(), player_colors[3])?,
-            carriage_sprite: Sprite::new_with_color(
-                gl,
-                carriage_texture.clone(),
-                player_colors[3],
-            )?,
-            cannon_power: 200,
-        },
-    ];
-
-    for player in &mut players {
-        reposition_player(player, &terrain_contour);
-    }
+    camera.look_at(players[0].carriage_sprite.global_position);
 
     let game_state = GameState {
         timestamp: 0.0,
         current_player: 0,
         terrain_contour,
+        terrain_scorch,
         rocket: None,
+        wind: 0.0,
+        damage_numbers: Vec::new(),
+        effects: Vec::new(),
         players,
         terrain_dirty: false,
-        client_width,
-        client_height,
-        exiting: false,
-        game_over: false,
+        world_width,
+        world_height,
+        camera,
+        scoreboard: Scoreboard::new(flow.player_count()),
+        accounts: vec![Account::new(); flow.player_count()],
+        shop: Shop::default(),
+        flow,
+        round_pending: false,
     };
 
     update_ui(&game_state);
@@ -335,51 +616,195 @@ fn initialize(
     Ok(TankGameFlyweight {
         foreground_sprite,
         foreground_mask_buffer,
-        background_sprite,
+        background,
         game_state,
         sprite_renderer,
-        rocket_texture,
-        cannon_texture,
-        carriage_texture,
-        smoke_texture,
-        explosion_texture,
+        terrain_renderer,
+        text_renderer,
+        atlas,
+        animations,
+        catalogue: catalogue.clone(),
+        white_texture,
         smoke_emitter,
         explosion_emitter,
-        sprite_shader,
         particle_shader,
-        launch_sound,
-        hitcannon_sound,
-        hitterrain_sound,
+        audio,
+        music: MusicPlayer::new(&settings.music),
+        input: InputState::new(load_key_bindings(&settings.key_bindings)),
+        pointer: PointerInput::new(),
+        gamepads: GamepadInput::new(),
         render_shapes: false,
+        graphics: settings.graphics,
+        post_processor: new_post_processor(gl, &settings.graphics)?,
+        context_lost: false,
+        hud,
+        minimap,
     })
 }
 
+// The world and camera work in CSS pixels, only the drawing buffer
+// and viewport follow the device pixel ratio.
+fn resize(canvas: &HtmlCanvasElement, gl: &WebGl2RenderingContext, game: &mut TankGameFlyweight) {
+    if resize_canvas_to_display_size(canvas) {
+        gl.viewport(0, 0, gl.drawing_buffer_width(), gl.drawing_buffer_height());
+
+        if let Some(post_processor) = &mut game.post_processor {
+            // The old targets stay, a blurry frame beats a frozen page
+            let width = gl.drawing_buffer_width() as u32;
+            let height = gl.drawing_buffer_height() as u32;
+            if let Err(e) = post_processor.resize(gl, width, height) {
+                console::error_2(&"Could not resize render targets:".into(), &e);
+            }
+        }
+    }
+
+    game.game_state.camera.set_viewport(
+        canvas.client_width() as f32,
+        canvas.client_height() as f32,
+    );
+}
+
+// Spread evenly over the world, the same for every round
+fn player_positions(player_count: usize, world_width: u32) -> Vec<u32> {
+    (0..player_count)
+        .map(|i| ((i as f32 + 0.5) / player_count as f32 * world_width as f32) as u32)
+        .collect()
+}
+
+fn new_terrain_contour(world_width: u32, world_height: u32, player_count: usize) -> Float32Array {
+    let mut terrain_contour = js_sys::Float32Array::new_with_length(world_width);
+    generate_terrain_contour(&mut terrain_contour, world_height as f32);
+
+    // Flatten the terrain under the player positions
+    for position in player_positions(player_count, world_width) {
+        let start = position.saturating_sub(50);
+        let end = (position + 50).min(world_width);
+        let height = terrain_contour.get_index(start);
+        for i in start..end {
+            terrain_contour.set_index(i, height);
+        }
+    }
+
+    terrain_contour
+}
+
+fn new_players(
+    gl: &WebGl2RenderingContext,
+    atlas: &TextureAtlas,
+    animations: &AnimationLibrary,
+    player_count: usize,
+    terrain_contour: &Float32Array,
+) -> Result<Vec<Player>, JsValue> {
+    let player_colors = [
+        [1.0, 0.0, 0.0, 1.0],
+        [0.0, 1.0, 0.0, 1.0],
+        [0.0, 0.0, 1.0, 1.0],
+        [1.0, 0.0, 1.0, 1.0],
+    ];
+
+    let world_width = terrain_contour.length();
+    let mut players = Vec::new();
+    for (id, position) in player_positions(player_count, world_width).into_iter().enumerate() {
+        let mut player = Player {
+            id,
+            is_alive: true,
+            health: MAX_HEALTH,
+            terrain_position: position,
+            cannon_angle: 45.0,
+            cannon_sprite: Sprite::new_from_atlas(gl, atlas, "cannon.png", player_colors[id])?,
+            carriage_sprite: Sprite::new_from_atlas(gl, atlas, "carriage.png", player_colors[id])?,
+            tread: animations.spawn(&animations.tread, Vec3::new(0.0, 0.0, 0.0), Vec3::new(100.0, 10.0, 1.0)),
+            wreck: None,
+            cannon_power: 200,
+        };
+
+        // Treads and cannon sit behind the carriage
+        player.carriage_sprite.z_order = 1.0;
+        reposition_player(&mut player, terrain_contour);
+        players.push(player);
+    }
+
+    Ok(players)
+}
+
+/*
+ * A fresh field for the next round: new terrain without scorch marks,
+ * every tank back at full health in its spot and the turn going to
+ * the player whose turn it is to open.
+ */
+fn start_round(gl: &WebGl2RenderingContext, game: &mut TankGameFlyweight) -> Result<(), JsValue> {
+    let state = &mut game.game_state;
+    let player_count = state.flow.player_count();
+
+    // Everything that can fail comes first, so a failure leaves the last round as it was
+    let terrain_contour = new_terrain_contour(state.world_width, state.world_height, player_count);
+    state.players = new_players(gl, &game.atlas, &game.animations, player_count, &terrain_contour)?;
+    state.terrain_contour = terrain_contour;
+    state.terrain_scorch = vec![0.0; state.world_width as usize];
+    state.terrain_dirty = true;
+    state.current_player = state.flow.starting_player();
+    // Scores, money and inventories carry over between the rounds of a match
+    if state.flow.round() == 1 {
+        state.scoreboard = Scoreboard::new(player_count);
+        state.accounts = vec![Account::new(); player_count];
+    } else {
+        state.scoreboard.start_round();
+    }
+    state.rocket = None;
+    state.wind = 0.0;
+    state.damage_numbers.clear();
+    state.effects.clear();
+    state.camera.look_at(state.players[state.current_player].carriage_sprite.global_position);
+    state.round_pending = false;
+
+    game.smoke_emitter.spawn_frequency_hz = 0.;
+    game.music.restart(&mut game.audio);
+    update_ui(&game.game_state);
+
+    Ok(())
+}
+
+// Where a tank at `terrain_position` stands on ground `terrain_height` high, its carriage and cannon hang off it
+fn tank_position(terrain_position: u32, terrain_height: f32) -> Vec3 {
+    Vec3::new(terrain_position as f32, terrain_height - 19.5, 0.0)
+}
+
 fn reposition_player(player: &mut Player, terrain_contour: &Float32Array) {
-    let x = player.terrain_position;
-    let y = terrain_contour.get_index(x) + -19.5;
+    let position = tank_position(player.terrain_position, terrain_contour.get_index(player.terrain_position));
+    let (x, y) = (position.x(), position.y());
     player.carriage_sprite.global_scale = Vec3::new(100.0, 39.0, 1.0);
     player.carriage_sprite.local_position = Vec3::new(-50.0, -19.5, 0.0);
-    player.carriage_sprite.global_position = Vec3::new(x as f32, y, 0.0);
+    player.carriage_sprite.global_position = Vec3::new(x, y, 0.0);
     player.carriage_sprite.update();
     player.cannon_sprite.global_scale = Vec3::new(20.0, 70.0, 1.0);
     player.cannon_sprite.local_position = Vec3::new(-10.0, -55.0, 0.0);
-    player.cannon_sprite.global_position = Vec3::new(x as f32, y, 0.0);
+    player.cannon_sprite.global_position = Vec3::new(x, y, 0.0);
     player.cannon_sprite.global_rotation = player.cannon_angle;
     player.cannon_sprite.update();
+    player.tread.sprite.local_position = Vec3::new(-50.0, 11.5, 0.0);
+    player.tread.sprite.global_position = Vec3::new(x, y, 0.0);
+    player.tread.sprite.update();
 }
 
+// Terrain position after driving `step` world units, the tank stays on the map
+fn drive(terrain_position: u32, step: i32, world_width: u32) -> u32 {
+    (terrain_position as i64 + step as i64).clamp(0, world_width as i64 - 1) as u32
+}
+
+#[allow(clippy::too_many_arguments)]
 fn create_rocket(
-    texture: Rc<WebGlTexture>,
-    mask: Rc<WebGlTexture>,
+    atlas: &TextureAtlas,
+    mask: Rc<Texture>,
     color: [f32; 4],
     cannon_angle: f32,
     cannon_power: f32,
     cannon_x: f32,
     cannon_y: f32,
     player_id: usize,
+    weapon: Weapon,
 ) -> Result<Rocket, JsValue> {
     // Add an offset make it look like the rocket is leaving the cannon
-    let position = Vec3::new(cannon_x as f32 - 10.0, cannon_y - 15.0, 0.0);
+    let position = Vec3::new(cannon_x - 10.0, cannon_y - 15.0, 0.0);
 
     let power = cannon_power;
     let (sin, cos) = ((cannon_angle - 90.0).to_radians()).sin_cos();
@@ -388,7 +813,9 @@ fn create_rocket(
     let velocity = Vec3::new(vx, vy, 0.0);
 
     let scale_factor = 1.0 / 8.0;
-    let mut sprite = Sprite::new_with_mask(texture, mask)?;
+    let mut sprite = Sprite::new_with_mask(atlas.texture(), mask)?;
+    sprite.layer = RenderLayer::Projectiles;
+    sprite.uv = atlas.uv("rocket.png")?;
     sprite.color = color;
     sprite.global_scale = Vec3::new(86.0 * scale_factor, 287.0 * scale_factor, 0.0);
     sprite.local_position = Vec3::new(
@@ -400,43 +827,169 @@ fn create_rocket(
 
     Ok(Rocket {
         player_id,
+        weapon,
         sprite,
         velocity,
     })
 }
 
-fn play_audio(audio: &HtmlAudioElement) {
-    // See https://docs.rs/web-sys/latest/web_sys/struct.HtmlAudioElement.html
-    if audio.ready_state() >= 2 {
-        match audio.play() {
-            Err(..) => console::log_2(&"Could not play audio:".into(), &audio.src().into()),
-            _ => {}
+// Sound effects are panned to where they happen on screen
+fn play_sound_at(game: &mut TankGameFlyweight, sound: &str, world_x: f32) {
+    let camera = &game.game_state.camera;
+    let options = PlayOptions {
+        pan: stereo_pan(world_x - camera.position.x(), camera.view_width()),
+        pitch_variation: SFX_PITCH_VARIATION,
+        ..PlayOptions::default()
+    };
+    game.audio.play(sound, SoundCategory::Sfx, &options);
+}
+
+// Pivot of the cannon of the current player, while it can be aimed
+fn aiming_cannon(game: &TankGameFlyweight) -> Option<Vec3> {
+    let state = &game.game_state;
+    let player = &state.players[state.current_player];
+
+    if player.is_alive && state.rocket.is_none() && state.flow.phase() == MatchPhase::InRound {
+        Some(player.cannon_sprite.global_position)
+    } else {
+        None
+    }
+}
+
+// Only shown while there is something to fire
+fn visible_fire_button(game: &TankGameFlyweight) -> Option<ScreenRect> {
+    let camera = &game.game_state.camera;
+    aiming_cannon(game).map(|_| fire_button(camera.viewport_width(), camera.viewport_height()))
+}
+
+fn add_pointer_listeners(
+    canvas: &HtmlCanvasElement,
+    game: &Rc<RefCell<Option<TankGameFlyweight>>>,
+) -> Result<(), JsValue> {
+    let pointerdown_game_clone = game.clone();
+    let pointerdown_canvas = canvas.clone();
+    let pointerdown_callback = Closure::wrap(Box::new(move |e: &PointerEvent| {
+        if let Some(game) = pointerdown_game_clone.borrow_mut().as_mut() {
+            let screen = Vec3::new(e.offset_x() as f32, e.offset_y() as f32, 0.0);
+            let world = game.game_state.camera.screen_to_world(screen.x(), screen.y());
+            let fire_button = visible_fire_button(game);
+            let cannon = aiming_cannon(game);
+
+            // Outside of rounds the lines of the screen are tapped instead
+            let phase = game.game_state.flow.phase();
+            if phase != MatchPhase::InRound {
+                let lines = match_screen(game).map_or(0, |(_, _, lines)| lines.len());
+                let camera = &game.game_state.camera;
+                let (width, height) = (camera.viewport_width(), camera.viewport_height());
+
+                if let Some(line) = (0..lines).find(|line| menu_line(*line, width, height).contains(screen)) {
+                    let left_half = screen.x() < width / 2.0;
+                    for action in menu_actions(phase, line, lines, left_half, game.game_state.shop.selected) {
+                        game.input.trigger(action);
+                    }
+                    e.prevent_default();
+                }
+                return;
+            }
+
+            match game.pointer.pointer_down(e.pointer_id(), screen, world, fire_button, cannon) {
+                Press::Fire => game.input.trigger(Action::Fire),
+                // Keep getting moves when the finger leaves the canvas
+                Press::Aim => {
+                    let _ = pointerdown_canvas.set_pointer_capture(e.pointer_id());
+                }
+                Press::Ignored => return,
+            }
+            e.prevent_default();
+        }
+    }) as Box<dyn FnMut(&PointerEvent)>);
+    canvas.add_event_listener_with_callback("pointerdown", pointerdown_callback.as_ref().unchecked_ref())?;
+    pointerdown_callback.forget();
+
+    let pointermove_game_clone = game.clone();
+    let pointermove_callback = Closure::wrap(Box::new(move |e: &PointerEvent| {
+        if let Some(game) = pointermove_game_clone.borrow_mut().as_mut() {
+            let world = game
+                .game_state
+                .camera
+                .screen_to_world(e.offset_x() as f32, e.offset_y() as f32);
+            game.pointer.pointer_move(e.pointer_id(), world);
+        }
+    }) as Box<dyn FnMut(&PointerEvent)>);
+    canvas.add_event_listener_with_callback("pointermove", pointermove_callback.as_ref().unchecked_ref())?;
+    pointermove_callback.forget();
+
+    let pointerup_game_clone = game.clone();
+    let pointerup_callback = Closure::wrap(Box::new(move |e: &PointerEvent| {
+        if let Some(game) = pointerup_game_clone.borrow_mut().as_mut() {
+            game.pointer.pointer_up(e.pointer_id());
         }
+    }) as Box<dyn FnMut(&PointerEvent)>);
+    for event in ["pointerup", "pointercancel"] {
+        canvas.add_event_listener_with_callback(event, pointerup_callback.as_ref().unchecked_ref())?;
     }
+    pointerup_callback.forget();
+
+    Ok(())
 }
 
-fn handle_keyboard_input(game: &mut TankGameFlyweight, key_code: &str) -> bool {
-    let player = &mut game.game_state.players[game.game_state.current_player];
+fn perform_action(game: &mut TankGameFlyweight, action: Action) {
+    match action {
+        Action::ToggleMute => {
+            let muted = !game.audio.settings().muted;
+            game.audio.set_muted(muted);
+            return;
+        }
+        Action::VolumeUp => {
+            let volume = game.audio.settings().volume + VOLUME_STEP;
+            game.audio.set_volume(volume);
+            return;
+        }
+        Action::VolumeDown => {
+            let volume = game.audio.settings().volume - VOLUME_STEP;
+            game.audio.set_volume(volume);
+            return;
+        }
+        _ => {}
+    }
 
-    match key_code {
-        "ArrowLeft" => player.cannon_angle -= 2.0,
-        "ArrowRight" => player.cannon_angle += 2.0,
-        "ArrowUp" => {
-            player.cannon_power += 5;
-            update_ui(&game.game_state);
+    if game.game_state.flow.phase() != MatchPhase::InRound {
+        perform_menu_action(&mut game.game_state, &game.catalogue, action);
+        return;
+    }
+
+    let world_width = game.game_state.world_width;
+    let rocket_in_flight = game.game_state.rocket.is_some();
+    let player = &mut game.game_state.players[game.game_state.current_player];
+    let account = &mut game.game_state.accounts[player.id];
+
+    match action {
+        Action::AimLeft => player.cannon_angle -= AIM_STEP,
+        Action::AimRight => player.cannon_angle += AIM_STEP,
+        // The page shows the power, `update` posts it once the frame's actions are done
+        Action::PowerUp => player.cannon_power += POWER_STEP,
+        Action::PowerDown => player.cannon_power = player.cannon_power.saturating_sub(POWER_STEP),
+        // The tank stands still while its rocket flies, and driving takes fuel
+        Action::MoveLeft if !rocket_in_flight && account.burn_fuel(MOVE_STEP as f32) => {
+            player.terrain_position = drive(player.terrain_position, -(MOVE_STEP as i32), world_width);
+            reposition_player(player, &game.game_state.terrain_contour);
         }
-        "ArrowDown" => {
-            player.cannon_power -= 5;
-            update_ui(&game.game_state);
+        Action::MoveRight if !rocket_in_flight && account.burn_fuel(MOVE_STEP as f32) => {
+            player.terrain_position = drive(player.terrain_position, MOVE_STEP as i32, world_width);
+            reposition_player(player, &game.game_state.terrain_contour);
         }
-        " " => {
+        Action::MoveLeft | Action::MoveRight => {}
+        Action::NextWeapon => account.next_weapon(&game.catalogue),
+        Action::ToggleMute | Action::VolumeUp | Action::VolumeDown => {}
+        Action::Fire => {
             if game.game_state.rocket.is_none() {
                 let x = player.terrain_position;
                 let y = game.game_state.terrain_contour.get_index(x);
+                let weapon = account.fire(&game.catalogue);
 
                 game.game_state.rocket = Some(
                     create_rocket(
-                        game.rocket_texture.clone(),
+                        &game.atlas,
                         player.cannon_sprite.mask(),
                         player.cannon_sprite.color,
                         player.cannon_angle,
@@ -444,23 +997,102 @@ fn handle_keyboard_input(game: &mut TankGameFlyweight, key_code: &str) -> bool {
                         x as f32,
                         y,
                         player.id,
+                        weapon,
                     )
                     .expect("Could not create rocket"),
                 );
+                game.game_state
+                    .scoreboard
+                    .shot_fired(player.id, player.cannon_sprite.global_position);
+                game.game_state
+                    .effects
+                    .push(game.animations.spawn_muzzle_flash(&player.cannon_sprite));
                 game.smoke_emitter.reset();
                 game.smoke_emitter.spawn_frequency_hz = 120.;
-                play_audio(&game.launch_sound);
+                play_sound_at(game, "launch", x as f32);
             }
         }
-        _ => return false,
+    }
+}
+
+/*
+ * Between rounds the same keys drive the screens: fire moves on, and
+ * during setup left and right pick the number of players, up and
+ * down the number of rounds. In the shop the aim and power keys move
+ * the cursor and fire buys.
+ */
+fn perform_menu_action(state: &mut GameState, catalogue: &Catalogue, action: Action) {
+    let flow = &mut state.flow;
+    let shop = &mut state.shop;
+    // The catalogue and a last entry to leave the shop
+    let shop_entries = catalogue.items.len() + 1;
+    let event = match (flow.phase(), action) {
+        (MatchPhase::Setup, Action::AimLeft) => {
+            flow.set_player_count(flow.player_count() - 1);
+            None
+        }
+        (MatchPhase::Setup, Action::AimRight) => {
+            flow.set_player_count(flow.player_count() + 1);
+            None
+        }
+        (MatchPhase::Setup, Action::PowerUp) => {
+            flow.set_rounds(flow.rounds() + 1);
+            None
+        }
+        (MatchPhase::Setup, Action::PowerDown) => {
+            flow.set_rounds(flow.rounds() - 1);
+            None
+        }
+        (MatchPhase::Title, Action::Fire) | (MatchPhase::Results, Action::Fire) => Some(MatchEvent::OpenSetup),
+        (MatchPhase::Setup, Action::Fire) => Some(MatchEvent::StartMatch),
+        (MatchPhase::BetweenRounds, Action::Fire) => Some(MatchEvent::OpenShop),
+        (MatchPhase::Shop, Action::AimLeft) | (MatchPhase::Shop, Action::PowerUp) => {
+            shop.move_selection(-1, shop_entries);
+            None
+        }
+        (MatchPhase::Shop, Action::AimRight) | (MatchPhase::Shop, Action::PowerDown) => {
+            shop.move_selection(1, shop_entries);
+            None
+        }
+        (MatchPhase::Shop, Action::Fire) => match catalogue.items.get(shop.selected) {
+            Some(item) => {
+                if let Err(e) = state.accounts[shop.shopper].buy(item) {
+                    console::log_1(&e.into());
+                }
+                None
+            }
+            None if shop.next_shopper(flow.player_count()) => None,
+            None => Some(MatchEvent::NextRound),
+        },
+        _ => None,
     };
 
-    // Keydown was handled
-    true
+    if let Some(event) = event {
+        match flow.transition(event).expect("Menu events match their phase") {
+            MatchPhase::InRound => state.round_pending = true,
+            MatchPhase::Shop => *shop = Shop::default(),
+            _ => {}
+        }
+    }
+
+    // Shoppers use their own gamepad
+    if flow.phase() == MatchPhase::Shop {
+        state.current_player = shop.shopper;
+    }
+    update_ui(state);
 }
 
-fn update_players(game_state: &mut GameState) {
+fn update_players(game_state: &mut GameState, animations: &AnimationLibrary, dt: f32) {
     for player in &mut game_state.players {
+        if let Some(wreck) = &mut player.wreck {
+            wreck.update(dt);
+
+            // Once burnt out the wreck keeps smoldering
+            if wreck.animation.is_finished() {
+                wreck.animation = animations.smolder.clone();
+            }
+        }
+
         if player.is_alive && game_state.current_player == player.id {
             player.cannon_sprite.global_rotation = player.cannon_angle;
             player.cannon_sprite.update()
@@ -472,21 +1104,23 @@ fn update_players(game_state: &mut GameState) {
             .terrain_contour
             .get_index(player.terrain_position);
 
-        if position_y >= game_state.client_height as f32 {
+        if position_y >= game_state.world_height as f32 {
             player.is_alive = false;
         } else if terrain_height > position_y {
+            // The treads only roll while the tank is moving
             reposition_player(player, &game_state.terrain_contour);
+            player.tread.update(dt);
         }
     }
 }
 
-fn is_rocket_in_bounds(rocket: &Rocket, canvas_width: u32, canvas_height: u32) -> bool {
+fn is_rocket_in_bounds(rocket: &Rocket, world_width: u32, world_height: u32) -> bool {
     let position = &rocket.sprite.global_position;
 
     position.x() > 0.0
         && position.y() > 0.0
-        && position.x() < canvas_width as f32
-        && position.y() < canvas_height as f32
+        && position.x() < world_width as f32
+        && position.y() < world_height as f32
 }
 
 fn update_rocket(rocket: &mut Rocket, dt: f32) {
@@ -550,6 +1184,8 @@ fn rocket_hit_terrain(rocket: &Rocket, terrain_contour: &Float32Array) -> bool {
 
 fn update_ui(state: &GameState) {
     let current_player = &state.players[state.current_player];
+    let flow = &state.flow;
+    let round_over = matches!(flow.phase(), MatchPhase::BetweenRounds | MatchPhase::Results);
     post_ui_state(&Ui {
         cannon_power: Some(current_player.cannon_power),
         current_player: Some(current_player.id),
@@ -560,30 +1196,109 @@ fn update_ui(state: &GameState) {
             3 => "purple",
             _ => "yelow",
         })),
-        game_over: Some(state.game_over),
+        phase: Some(flow.phase()),
+        round: Some(flow.round()),
+        rounds: Some(flow.rounds()),
+        wins: Some(flow.wins().to_vec()),
+        round_winner: flow.round_winner(),
+        match_winner: flow.match_winner(),
+        accounts: Some(state.accounts.clone()),
+        results: if round_over {
+            Some(state.scoreboard.results(flow.round(), flow.phase() == MatchPhase::Results))
+        } else {
+            None
+        },
+        ..Default::default()
     })
     .expect("Could not post UI state");
 }
 
 fn next_turn(state: &mut GameState) {
-    let previous_player = state.current_player;
-    for _ in 0..state.players.len() {
-        state.current_player = (state.current_player + 1) % state.players.len();
-
-        if state.players[state.current_player].is_alive {
-            break;
+    let alive: Vec<bool> = state.players.iter().map(|player| player.is_alive).collect();
+    if let Some(survivor) = round_survivor(&alive) {
+        state.scoreboard.end_round(survivor);
+        for (player, account) in state.accounts.iter_mut().enumerate() {
+            account.money += earnings(state.scoreboard.round(player));
+        }
+        state
+            .flow
+            .transition(MatchEvent::RoundEnded(survivor))
+            .expect("Turns only pass during a round");
+    } else {
+        for _ in 0..state.players.len() {
+            state.current_player = (state.current_player + 1) % state.players.len();
+
+            if state.players[state.current_player].is_alive {
+                break;
+            }
         }
     }
-    state.game_over = previous_player == state.current_player;
 
     update_ui(state);
 }
 
+// `attacker` fired the rocket that did it, for the scores
+fn damage_player(state: &mut GameState, attacker: Option<usize>, player_id: usize, amount: f32) {
+    let player = &mut state.players[player_id];
+    if !player.is_alive || amount < 1.0 {
+        return;
+    }
+
+    // Shields take the damage first
+    let amount = state.accounts[player_id].absorb(amount);
+    if amount < 1.0 {
+        return;
+    }
+
+    let health_before = player.health;
+    player.health = (player.health - amount).max(0.0);
+    if player.health <= 0.0 {
+        player.is_alive = false;
+    }
+
+    state.scoreboard.damage(
+        attacker,
+        player_id,
+        health_before - player.health,
+        player.carriage_sprite.global_position,
+        !player.is_alive,
+    );
+
+    state.damage_numbers.push(DamageNumber::new(
+        amount.round() as u32,
+        player.carriage_sprite.global_position - Vec3::new(0.0, 100.0, 0.0),
+        [1.0, 0.9, 0.2, 1.0],
+    ));
+}
+
+// Damage falls off linearly with the distance to the explosion
+fn apply_splash_damage(
+    state: &mut GameState,
+    center: Vec3,
+    spared_player: Option<usize>,
+    attacker: Option<usize>,
+    weapon: &Weapon,
+) {
+    for id in 0..state.players.len() {
+        if spared_player == Some(id) {
+            continue;
+        }
+
+        let distance = (state.players[id].carriage_sprite.global_position - center).length();
+        if distance < weapon.splash_radius {
+            damage_player(state, attacker, id, weapon.splash_damage * (1.0 - distance / weapon.splash_radius));
+        }
+    }
+}
+
 fn add_crater_to_terrain(
     terrain_contour: &mut Float32Array,
+    terrain_scorch: &mut [f32],
     crater_center_x: f32,
     crater_radius: f32,
 ) {
+    scorch_crater(terrain_scorch, crater_center_x, crater_radius);
+
     let crater_start = (crater_center_x - crater_radius).floor() as u32 + 1;
     let crater_end = (crater_center_x + crater_radius).ceil() as u32 - 1;
     let crater_center_y = terrain_contour.get_index(crater_center_x as u32);
@@ -602,7 +1317,37 @@ fn add_crater_to_terrain(
 }
 
 fn update(game: &mut TankGameFlyweight, dt: f32) {
+    let turn = game.game_state.current_player;
+    let mut actions = game.input.update(dt);
+    // Steer the cannon towards the slingshot with the same steps as the keys
+    if let (Some(target), Some(cannon)) = (game.pointer.aim_target(), aiming_cannon(game)) {
+        let player = &game.game_state.players[game.game_state.current_player];
+        let (angle, power) = slingshot_aim(cannon, target, GAUGE_POWER);
+        actions.extend(aim_actions(player.cannon_angle, player.cannon_power, angle, power));
+    }
+    actions.extend(game.gamepads.update(
+        &poll_gamepads(),
+        game.game_state.players.len(),
+        game.game_state.current_player,
+        dt,
+    ));
+    let power_changed = game.game_state.flow.phase() == MatchPhase::InRound
+        && actions.iter().any(|action| matches!(action, Action::PowerUp | Action::PowerDown));
+    for action in actions {
+        perform_action(game, action);
+    }
+    if power_changed {
+        update_ui(&game.game_state);
+    }
+    game.audio.update(dt);
+
+    if let Some(post_processor) = &mut game.post_processor {
+        post_processor.update(dt);
+    }
+
     if let Some(rocket) = &mut game.game_state.rocket {
+        let shooter = Some(rocket.player_id);
+        let weapon = rocket.weapon.clone();
         update_rocket(rocket, dt);
         game.smoke_emitter.location = rocket.sprite.global_position
             - Vec3::new(
@@ -613,8 +1358,8 @@ fn update(game: &mut TankGameFlyweight, dt: f32) {
 
         if !is_rocket_in_bounds(
             rocket,
-            game.game_state.client_width,
-            game.game_state.client_height,
+            game.game_state.world_width,
+            game.game_state.world_height,
         ) {
             game.game_state.rocket = None;
             game.smoke_emitter.spawn_frequency_hz = 0.;
@@ -622,15 +1367,26 @@ fn update(game: &mut TankGameFlyweight, dt: f32) {
         } else if let Some(player) = rocket_collided(rocket, &game.game_state.players) {
             add_crater_to_terrain(
                 &mut game.game_state.terrain_contour,
+                &mut game.game_state.terrain_scorch,
                 game.game_state.players[player]
                     .carriage_sprite
                     .global_position
                     .x(),
-                40.0f32,
+                weapon.crater_radius,
             );
             game.game_state.terrain_dirty = true;
 
-            game.game_state.players[player].is_alive = false;
+            let impact = game.game_state.players[player]
+                .carriage_sprite
+                .global_position;
+            game.game_state
+                .effects
+                .push(game.animations.spawn_explosion(impact));
+            damage_player(&mut game.game_state, shooter, player, weapon.direct_damage);
+            if let Some(post_processor) = &mut game.post_processor {
+                post_processor.flash.trigger([1.0, 0.95, 0.85], 0.6);
+            }
+            apply_splash_damage(&mut game.game_state, impact, Some(player), shooter, &weapon);
             game.smoke_emitter.spawn_frequency_hz = 0.;
             game.explosion_emitter.location = game.game_state.players[player]
                 .carriage_sprite
@@ -643,17 +1399,24 @@ fn update(game: &mut TankGameFlyweight, dt: f32) {
             game.explosion_emitter.reset();
             game.explosion_emitter.spawn_frequency_hz = 50.;
             game.game_state.rocket = None;
-            play_audio(&game.hitcannon_sound);
+            play_sound_at(game, "hitcannon", impact.x());
+            game.audio.duck_music(EXPLOSION_DUCKING);
             next_turn(&mut game.game_state);
         } else if rocket_hit_terrain(rocket, &game.game_state.terrain_contour) {
             add_crater_to_terrain(
                 &mut game.game_state.terrain_contour,
+                &mut game.game_state.terrain_scorch,
                 rocket.sprite.global_position.x(),
-                40.0f32,
+                weapon.crater_radius,
             );
             game.game_state.terrain_dirty = true;
+            let impact = rocket.sprite.global_position;
+            game.game_state
+                .effects
+                .push(game.animations.spawn_explosion(impact));
+            apply_splash_damage(&mut game.game_state, impact, None, shooter, &weapon);
             game.smoke_emitter.spawn_frequency_hz = 0.;
-            game.explosion_emitter.location = rocket.sprite.global_position
+            game.explosion_emitter.location = impact
                 - Vec3::new(
                     game.explosion_emitter.initial_particle_scale / 2.,
                     game.explosion_emitter.initial_particle_scale / 2.,
@@ -662,35 +1425,142 @@ fn update(game: &mut TankGameFlyweight, dt: f32) {
             game.explosion_emitter.reset();
             game.explosion_emitter.spawn_frequency_hz = 50.;
             game.game_state.rocket = None;
-            play_audio(&game.hitterrain_sound);
+            play_sound_at(game, "hitterrain", impact.x());
+            game.audio.duck_music(EXPLOSION_DUCKING);
             next_turn(&mut game.game_state)
         }
     }
 
-    update_players(&mut game.game_state);
+    spawn_wrecks(&mut game.game_state, &game.animations);
+    update_players(&mut game.game_state, &game.animations, dt);
+    update_effects(&mut game.game_state.effects, dt);
+    update_camera(&mut game.game_state, dt);
+    game.background
+        .update(&game.game_state.camera, game.game_state.wind, dt);
+    update_damage_numbers(&mut game.game_state.damage_numbers, dt);
     game.smoke_emitter.update(dt);
     game.explosion_emitter.update(dt);
 
-    if !game.game_state.players[game.game_state.current_player].is_alive {
+    let in_round = game.game_state.flow.phase() == MatchPhase::InRound;
+    if in_round && !game.game_state.players[game.game_state.current_player].is_alive {
         next_turn(&mut game.game_state);
     }
+
+    // A slingshot held through a shot or a turn change must not aim the next cannon
+    if aiming_cannon(game).is_none() || game.game_state.current_player != turn {
+        game.pointer.cancel();
+    }
+
+    let players_alive = game.game_state.players.iter().filter(|player| player.is_alive).count();
+    let round_over = matches!(
+        game.game_state.flow.phase(),
+        MatchPhase::BetweenRounds | MatchPhase::Results
+    );
+    game.music.update(&mut game.audio, players_alive, round_over);
+}
+
+// Tanks destroyed by damage burn out where they stood
+fn spawn_wrecks(state: &mut GameState, animations: &AnimationLibrary) {
+    for player in &mut state.players {
+        if player.is_alive || player.health > 0.0 || player.wreck.is_some() {
+            continue;
+        }
+
+        let carriage = &player.carriage_sprite;
+        let mut wreck = animations.spawn(&animations.wreck, carriage.global_position, carriage.global_scale);
+        wreck.sprite.local_position = carriage.local_position;
+        wreck.sprite.layer = RenderLayer::Debris;
+        wreck.sprite.update();
+        player.wreck = Some(wreck);
+
+        state
+            .effects
+            .push(animations.spawn_explosion(carriage.global_position));
+    }
+}
+
+fn update_effects(effects: &mut Vec<AnimatedSprite>, dt: f32) {
+    for effect in effects.iter_mut() {
+        effect.update(dt);
+    }
+
+    effects.retain(|effect| !effect.animation.is_finished());
+}
+
+fn update_camera(state: &mut GameState, dt: f32) {
+    let target = match &state.rocket {
+        Some(rocket) => rocket.sprite.global_position,
+        None => {
+            state.players[state.current_player]
+                .carriage_sprite
+                .global_position
+        }
+    };
+
+    state.camera.follow(target, dt);
+}
+
+fn new_post_processor(
+    gl: &WebGl2RenderingContext,
+    graphics: &GraphicsSettings,
+) -> Result<Option<PostProcessor>, JsValue> {
+    if !graphics.post_processing() {
+        return Ok(None);
+    }
+
+    let width = gl.drawing_buffer_width() as u32;
+    let height = gl.drawing_buffer_height() as u32;
+    Ok(Some(PostProcessor::new(gl, graphics, width, height)?))
+}
+
+/*
+ * Everything on the GPU is gone after a context loss. Textures are
+ * recreated in place since sprites throughout the game state share
+ * them, shaders and vertex arrays are built again and the terrain
+ * mask is uploaded again from the CPU side buffer.
+ */
+fn restore_graphics(gl: &WebGl2RenderingContext, game: &mut TankGameFlyweight) -> Result<(), JsValue> {
+    gl.viewport(0, 0, gl.drawing_buffer_width(), gl.drawing_buffer_height());
+
+    restore_textures()?;
+
+    let sprite_shader = Rc::new(new_sprite_shader(gl)?);
+    game.sprite_renderer = SpriteRenderer::new(gl, sprite_shader.clone())?;
+    game.text_renderer.restore(gl, sprite_shader)?;
+    game.terrain_renderer.restore(gl)?;
+
+    let particle_shader = Rc::new(new_particle_shader(gl)?);
+    game.smoke_emitter.restore(gl, particle_shader.clone())?;
+    game.explosion_emitter.restore(gl, particle_shader.clone())?;
+    game.particle_shader = particle_shader;
+
+    game.post_processor = new_post_processor(gl, &game.graphics)?;
+    game.game_state.terrain_dirty = true;
+
+    Ok(())
 }
 
 fn prepare_dirty_resources(gl: &WebGl2RenderingContext, game: &mut TankGameFlyweight) {
-    if game.game_state.terrain_dirty {
-        let old_mask = game.foreground_sprite.mask().clone();
+    if game.game_state.round_pending {
+        // Tried again next frame, the round stays pending until it starts
+        if let Err(e) = start_round(gl, game) {
+            console::error_2(&"Could not start the round:".into(), &e);
+        }
+    }
 
+    if game.game_state.terrain_dirty {
         let new_mask = generate_terrain_mask(
             gl,
             &mut game.foreground_mask_buffer,
             &game.game_state.terrain_contour,
-            game.game_state.client_width,
-            game.game_state.client_height,
+            &game.game_state.terrain_scorch,
+            game.game_state.world_width,
+            game.game_state.world_height,
         )
         .expect("Could not create terrain mask");
 
-        game.foreground_sprite.set_mask(new_mask.clone());
-        gl.delete_texture(Some(&old_mask));
+        // The old mask is deleted once the last sprite using it is dropped
+        game.foreground_sprite.set_mask(new_mask);
         game.game_state.terrain_dirty = false;
     }
 }
@@ -698,10 +1568,11 @@ fn prepare_dirty_resources(gl: &WebGl2RenderingContext, game: &mut TankGameFlywe
 fn render_shape(
     gl: &WebGl2RenderingContext,
     shape: &Shape,
-    texture: Rc<WebGlTexture>,
+    texture: Rc<Texture>,
     game: &TankGameFlyweight,
 ) {
-    let mut sprite = Sprite::new(gl, texture).expect("Could not create shape sprite");
+    let mut sprite = Sprite::new_with_mask(texture, game.white_texture.clone())
+        .expect("Could not create shape sprite");
     match shape {
         Shape::Rectangle(rectangle) => {
             sprite.global_position = rectangle.top_left;
@@ -721,45 +1592,387 @@ fn render_shape(
 fn render(gl: &WebGl2RenderingContext, game: &TankGameFlyweight) {
     gl.clear_color(0.0, 0.0, 0.0, 1.0);
     gl.clear_depth(1.0);
-    gl.enable(WebGl2RenderingContext::BLEND);
-    gl.blend_func(
-        WebGl2RenderingContext::SRC_ALPHA,
-        WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
-    );
-
     gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
 
-    let renderer = &game.sprite_renderer;
-    renderer.render(gl, &game.background_sprite);
-    renderer.render(gl, &game.foreground_sprite);
+    let projection = game.game_state.camera.projection();
+    set_projection(gl, game, &projection);
+
+    // Sprites carry their own layer, the queue puts everything in order
+    let mut queue = RenderQueue::new();
+    for sprite in game.background.sprites() {
+        queue.push_sprite(sprite);
+    }
+    // The terrain shader lights the ground from the data in its mask
+    let world_size = (game.game_state.world_width as f32, game.game_state.world_height as f32);
+    let draw_terrain = |gl: &WebGl2RenderingContext| {
+        game.terrain_renderer.render(gl, &game.foreground_sprite, world_size.0, world_size.1)
+    };
+    queue.push(game.foreground_sprite.render_key(), Drawable::Custom(&draw_terrain));
 
     for player in &game.game_state.players {
         if !player.is_alive {
+            if let Some(wreck) = &player.wreck {
+                queue.push_sprite(&wreck.sprite);
+            }
             continue;
         }
 
-        renderer.render(gl, &player.cannon_sprite);
-        renderer.render(gl, &player.carriage_sprite);
+        queue.push_sprite(&player.tread.sprite);
+        queue.push_sprite(&player.cannon_sprite);
+        queue.push_sprite(&player.carriage_sprite);
+    }
+
+    if let Some(rocket) = &game.game_state.rocket {
+        queue.push_sprite(&rocket.sprite);
+    }
+
+    for effect in &game.game_state.effects {
+        queue.push_sprite(&effect.sprite);
+    }
+
+    let particles = RenderKey::new(RenderLayer::Effects, 1.0, BlendMode::Additive);
+    queue.push(particles, Drawable::Particles(&game.smoke_emitter));
+    queue.push(particles, Drawable::Particles(&game.explosion_emitter));
+
+    // render shapes used in collision detection
+    let shapes = |gl: &WebGl2RenderingContext| render_shapes(gl, game);
+    if game.render_shapes {
+        queue.push(
+            RenderKey::new(RenderLayer::Effects, 2.0, BlendMode::Alpha),
+            Drawable::Custom(&shapes),
+        );
+    }
 
-        // render shapes used in collision detection
-        if game.render_shapes {
-            let shape = player_to_shape(player);
-            render_shape(gl, &shape, player.carriage_sprite.mask(), game);
+    let hud = |gl: &WebGl2RenderingContext| render_hud(gl, game);
+    queue.push(
+        RenderKey::new(RenderLayer::Hud, 0.0, BlendMode::Alpha),
+        Drawable::Custom(&hud),
+    );
+
+    let minimap = |gl: &WebGl2RenderingContext| {
+        if let Some(minimap) = &game.minimap {
+            render_minimap(gl, minimap, game);
         }
+    };
+    queue.push(
+        RenderKey::new(RenderLayer::Hud, 1.0, BlendMode::Alpha),
+        Drawable::Custom(&minimap),
+    );
+
+    match &game.post_processor {
+        Some(post_processor) => {
+            post_processor.begin(gl);
+            queue.render_layers(gl, &game.sprite_renderer, ..RenderLayer::Hud);
+            post_processor.finish(gl);
+        }
+        None => queue.render_layers(gl, &game.sprite_renderer, ..RenderLayer::Hud),
+    }
+
+    // The HUD is drawn on top of the post-processed scene
+    queue.render_layers(gl, &game.sprite_renderer, RenderLayer::Hud..);
+}
+
+fn render_shapes(gl: &WebGl2RenderingContext, game: &TankGameFlyweight) {
+    for player in game.game_state.players.iter().filter(|player| player.is_alive) {
+        let shape = player_to_shape(player);
+        render_shape(gl, &shape, player.carriage_sprite.mask(), game);
     }
 
     if let Some(rocket) = &game.game_state.rocket {
-        renderer.render(gl, &rocket.sprite);
-        if game.render_shapes {
-            let shape = rocket_to_shape(rocket);
-            render_shape(gl, &shape, rocket.sprite.mask(), game);
+        let shape = rocket_to_shape(rocket);
+        render_shape(gl, &shape, rocket.sprite.mask(), game);
+    }
+}
+
+fn render_hud(gl: &WebGl2RenderingContext, game: &TankGameFlyweight) {
+    let camera = &game.game_state.camera;
+    let player = &game.game_state.players[game.game_state.current_player];
+
+    // World space elements follow the tanks
+    for other in &game.game_state.players {
+        if !other.is_alive {
+            continue;
         }
+
+        // Shows which gamepad drives the tank
+        let name = match game.gamepads.player_gamepad(other.id) {
+            Some(gamepad) => format!("Player {} (pad {})", other.id + 1, gamepad + 1),
+            None => format!("Player {}", other.id + 1),
+        };
+        game.hud
+            .render_nameplate(
+                gl,
+                &game.sprite_renderer,
+                &game.text_renderer,
+                &Nameplate {
+                    name: &name,
+                    position: other.carriage_sprite.global_position - Vec3::new(0.0, 60.0, 0.0),
+                    color: other.carriage_sprite.color,
+                    health_fraction: other.health / MAX_HEALTH,
+                },
+            )
+            .expect("Could not render nameplate");
     }
 
-    gl.blend_func(
-        WebGl2RenderingContext::SRC_ALPHA,
-        WebGl2RenderingContext::ONE,
+    if aiming_cannon(game).is_some() {
+        game.hud
+            .render_aim_gauge(
+                gl,
+                &game.sprite_renderer,
+                &game.text_renderer,
+                &AimGauge {
+                    pivot: player.cannon_sprite.global_position,
+                    angle: player.cannon_angle,
+                    power_fraction: (player.cannon_power as f32 / GAUGE_POWER as f32).min(1.0),
+                    color: player.carriage_sprite.color,
+                },
+            )
+            .expect("Could not render aim gauge");
+    }
+
+    game.hud
+        .render_damage_numbers(gl, &game.text_renderer, &game.game_state.damage_numbers);
+
+    // Screen space elements stay in place
+    game.sprite_renderer
+        .set_projection(gl, &camera.screen_projection());
+
+    if game.game_state.wind != 0.0 {
+        game.hud
+            .render_wind(
+                gl,
+                &game.sprite_renderer,
+                &game.text_renderer,
+                camera,
+                game.game_state.wind / MAX_WIND,
+            )
+            .expect("Could not render wind indicator");
+    }
+
+    if game.game_state.flow.phase() == MatchPhase::InRound {
+        let account = &game.game_state.accounts[player.id];
+        let (weapon, _) = game.catalogue.weapon(account.weapon.as_deref());
+        let weapon = match account.weapon.as_ref().and_then(|id| account.inventory.ammo.get(id)) {
+            Some(ammo) => format!("{} x{}", weapon, ammo),
+            None => String::from(weapon),
+        };
+        let text = format!(
+            "Angle: {}  Power: {}  {}  Shield: {}  Fuel: {}",
+            player.cannon_angle.round(),
+            player.cannon_power,
+            weapon,
+            account.inventory.shield.round(),
+            account.inventory.fuel.round()
+        );
+        game.text_renderer.render(
+            gl,
+            &text,
+            Vec3::new(16.0, camera.viewport_height() - 48.0, 0.0),
+            1.0,
+            TextAlign::Left,
+            &player.carriage_sprite.color,
+        );
+    }
+
+    if let Some(button) = visible_fire_button(game) {
+        game.hud
+            .render_fire_button(
+                gl,
+                &game.sprite_renderer,
+                &game.text_renderer,
+                &button,
+                player.carriage_sprite.color,
+            )
+            .expect("Could not render fire button");
+    }
+
+    render_match_screen(gl, game);
+
+    game.sprite_renderer.set_projection(gl, &camera.projection());
+}
+
+// Money, then every item with what the shopper already has of it
+// A line of text and its color
+type TextLine = (String, [f32; 4]);
+
+fn shop_lines(state: &GameState, catalogue: &Catalogue) -> Vec<TextLine> {
+    let white = [1.0, 1.0, 1.0, 1.0];
+    let highlight = [1.0, 0.9, 0.2, 1.0];
+    let account = &state.accounts[state.shop.shopper];
+    let inventory = &account.inventory;
+
+    let mut lines = vec![(format!("Money: ${}", account.money), white)];
+    for (i, item) in catalogue.items.iter().enumerate() {
+        let owned = match &item.kind {
+            ItemKind::Weapon(_) => inventory.ammo.get(&item.id).copied().unwrap_or(0) as f32,
+            ItemKind::Shield { .. } => inventory.shield,
+            ItemKind::Fuel { .. } => inventory.fuel,
+        };
+        let line = format!("{}   ${}   (have {})", item.name, item.price, owned.round());
+        let color = if state.shop.selected == i { highlight } else { white };
+        lines.push((line, color));
+    }
+
+    let color = if state.shop.selected == catalogue.items.len() { highlight } else { white };
+    lines.push((String::from("Done"), color));
+    lines
+}
+
+// Title, color and lines of the screen over the field outside of rounds, the last line prompts to go on
+fn match_screen(game: &TankGameFlyweight) -> Option<(String, [f32; 4], Vec<TextLine>)> {
+    let flow = &game.game_state.flow;
+    let white = [1.0, 1.0, 1.0, 1.0];
+    let player_color = |player: Option<usize>| {
+        player
+            .and_then(|player| game.game_state.players.get(player))
+            .map_or(white, |player| player.carriage_sprite.color)
+    };
+    let standings = || {
+        flow.wins()
+            .iter()
+            .enumerate()
+            .map(|(player, wins)| {
+                let stats = game.game_state.scoreboard.total(player);
+                let line = format!(
+                    "Player {}   {} won   {} points   {}% hits   longest hit {}",
+                    player + 1,
+                    wins,
+                    stats.score(),
+                    (stats.accuracy() * 100.0).round(),
+                    stats.longest_hit.round()
+                );
+                (line, player_color(Some(player)))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let (title, title_color, mut lines) = match flow.phase() {
+        MatchPhase::InRound => return None,
+        MatchPhase::Title => (String::from("Tank Shooter"), white, vec![]),
+        MatchPhase::Setup => (
+            String::from("New match"),
+            white,
+            vec![
+                (format!("<   Players: {}  (left / right)   >", flow.player_count()), white),
+                (format!("<   Rounds: {}  (up / down)   >", flow.rounds()), white),
+            ],
+        ),
+        MatchPhase::BetweenRounds => {
+            let title = match flow.round_winner() {
+                Some(player) => format!("Player {} wins round {}", player + 1, flow.round()),
+                None => format!("Round {} is a draw", flow.round()),
+            };
+            (title, player_color(flow.round_winner()), standings())
+        }
+        MatchPhase::Shop => {
+            let shop = &game.game_state.shop;
+            (
+                format!("Player {} goes shopping", shop.shopper + 1),
+                player_color(Some(shop.shopper)),
+                shop_lines(&game.game_state, &game.catalogue),
+            )
+        }
+        MatchPhase::Results => {
+            let title = match flow.match_winner() {
+                Some(player) => format!("Player {} wins the match!", player + 1),
+                None => String::from("The match is a draw"),
+            };
+            (title, player_color(flow.match_winner()), standings())
+        }
+    };
+
+    let prompt = match flow.phase() {
+        MatchPhase::Title => String::from("Press fire or tap here to start"),
+        MatchPhase::Setup => String::from("Press fire or tap here to begin"),
+        MatchPhase::BetweenRounds => String::from("Press fire or tap here to go shopping"),
+        MatchPhase::Shop => String::from("Up / down or tap to choose, fire or tap again to buy"),
+        _ => String::from("Press fire or tap here for a new match"),
+    };
+    lines.push((prompt, white));
+
+    Some((title, title_color, lines))
+}
+
+// Title, setup, shop and results text over the field, in screen space
+fn render_match_screen(gl: &WebGl2RenderingContext, game: &TankGameFlyweight) {
+    let (title, title_color, lines) = match match_screen(game) {
+        Some(screen) => screen,
+        None => return,
+    };
+
+    let camera = &game.game_state.camera;
+    let (width, height) = (camera.viewport_width(), camera.viewport_height());
+    let center_x = width / 2.0;
+    game.text_renderer.render(
+        gl,
+        &title,
+        Vec3::new(center_x, menu_title_top(height), 0.0),
+        2.0,
+        TextAlign::Center,
+        &title_color,
     );
-    game.smoke_emitter.render(gl);
-    game.explosion_emitter.render(gl);
+
+    for (i, (line, color)) in lines.iter().enumerate() {
+        let y = menu_line(i, width, height).top_left.y();
+        game.text_renderer
+            .render(gl, line, Vec3::new(center_x, y, 0.0), 1.0, TextAlign::Center, color);
+    }
+}
+
+fn set_projection(gl: &WebGl2RenderingContext, game: &TankGameFlyweight, projection: &Mat4) {
+    game.sprite_renderer.set_projection(gl, projection);
+    game.terrain_renderer.set_projection(gl, projection);
+
+    game.particle_shader.use_program(gl);
+    game.particle_shader.set_mat4(gl, "uProjectionMatrix", projection);
+}
+
+fn render_minimap(gl: &WebGl2RenderingContext, minimap: &Minimap, game: &TankGameFlyweight) {
+    let mut markers: Vec<MinimapMarker> = game
+        .game_state
+        .players
+        .iter()
+        .filter(|player| player.is_alive)
+        .map(|player| MinimapMarker {
+            position: player.carriage_sprite.global_position,
+            color: player.carriage_sprite.color,
+        })
+        .collect();
+
+    if let Some(rocket) = &game.game_state.rocket {
+        markers.push(MinimapMarker {
+            position: rocket.sprite.global_position,
+            color: [1.0, 1.0, 1.0, 1.0],
+        });
+    }
+
+    minimap
+        .render(
+            gl,
+            &game.sprite_renderer,
+            &game.game_state.camera,
+            &game.foreground_sprite,
+            &markers,
+        )
+        .expect("Could not render minimap");
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{drive, tank_position, MOVE_STEP};
+    use crate::vector::Vec3;
+
+    #[test]
+    fn driving_moves_the_tank_on_flat_ground() {
+        let moved = drive(100, MOVE_STEP as i32, 2400);
+        assert_eq!(102, moved);
+
+        // The carriage follows even when the ground height does not change
+        assert_eq!(Vec3::new(100.0, 480.5, 0.0), tank_position(100, 500.0));
+        assert_eq!(Vec3::new(102.0, 480.5, 0.0), tank_position(moved, 500.0));
+
+        assert_eq!(0, drive(1, -(MOVE_STEP as i32), 2400));
+        assert_eq!(2399, drive(2399, MOVE_STEP as i32, 2400));
+    }
 }
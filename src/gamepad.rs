@@ -0,0 +1,329 @@
+use wasm_bindgen::JsCast;
+use web_sys::{Gamepad, GamepadButton};
+
+use crate::{
+    dom::window,
+    input::{Action, AIM_STEP, POWER_STEP},
+};
+
+// Buttons and axes of the browser's "standard" gamepad layout
+const BUTTON_A: usize = 0;
+const BUTTON_Y: usize = 3;
+const BUTTON_RIGHT_BUMPER: usize = 5;
+const LEFT_TRIGGER: usize = 6;
+const RIGHT_TRIGGER: usize = 7;
+const BUTTON_BACK: usize = 8;
+const DPAD_LEFT: usize = 14;
+const DPAD_RIGHT: usize = 15;
+const LEFT_STICK_X: usize = 0;
+
+// Sticks and triggers rest a little off zero
+const DEAD_ZONE: f32 = 0.15;
+// At full deflection, in degrees and power per second
+const AIM_SPEED: f32 = 60.0;
+const POWER_SPEED: f32 = 60.0;
+// Drive actions per second while the d-pad is held
+const MOVES_PER_SECOND: f32 = 20.0;
+// Button value above which a trigger counts as pressed
+const PRESSED_THRESHOLD: f32 = 0.5;
+
+// One gamepad as polled this frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct GamepadSnapshot {
+    pub index: u32,
+    pub axes: Vec<f32>,
+    // Between 0 and 1, only triggers have values in between
+    pub buttons: Vec<f32>,
+}
+
+impl GamepadSnapshot {
+    fn axis(&self, axis: usize) -> f32 {
+        let value = self.axes.get(axis).copied().unwrap_or(0.0);
+        if value.abs() < DEAD_ZONE {
+            0.0
+        } else {
+            // Rescaled so the dead zone edge is zero
+            value.signum() * (value.abs() - DEAD_ZONE) / (1.0 - DEAD_ZONE)
+        }
+    }
+
+    fn button(&self, button: usize) -> f32 {
+        let value = self.buttons.get(button).copied().unwrap_or(0.0);
+        if value < DEAD_ZONE {
+            0.0
+        } else {
+            value
+        }
+    }
+
+    fn pressed(&self) -> Vec<bool> {
+        self.buttons.iter().map(|value| *value >= PRESSED_THRESHOLD).collect()
+    }
+}
+
+// Browsers without the Gamepad API, or that block it, have no gamepads
+pub fn poll_gamepads() -> Vec<GamepadSnapshot> {
+    let gamepads = match window().navigator().get_gamepads() {
+        Ok(gamepads) => gamepads,
+        Err(_) => return Vec::new(),
+    };
+
+    // Disconnected slots are null
+    gamepads
+        .iter()
+        .filter_map(|gamepad| gamepad.dyn_into::<Gamepad>().ok())
+        .filter(|gamepad| gamepad.connected())
+        .map(|gamepad| GamepadSnapshot {
+            index: gamepad.index(),
+            axes: gamepad
+                .axes()
+                .iter()
+                .map(|axis| axis.as_f64().unwrap_or(0.0) as f32)
+                .collect(),
+            buttons: gamepad
+                .buttons()
+                .iter()
+                .map(|button| button.unchecked_into::<GamepadButton>().value() as f32)
+                .collect(),
+        })
+        .collect()
+}
+
+struct Controller {
+    index: u32,
+    pressed: Vec<bool>,
+}
+
+/*
+ * Gamepads, polled once a frame. The first press on a new gamepad
+ * claims the first player without one, after that the gamepad only
+ * controls that player's tank and only on their turn. The left stick
+ * aims, the triggers set the power, the d-pad drives, A fires and Y or
+ * the right bumper switch weapons; all as the keyboard's actions.
+ */
+pub struct GamepadInput {
+    // Gamepad index for every player
+    players: Vec<Option<u32>>,
+    controllers: Vec<Controller>,
+    // Player whose turn the carried fractions belong to
+    turn: usize,
+    // Fractions of an action carried over to the next frame
+    aim: f32,
+    power: f32,
+    movement: f32,
+}
+
+impl GamepadInput {
+    pub fn new() -> GamepadInput {
+        GamepadInput {
+            players: Vec::new(),
+            controllers: Vec::new(),
+            turn: 0,
+            aim: 0.0,
+            power: 0.0,
+            movement: 0.0,
+        }
+    }
+
+    pub fn player_gamepad(&self, player: usize) -> Option<u32> {
+        self.players.get(player).copied().flatten()
+    }
+
+    pub fn update(
+        &mut self,
+        gamepads: &[GamepadSnapshot],
+        player_count: usize,
+        current_player: usize,
+        dt: f32,
+    ) -> Vec<Action> {
+        self.players.resize(player_count, None);
+        self.disconnect_missing(gamepads);
+
+        // What is left of one player's stick does not move the next player's tank
+        if current_player != self.turn {
+            self.turn = current_player;
+            self.aim = 0.0;
+            self.power = 0.0;
+            self.movement = 0.0;
+        }
+
+        let mut actions = Vec::new();
+        for gamepad in gamepads {
+            let pressed = gamepad.pressed();
+            let previous = self.previous_pressed(gamepad.index, &pressed);
+            let just_pressed = |button: usize| {
+                pressed.get(button).copied().unwrap_or(false) && !previous.get(button).copied().unwrap_or(false)
+            };
+
+            match self.players.iter().position(|index| *index == Some(gamepad.index)) {
+                Some(player) if player == current_player => {
+                    if just_pressed(BUTTON_A) {
+                        actions.push(Action::Fire);
+                    }
+                    if just_pressed(BUTTON_Y) || just_pressed(BUTTON_RIGHT_BUMPER) {
+                        actions.push(Action::NextWeapon);
+                    }
+                    if just_pressed(BUTTON_BACK) {
+                        actions.push(Action::ToggleMute);
+                    }
+                    actions.extend(self.analog_actions(gamepad, dt));
+                }
+                Some(_) => {}
+                // The claiming press only claims, so it does not fire by accident
+                None => {
+                    if (0..pressed.len()).any(just_pressed) {
+                        if let Some(free) = self.players.iter_mut().find(|index| index.is_none()) {
+                            *free = Some(gamepad.index);
+                        }
+                    }
+                }
+            }
+        }
+
+        actions
+    }
+
+    fn analog_actions(&mut self, gamepad: &GamepadSnapshot, dt: f32) -> Vec<Action> {
+        let aim = gamepad.axis(LEFT_STICK_X) * AIM_SPEED * dt / AIM_STEP;
+        let power =
+            (gamepad.button(RIGHT_TRIGGER) - gamepad.button(LEFT_TRIGGER)) * POWER_SPEED * dt / POWER_STEP as f32;
+        let movement = (gamepad.button(DPAD_RIGHT) - gamepad.button(DPAD_LEFT)) * MOVES_PER_SECOND * dt;
+
+        let mut actions = Vec::new();
+        actions.extend(whole_steps(&mut self.aim, aim, Action::AimLeft, Action::AimRight));
+        actions.extend(whole_steps(&mut self.power, power, Action::PowerDown, Action::PowerUp));
+        actions.extend(whole_steps(&mut self.movement, movement, Action::MoveLeft, Action::MoveRight));
+        actions
+    }
+
+    // Returns the buttons pressed last frame and remembers this frame's
+    fn previous_pressed(&mut self, index: u32, pressed: &[bool]) -> Vec<bool> {
+        match self.controllers.iter_mut().find(|controller| controller.index == index) {
+            Some(controller) => std::mem::replace(&mut controller.pressed, pressed.to_vec()),
+            None => {
+                self.controllers.push(Controller {
+                    index,
+                    pressed: pressed.to_vec(),
+                });
+                Vec::new()
+            }
+        }
+    }
+
+    // Unplugged gamepads give their player back
+    fn disconnect_missing(&mut self, gamepads: &[GamepadSnapshot]) {
+        let connected = |index: u32| gamepads.iter().any(|gamepad| gamepad.index == index);
+
+        self.controllers.retain(|controller| connected(controller.index));
+        for player in self.players.iter_mut() {
+            if player.is_some_and(|index| !connected(index)) {
+                *player = None;
+            }
+        }
+    }
+}
+
+// Adds `amount` to `carried` and takes out the whole steps it makes
+fn whole_steps(carried: &mut f32, amount: f32, negative: Action, positive: Action) -> Vec<Action> {
+    if amount == 0.0 {
+        // Letting go drops what was left, so the next push starts fresh
+        *carried = 0.0;
+        return Vec::new();
+    }
+
+    *carried += amount;
+    let steps = carried.trunc();
+    *carried -= steps;
+
+    let action = if steps > 0.0 { positive } else { negative };
+    vec![action; steps.abs() as usize]
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{GamepadInput, GamepadSnapshot};
+    use crate::input::Action;
+
+    fn gamepad(index: u32, left_stick_x: f32, pressed: &[usize]) -> GamepadSnapshot {
+        let mut buttons = vec![0.0; 17];
+        for button in pressed {
+            buttons[*button] = 1.0;
+        }
+        GamepadSnapshot {
+            index,
+            axes: vec![left_stick_x, 0.0, 0.0, 0.0],
+            buttons,
+        }
+    }
+
+    #[test]
+    fn gamepads_claim_players_in_order() {
+        let mut input = GamepadInput::new();
+
+        // Nothing pressed yet, so nobody is claimed
+        assert!(input.update(&[gamepad(3, 0.0, &[])], 2, 0, 0.1).is_empty());
+        assert_eq!(None, input.player_gamepad(0));
+
+        // Claiming does not fire
+        assert!(input.update(&[gamepad(3, 0.0, &[0]), gamepad(1, 0.0, &[])], 2, 0, 0.1).is_empty());
+        assert!(input.update(&[gamepad(3, 0.0, &[]), gamepad(1, 0.0, &[9])], 2, 0, 0.1).is_empty());
+        assert_eq!(Some(3), input.player_gamepad(0));
+        assert_eq!(Some(1), input.player_gamepad(1));
+
+        // Only the gamepad of the player whose turn it is counts
+        let actions = input.update(&[gamepad(3, 0.0, &[0]), gamepad(1, 0.0, &[0])], 2, 1, 0.1);
+        assert_eq!(vec![Action::Fire], actions);
+
+        // Unplugging frees the player for another gamepad
+        input.update(&[gamepad(1, 0.0, &[])], 2, 1, 0.1);
+        assert_eq!(None, input.player_gamepad(0));
+    }
+
+    #[test]
+    fn buttons_fire_once_per_press() {
+        let mut input = GamepadInput::new();
+        input.update(&[gamepad(0, 0.0, &[2])], 1, 0, 0.1);
+
+        assert_eq!(vec![Action::Fire], input.update(&[gamepad(0, 0.0, &[0])], 1, 0, 0.1));
+        assert!(input.update(&[gamepad(0, 0.0, &[0])], 1, 0, 0.1).is_empty());
+        assert_eq!(vec![Action::NextWeapon], input.update(&[gamepad(0, 0.0, &[5])], 1, 0, 0.1));
+    }
+
+    #[test]
+    fn analog_input_carries_over_between_frames() {
+        let mut input = GamepadInput::new();
+        input.update(&[gamepad(0, 0.0, &[2])], 1, 0, 0.1);
+
+        // Full left stick turns 60 degrees a second, three steps in a tenth of a second
+        let actions = input.update(&[gamepad(0, -1.0, &[])], 1, 0, 0.1);
+        assert_eq!(vec![Action::AimLeft; 3], actions);
+
+        // 3.75 steps a frame, the quarters add up
+        let steps: Vec<usize> = (0..4)
+            .map(|_| input.update(&[gamepad(0, 1.0, &[])], 1, 0, 0.125).len())
+            .collect();
+        assert_eq!(vec![3, 4, 4, 4], steps);
+
+        // Inside the dead zone
+        assert!(input.update(&[gamepad(0, 0.1, &[])], 1, 0, 1.0).is_empty());
+
+        let mut pad = gamepad(0, 0.0, &[]);
+        pad.buttons[7] = 1.0;
+        assert_eq!(vec![Action::PowerUp; 12], input.update(&[pad], 1, 0, 1.0));
+    }
+
+    #[test]
+    fn carried_steps_stay_with_the_turn() {
+        let mut input = GamepadInput::new();
+        input.update(&[gamepad(0, 0.0, &[2]), gamepad(1, 0.0, &[])], 2, 0, 0.1);
+        input.update(&[gamepad(0, 0.0, &[]), gamepad(1, 0.0, &[2])], 2, 0, 0.1);
+
+        // 3.75 steps, three now and three quarters carried
+        assert_eq!(3, input.update(&[gamepad(0, 1.0, &[]), gamepad(1, 0.0, &[])], 2, 0, 0.125).len());
+
+        // A quarter step more would make one on the old turn, but not on the next
+        let actions = input.update(&[gamepad(0, 0.0, &[]), gamepad(1, 1.0, &[])], 2, 1, 1.0 / 240.0);
+        assert!(actions.is_empty());
+    }
+}
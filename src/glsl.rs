@@ -0,0 +1,232 @@
+/*
+ * A small preprocessor for the GLSL files in `src/shaders`.
+ *
+ * `#include "name.glsl"` pastes in one of the shared files listed in
+ * `INCLUDES`, and feature flags are turned into `#define` lines right
+ * after `#version`, so shaders can test them with `#ifdef`. Every
+ * output line remembers the file and line it came from, which is used
+ * to rewrite the line numbers in compiler logs.
+ */
+
+#[derive(Clone, Copy)]
+pub struct ShaderFile {
+    pub name: &'static str,
+    pub source: &'static str,
+}
+
+// Files that can be pulled into a shader with #include
+const INCLUDES: [ShaderFile; 3] = [
+    ShaderFile {
+        name: "texture_rect.glsl",
+        source: include_str!("shaders/texture_rect.glsl"),
+    },
+    ShaderFile {
+        name: "luminance.glsl",
+        source: include_str!("shaders/luminance.glsl"),
+    },
+    ShaderFile {
+        name: "mask_coverage.glsl",
+        source: include_str!("shaders/mask_coverage.glsl"),
+    },
+];
+
+pub struct PreprocessedShader {
+    pub source: String,
+    // File name and 1-based line of each output line
+    origins: Vec<(&'static str, usize)>,
+}
+
+impl PreprocessedShader {
+    pub fn origin(&self, line: usize) -> Option<(&'static str, usize)> {
+        line.checked_sub(1).and_then(|i| self.origins.get(i)).copied()
+    }
+
+    /*
+     * Rewrites `ERROR: 0:12: ...` lines of a compiler log into
+     * `ERROR: sprite.vert.glsl:7: ...`. Lines in another format are
+     * kept as they are.
+     */
+    pub fn translate_log(&self, log: &str) -> String {
+        log.trim_end_matches('\0')
+            .lines()
+            .map(|line| self.translate_log_line(line))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn translate_log_line(&self, line: &str) -> String {
+        for severity in ["ERROR: ", "WARNING: "] {
+            if let Some(rest) = line.strip_prefix(severity) {
+                let mut parts = rest.splitn(3, ':');
+                if let (Some(_), Some(number), Some(message)) = (parts.next(), parts.next(), parts.next()) {
+                    if let Some((name, line)) = number.trim().parse().ok().and_then(|n| self.origin(n)) {
+                        return format!("{}{}:{}:{}", severity, name, line, message);
+                    }
+                }
+            }
+        }
+
+        line.to_string()
+    }
+}
+
+pub fn preprocess(file: &ShaderFile, defines: &[&str]) -> Result<PreprocessedShader, String> {
+    preprocess_with(file, defines, &INCLUDES)
+}
+
+fn preprocess_with(
+    file: &ShaderFile,
+    defines: &[&str],
+    includes: &[ShaderFile],
+) -> Result<PreprocessedShader, String> {
+    let mut lines = file.source.lines().enumerate();
+
+    // #version has to be the very first line, even blank lines may not precede it
+    let version = loop {
+        match lines.next() {
+            Some((_, line)) if line.trim().is_empty() => continue,
+            Some((i, line)) if line.trim().starts_with("#version") => break (i, line.trim()),
+            Some((i, _)) => return Err(format!("{}:{}: expected #version", file.name, i + 1)),
+            None => return Err(format!("{}: empty shader", file.name)),
+        }
+    };
+
+    let mut output = PreprocessedShader {
+        source: String::new(),
+        origins: Vec::new(),
+    };
+    push_line(&mut output, version.1, file.name, version.0 + 1);
+
+    for define in defines {
+        push_line(&mut output, &format!("#define {}", define), file.name, version.0 + 1);
+    }
+
+    let mut stack = vec![file.name];
+    expand(&mut output, file, lines, includes, &mut stack)?;
+
+    Ok(output)
+}
+
+fn expand<'a>(
+    output: &mut PreprocessedShader,
+    file: &ShaderFile,
+    lines: impl Iterator<Item = (usize, &'a str)>,
+    includes: &[ShaderFile],
+    stack: &mut Vec<&'static str>,
+) -> Result<(), String> {
+    for (i, line) in lines {
+        let directive = line.trim();
+
+        if let Some(argument) = directive.strip_prefix("#include") {
+            let name = argument
+                .trim()
+                .strip_prefix('"')
+                .and_then(|name| name.strip_suffix('"'))
+                .ok_or_else(|| format!("{}:{}: malformed #include", file.name, i + 1))?;
+
+            let included = includes
+                .iter()
+                .find(|include| include.name == name)
+                .ok_or_else(|| format!("{}:{}: unknown include '{}'", file.name, i + 1, name))?;
+
+            if stack.contains(&included.name) {
+                return Err(format!("{}:{}: '{}' includes itself", file.name, i + 1, name));
+            }
+
+            stack.push(included.name);
+            expand(output, included, included.source.lines().enumerate(), includes, stack)?;
+            stack.pop();
+        } else if directive.starts_with("#version") {
+            return Err(format!("{}:{}: #version must start the shader", file.name, i + 1));
+        } else {
+            push_line(output, line, file.name, i + 1);
+        }
+    }
+
+    Ok(())
+}
+
+fn push_line(output: &mut PreprocessedShader, line: &str, name: &'static str, number: usize) {
+    output.source.push_str(line);
+    output.source.push('\n');
+    output.origins.push((name, number));
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{preprocess, preprocess_with, ShaderFile};
+
+    const COMMON: ShaderFile = ShaderFile {
+        name: "common.glsl",
+        source: "float twice(float x) {\n    return 2.0 * x;\n}",
+    };
+
+    const MAIN: ShaderFile = ShaderFile {
+        name: "main.glsl",
+        source: "#version 300 es\nprecision mediump float;\n#include \"common.glsl\"\nvoid main() {}",
+    };
+
+    #[test]
+    fn includes_and_defines_are_expanded() {
+        let shader = preprocess_with(&MAIN, &["MASKED"], &[COMMON]).unwrap();
+
+        assert_eq!(
+            "#version 300 es\n#define MASKED\nprecision mediump float;\nfloat twice(float x) {\n    return 2.0 * x;\n}\nvoid main() {}\n",
+            shader.source
+        );
+        assert_eq!(Some(("main.glsl", 2)), shader.origin(3));
+        assert_eq!(Some(("common.glsl", 2)), shader.origin(5));
+        assert_eq!(Some(("main.glsl", 4)), shader.origin(7));
+        assert_eq!(None, shader.origin(8));
+    }
+
+    #[test]
+    fn compiler_log_points_at_source_files() {
+        let shader = preprocess_with(&MAIN, &[], &[COMMON]).unwrap();
+        let log = "ERROR: 0:4: 'x' : undeclared identifier\nERROR: 1 compilation errors.\0";
+
+        assert_eq!(
+            "ERROR: common.glsl:2: 'x' : undeclared identifier\nERROR: 1 compilation errors.",
+            shader.translate_log(log)
+        );
+    }
+
+    #[test]
+    fn bad_includes_are_reported() {
+        let looping = ShaderFile {
+            name: "loop.glsl",
+            source: "#include \"loop.glsl\"",
+        };
+        let main = ShaderFile {
+            name: "main.glsl",
+            source: "#version 300 es\n#include \"loop.glsl\"",
+        };
+
+        assert_eq!(
+            Err(String::from("main.glsl:3: unknown include 'common.glsl'")),
+            preprocess_with(&MAIN, &[], &[]).map(|shader| shader.source)
+        );
+        assert_eq!(
+            Err(String::from("loop.glsl:1: 'loop.glsl' includes itself")),
+            preprocess_with(&main, &[], &[looping]).map(|shader| shader.source)
+        );
+    }
+
+    #[test]
+    fn game_shaders_preprocess() {
+        for file in [
+            crate::sprite_shader::SPRITE_VERTEX,
+            crate::particle_shader::PARTICLE_VERTEX,
+            crate::sprite_shader::TEXTURED_FRAGMENT,
+            crate::post_process::FULLSCREEN_VERTEX,
+            crate::post_process::BRIGHT_PASS_FRAGMENT,
+            crate::post_process::BLUR_FRAGMENT,
+            crate::post_process::COMPOSITE_FRAGMENT,
+            crate::terrain_shader::TERRAIN_FRAGMENT,
+        ] {
+            let shader = preprocess(&file, &["MASKED"]).unwrap();
+            assert!(shader.source.starts_with("#version 300 es\n"));
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::rc::Rc;
+
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::{
+    camera::Camera,
+    resources::Texture,
+    sprite::Sprite,
+    sprite_renderer::SpriteRenderer,
+    pointer::ScreenRect,
+    text_renderer::{TextAlign, TextRenderer},
+    vector::Vec3,
+};
+
+const DAMAGE_NUMBER_LIFE_SECONDS: f32 = 1.5;
+const DAMAGE_NUMBER_RISE_SPEED: f32 = 40.0;
+
+pub struct DamageNumber {
+    pub amount: u32,
+    pub position: Vec3,
+    pub color: [f32; 4],
+    pub age: f32,
+}
+
+impl DamageNumber {
+    pub fn new(amount: u32, position: Vec3, color: [f32; 4]) -> DamageNumber {
+        DamageNumber {
+            amount,
+            position,
+            color,
+            age: 0.0,
+        }
+    }
+}
+
+// Damage numbers drift upwards and are removed once they have faded out
+pub fn update_damage_numbers(numbers: &mut Vec<DamageNumber>, dt: f32) {
+    numbers.retain_mut(|number| {
+        number.age += dt;
+        number.position += Vec3::new(0.0, -DAMAGE_NUMBER_RISE_SPEED * dt, 0.0);
+        number.age < DAMAGE_NUMBER_LIFE_SECONDS
+    });
+}
+
+// Green at full health, through yellow, to red when almost dead
+pub fn health_color(fraction: f32) -> [f32; 4] {
+    let fraction = fraction.clamp(0.0, 1.0);
+    [
+        (2.0 * (1.0 - fraction)).min(1.0),
+        (2.0 * fraction).min(1.0),
+        0.0,
+        1.0,
+    ]
+}
+
+pub struct Nameplate<'a> {
+    pub name: &'a str,
+    // Top center of the tank in world units
+    pub position: Vec3,
+    pub color: [f32; 4],
+    pub health_fraction: f32,
+}
+
+pub struct AimGauge {
+    // Pivot of the cannon in world units
+    pub pivot: Vec3,
+    pub angle: f32,
+    pub power_fraction: f32,
+    pub color: [f32; 4],
+}
+
+/*
+ * In-canvas interface drawn with the sprite and text renderers.
+ * Nameplates, the aim gauge and damage numbers live in world space,
+ * the wind indicator and the fire button are drawn in screen space.
+ */
+pub struct Hud {
+    white_texture: Rc<Texture>,
+}
+
+impl Hud {
+    pub fn new(white_texture: Rc<Texture>) -> Hud {
+        Hud { white_texture }
+    }
+
+    pub fn render_nameplate(
+        &self,
+        gl: &WebGl2RenderingContext,
+        renderer: &SpriteRenderer,
+        text_renderer: &TextRenderer,
+        nameplate: &Nameplate,
+    ) -> Result<(), JsValue> {
+        let bar_width = 60.0;
+        let bar_height = 8.0;
+        let bar_top_left = nameplate.position - Vec3::new(bar_width / 2.0, 20.0, 0.0);
+
+        let background = self.rectangle(
+            bar_top_left - Vec3::new(1.0, 1.0, 0.0),
+            bar_width + 2.0,
+            bar_height + 2.0,
+            [0.0, 0.0, 0.0, 0.7],
+        )?;
+        renderer.render(gl, &background);
+
+        let fraction = nameplate.health_fraction.clamp(0.0, 1.0);
+        if fraction > 0.0 {
+            let fill = self.rectangle(
+                bar_top_left,
+                bar_width * fraction,
+                bar_height,
+                health_color(fraction),
+            )?;
+            renderer.render(gl, &fill);
+        }
+
+        let scale = 0.5;
+        let (_, text_height) = text_renderer.measure(nameplate.name, scale);
+        text_renderer.render(
+            gl,
+            nameplate.name,
+            bar_top_left + Vec3::new(bar_width / 2.0, -text_height, 0.0),
+            scale,
+            TextAlign::Center,
+            &nameplate.color,
+        );
+
+        Ok(())
+    }
+
+    // Dotted line along the cannon whose length follows the power, plus the angle readout
+    pub fn render_aim_gauge(
+        &self,
+        gl: &WebGl2RenderingContext,
+        renderer: &SpriteRenderer,
+        text_renderer: &TextRenderer,
+        gauge: &AimGauge,
+    ) -> Result<(), JsValue> {
+        let (sin, cos) = (gauge.angle - 90.0).to_radians().sin_cos();
+        let direction = Vec3::new(cos, sin, 0.0);
+
+        let start = 80.0;
+        let max_length = 160.0;
+        let dots = 12;
+        let dot_size = 5.0;
+        let filled = (gauge.power_fraction.clamp(0.0, 1.0) * dots as f32).ceil() as usize;
+
+        for i in 0..dots {
+            let center = gauge.pivot + direction.scaled(start + max_length * i as f32 / dots as f32);
+            let alpha = if i < filled { 0.9 } else { 0.2 };
+            let color = [gauge.color[0], gauge.color[1], gauge.color[2], alpha];
+            let dot = self.rectangle(
+                center - Vec3::new(dot_size / 2.0, dot_size / 2.0, 0.0),
+                dot_size,
+                dot_size,
+                color,
+            )?;
+            renderer.render(gl, &dot);
+        }
+
+        let label_position = gauge.pivot + direction.scaled(start - 25.0) - Vec3::new(0.0, 8.0, 0.0);
+        text_renderer.render(
+            gl,
+            &format!("{}", gauge.angle.round()),
+            label_position,
+            0.5,
+            TextAlign::Center,
+            &[1.0, 1.0, 1.0, 1.0],
+        );
+
+        Ok(())
+    }
+
+    pub fn render_damage_numbers(
+        &self,
+        gl: &WebGl2RenderingContext,
+        text_renderer: &TextRenderer,
+        numbers: &[DamageNumber],
+    ) {
+        for number in numbers {
+            let alpha = 1.0 - number.age / DAMAGE_NUMBER_LIFE_SECONDS;
+            let color = [number.color[0], number.color[1], number.color[2], alpha];
+            text_renderer.render(
+                gl,
+                &format!("-{}", number.amount),
+                number.position,
+                0.75,
+                TextAlign::Center,
+                &color,
+            );
+        }
+    }
+
+    // Arrow at the top center of the screen pointing with the wind, sized by its strength
+    pub fn render_wind(
+        &self,
+        gl: &WebGl2RenderingContext,
+        renderer: &SpriteRenderer,
+        text_renderer: &TextRenderer,
+        camera: &Camera,
+        wind_fraction: f32,
+    ) -> Result<(), JsValue> {
+        let center = Vec3::new(camera.viewport_width() / 2.0, 48.0, 0.0);
+        text_renderer.render(
+            gl,
+            "Wind",
+            center - Vec3::new(0.0, 40.0, 0.0),
+            0.6,
+            TextAlign::Center,
+            &[1.0, 1.0, 1.0, 1.0],
+        );
+
+        let max_length = 100.0;
+        let thickness = 6.0;
+        let length = max_length * wind_fraction.clamp(-1.0, 1.0);
+
+        let track = self.rectangle(
+            center - Vec3::new(max_length, thickness / 2.0, 0.0),
+            2.0 * max_length,
+            thickness,
+            [0.0, 0.0, 0.0, 0.5],
+        )?;
+        renderer.render(gl, &track);
+
+        if length.abs() < 1.0 {
+            return Ok(());
+        }
+
+        let shaft = self.rectangle(
+            Vec3::new(center.x() + length.min(0.0), center.y() - thickness / 2.0, 0.0),
+            length.abs(),
+            thickness,
+            [1.0, 1.0, 1.0, 0.9],
+        )?;
+        renderer.render(gl, &shaft);
+
+        // A square rotated 45 degrees makes the arrow head
+        let head_size = 14.0;
+        let mut head = self.rectangle(
+            Vec3::new(center.x() + length, center.y(), 0.0),
+            head_size,
+            head_size,
+            [1.0, 1.0, 1.0, 0.9],
+        )?;
+        head.local_position = Vec3::new(-head_size / 2.0, -head_size / 2.0, 0.0);
+        head.global_rotation = 45.0;
+        head.update();
+        renderer.render(gl, &head);
+
+        Ok(())
+    }
+
+    // Button for mouse and touch players, the keyboard fires with its own key
+    pub fn render_fire_button(
+        &self,
+        gl: &WebGl2RenderingContext,
+        renderer: &SpriteRenderer,
+        text_renderer: &TextRenderer,
+        button: &ScreenRect,
+        color: [f32; 4],
+    ) -> Result<(), JsValue> {
+        let border = 3.0;
+        let outline = self.rectangle(button.top_left, button.width, button.height, [1.0, 1.0, 1.0, 0.8])?;
+        renderer.render(gl, &outline);
+
+        let fill = self.rectangle(
+            button.top_left + Vec3::new(border, border, 0.0),
+            button.width - 2.0 * border,
+            button.height - 2.0 * border,
+            [color[0], color[1], color[2], 0.7],
+        )?;
+        renderer.render(gl, &fill);
+
+        let scale = 0.8;
+        let (_, text_height) = text_renderer.measure("FIRE", scale);
+        text_renderer.render(
+            gl,
+            "FIRE",
+            button.top_left + Vec3::new(button.width / 2.0, (button.height - text_height) / 2.0, 0.0),
+            scale,
+            TextAlign::Center,
+            &[1.0, 1.0, 1.0, 1.0],
+        );
+
+        Ok(())
+    }
+
+    fn rectangle(&self, top_left: Vec3, width: f32, height: f32, color: [f32; 4]) -> Result<Sprite, JsValue> {
+        Sprite::new_rectangle(self.white_texture.clone(), top_left, width, height, color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{health_color, update_damage_numbers, DamageNumber};
+    use crate::vector::Vec3;
+
+    #[test]
+    fn health_color_fades_from_green_to_red() {
+        assert_eq!([0.0, 1.0, 0.0, 1.0], health_color(1.0));
+        assert_eq!([1.0, 1.0, 0.0, 1.0], health_color(0.5));
+        assert_eq!([1.0, 0.0, 0.0, 1.0], health_color(0.0));
+    }
+
+    #[test]
+    fn damage_numbers_rise_and_expire() {
+        let mut numbers = vec![DamageNumber::new(25, Vec3::new(10.0, 100.0, 0.0), [1.0; 4])];
+
+        update_damage_numbers(&mut numbers, 0.5);
+        assert_eq!(1, numbers.len());
+        assert_eq!(80.0, numbers[0].position.y());
+
+        update_damage_numbers(&mut numbers, 1.0);
+        assert!(numbers.is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+// How much one aim or power action changes the cannon
+pub const AIM_STEP: f32 = 2.0;
+pub const POWER_STEP: u32 = 5;
+
+// Keys held this long start repeating their action
+const REPEAT_DELAY: f32 = 0.3;
+const INITIAL_REPEAT_INTERVAL: f32 = 0.12;
+const MIN_REPEAT_INTERVAL: f32 = 0.03;
+// How much shorter the interval gets for every second the key is held
+const REPEAT_ACCELERATION: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    AimLeft,
+    AimRight,
+    PowerUp,
+    PowerDown,
+    Fire,
+    NextWeapon,
+    MoveLeft,
+    MoveRight,
+    ToggleMute,
+    VolumeUp,
+    VolumeDown,
+}
+
+impl Action {
+    // Adjustments repeat while their key is held, one-off actions do not
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::AimLeft
+                | Action::AimRight
+                | Action::PowerUp
+                | Action::PowerDown
+                | Action::MoveLeft
+                | Action::MoveRight
+                | Action::VolumeUp
+                | Action::VolumeDown
+        )
+    }
+}
+
+/*
+ * Which keys trigger which action, as `KeyboardEvent.key` values.
+ * Letters match regardless of case. Stored as JSON in the form
+ * `{ "Fire": [" "], "AimLeft": ["ArrowLeft", "a"], ... }`.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct KeyBindings {
+    keys: BTreeMap<Action, Vec<String>>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let defaults: [(Action, &[&str]); 11] = [
+            (Action::AimLeft, &["ArrowLeft"]),
+            (Action::AimRight, &["ArrowRight"]),
+            (Action::PowerUp, &["ArrowUp"]),
+            (Action::PowerDown, &["ArrowDown"]),
+            (Action::Fire, &[" "]),
+            (Action::NextWeapon, &["Tab"]),
+            (Action::MoveLeft, &["a"]),
+            (Action::MoveRight, &["d"]),
+            (Action::ToggleMute, &["m"]),
+            (Action::VolumeUp, &["+", "="]),
+            (Action::VolumeDown, &["-"]),
+        ];
+
+        KeyBindings {
+            keys: defaults
+                .iter()
+                .map(|(action, keys)| (*action, keys.iter().map(|key| key.to_string()).collect()))
+                .collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn parse(json: &str) -> Result<KeyBindings, String> {
+        let bindings: KeyBindings =
+            serde_json::from_str(json).map_err(|e| format!("Invalid key bindings: {}", e))?;
+        bindings.validate()?;
+        Ok(bindings)
+    }
+
+    // A key can only trigger one action
+    pub fn validate(&self) -> Result<(), String> {
+        let mut seen: Vec<String> = Vec::new();
+        for key in self.keys.values().flatten() {
+            let key = normalize_key(key);
+            if seen.contains(&key) {
+                return Err(format!("Key '{}' is bound twice", key));
+            }
+            seen.push(key);
+        }
+
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Key bindings always serialize")
+    }
+
+    pub fn action(&self, key: &str) -> Option<Action> {
+        let key = normalize_key(key);
+        self.keys
+            .iter()
+            .find(|(_, keys)| keys.iter().any(|bound| normalize_key(bound) == key))
+            .map(|(action, _)| *action)
+    }
+
+    // The key stops triggering whatever it was bound to before
+    pub fn bind(&mut self, action: Action, key: &str) {
+        let normalized = normalize_key(key);
+        for keys in self.keys.values_mut() {
+            keys.retain(|bound| normalize_key(bound) != normalized);
+        }
+        self.keys.entry(action).or_default().push(key.to_string());
+    }
+}
+
+// "A" and "a" are the same key, named keys such as "ArrowLeft" are kept
+fn normalize_key(key: &str) -> String {
+    if key.chars().count() == 1 {
+        key.to_lowercase()
+    } else {
+        key.to_string()
+    }
+}
+
+struct HeldKey {
+    key: String,
+    action: Action,
+    held_for: f32,
+    // Value of `held_for` at which the action fires again
+    next_repeat: f32,
+}
+
+/*
+ * Turns key events into actions. An action fires once when its key
+ * goes down and, for the ones that repeat, again while the key is
+ * held, faster the longer it is held. The browser's own key repeat is
+ * ignored so every machine repeats at the same pace.
+ */
+pub struct InputState {
+    bindings: KeyBindings,
+    held: Vec<HeldKey>,
+    pending: Vec<Action>,
+}
+
+impl InputState {
+    pub fn new(bindings: KeyBindings) -> InputState {
+        InputState {
+            bindings,
+            held: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    // Returns true when the key is bound, so the page does not act on it as well
+    pub fn key_down(&mut self, key: &str) -> bool {
+        let action = match self.bindings.action(key) {
+            Some(action) => action,
+            None => return false,
+        };
+
+        let key = normalize_key(key);
+        if self.held.iter().any(|held| held.key == key) {
+            return true;
+        }
+
+        self.pending.push(action);
+        self.held.push(HeldKey {
+            key,
+            action,
+            held_for: 0.0,
+            next_repeat: REPEAT_DELAY,
+        });
+        true
+    }
+
+    // For actions coming from elsewhere, such as the on-screen fire button
+    pub fn trigger(&mut self, action: Action) {
+        self.pending.push(action);
+    }
+
+    pub fn key_up(&mut self, key: &str) -> bool {
+        let key = normalize_key(key);
+        self.held.retain(|held| held.key != key);
+        self.bindings.action(&key).is_some()
+    }
+
+    // Keyup never arrives for keys held while the page loses focus
+    pub fn release_all(&mut self) {
+        self.held.clear();
+    }
+
+    // Actions triggered since the last update, in order
+    pub fn update(&mut self, dt: f32) -> Vec<Action> {
+        let mut actions = std::mem::take(&mut self.pending);
+
+        for held in self.held.iter_mut().filter(|held| held.action.repeats()) {
+            held.held_for += dt;
+            while held.next_repeat <= held.held_for {
+                actions.push(held.action);
+                held.next_repeat += repeat_interval(held.next_repeat);
+            }
+        }
+
+        actions
+    }
+}
+
+pub fn repeat_interval(held_for: f32) -> f32 {
+    let accelerated = INITIAL_REPEAT_INTERVAL - REPEAT_ACCELERATION * (held_for - REPEAT_DELAY).max(0.0);
+    accelerated.max(MIN_REPEAT_INTERVAL)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{repeat_interval, Action, InputState, KeyBindings};
+
+    #[test]
+    fn bindings_round_trip_through_json() {
+        let mut bindings = KeyBindings::default();
+        bindings.bind(Action::Fire, "Enter");
+        bindings.bind(Action::AimLeft, "D");
+
+        let parsed = KeyBindings::parse(&bindings.to_json()).unwrap();
+        assert_eq!(bindings, parsed);
+        assert_eq!(Some(Action::Fire), parsed.action("Enter"));
+        assert_eq!(Some(Action::Fire), parsed.action(" "));
+        // Rebinding takes the key away from moving right
+        assert_eq!(Some(Action::AimLeft), parsed.action("d"));
+
+        assert_eq!(
+            Err(String::from("Key 'x' is bound twice")),
+            KeyBindings::parse(r#"{ "Fire": ["x"], "AimLeft": ["X"] }"#)
+        );
+    }
+
+    #[test]
+    fn held_keys_repeat_faster_over_time() {
+        let mut input = InputState::new(KeyBindings::default());
+        assert!(input.key_down("ArrowUp"));
+        assert!(!input.key_down("F5"));
+
+        assert_eq!(vec![Action::PowerUp], input.update(0.1));
+        assert!(input.update(0.1).is_empty());
+
+        // The browser repeating the keydown does not add actions
+        input.key_down("ArrowUp");
+        assert_eq!(vec![Action::PowerUp], input.update(0.15));
+
+        assert!(repeat_interval(2.0) < repeat_interval(0.5));
+        assert_eq!(0.03, repeat_interval(10.0));
+
+        input.key_up("ArrowUp");
+        assert!(input.update(1.0).is_empty());
+    }
+
+    #[test]
+    fn one_off_actions_do_not_repeat() {
+        let mut input = InputState::new(KeyBindings::default());
+        input.key_down(" ");
+        input.key_down("M");
+
+        assert_eq!(vec![Action::Fire, Action::ToggleMute], input.update(0.0));
+        assert!(input.update(5.0).is_empty());
+
+        // Pressed again after being released
+        input.key_up(" ");
+        input.key_down(" ");
+        assert_eq!(vec![Action::Fire], input.update(0.0));
+    }
+}
@@ -1,6 +1,20 @@
+mod animation;
+mod assets;
+mod audio;
+mod atlas;
+mod background;
 mod buffer;
+mod camera;
 mod dom;
+mod economy;
+mod font;
+mod gamepad;
+mod glsl;
+mod hud;
+mod input;
+mod match_flow;
 mod matrix;
+mod scoring;
 mod shader;
 mod texture;
 mod vector;
@@ -8,11 +22,23 @@ mod vao;
 mod sprite;
 mod sprite_shader;
 mod sprite_renderer;
+mod sprite_batch;
 mod particle_shader;
+mod render_queue;
+mod resources;
 mod particle_emitter;
+mod pointer;
+mod post_process;
+mod render_target;
 mod shapes;
 mod terrain;
+mod terrain_renderer;
+mod terrain_shader;
+mod text_renderer;
 mod ui;
+mod minimap;
+mod music;
+mod settings;
 
 pub mod game;
 
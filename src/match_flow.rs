@@ -0,0 +1,254 @@
+use serde::{Deserialize, Serialize};
+
+pub const MIN_PLAYERS: usize = 2;
+// One color for each in the game
+pub const MAX_PLAYERS: usize = 4;
+pub const MAX_ROUNDS: u32 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchPhase {
+    Title,
+    Setup,
+    InRound,
+    BetweenRounds,
+    // Spending what the last round earned
+    Shop,
+    Results,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchEvent {
+    // From the title, or from the results for another match
+    OpenSetup,
+    StartMatch,
+    // With the last player standing, None when nobody survived
+    RoundEnded(Option<usize>),
+    OpenShop,
+    NextRound,
+}
+
+/*
+ * A round is over once at most one tank is left: Some with the tank
+ * still standing, or Some(None) when nobody made it. None while the
+ * round goes on.
+ */
+pub fn round_survivor(alive: &[bool]) -> Option<Option<usize>> {
+    let mut standing = alive.iter().enumerate().filter(|(_, alive)| **alive).map(|(player, _)| player);
+
+    match (standing.next(), standing.next()) {
+        (_, Some(_)) => None,
+        (survivor, None) => Some(survivor),
+    }
+}
+
+/*
+ * Where the match is: title screen, setup, a round being played, the
+ * break between rounds with its shop and the final results. Every
+ * change goes through `transition`, which refuses events that make no
+ * sense in the current phase. A match lasts `rounds` rounds, or ends
+ * early once a player has won more than half of them.
+ */
+#[derive(Debug, Clone)]
+pub struct MatchFlow {
+    phase: MatchPhase,
+    player_count: usize,
+    rounds: u32,
+    // Starts at 1, 0 before the first round
+    round: u32,
+    wins: Vec<u32>,
+    round_winner: Option<usize>,
+}
+
+impl MatchFlow {
+    pub fn new(player_count: usize, rounds: u32) -> MatchFlow {
+        let mut flow = MatchFlow {
+            phase: MatchPhase::Title,
+            player_count: MAX_PLAYERS,
+            rounds: 1,
+            round: 0,
+            wins: Vec::new(),
+            round_winner: None,
+        };
+        flow.set_player_count(player_count);
+        flow.set_rounds(rounds);
+        flow
+    }
+
+    pub fn phase(&self) -> MatchPhase {
+        self.phase
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.player_count
+    }
+
+    pub fn rounds(&self) -> u32 {
+        self.rounds
+    }
+
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    pub fn wins(&self) -> &[u32] {
+        &self.wins
+    }
+
+    pub fn round_winner(&self) -> Option<usize> {
+        self.round_winner
+    }
+
+    // Only changes during setup, clamped to what the game supports
+    pub fn set_player_count(&mut self, player_count: usize) {
+        if matches!(self.phase, MatchPhase::Title | MatchPhase::Setup) {
+            self.player_count = player_count.clamp(MIN_PLAYERS, MAX_PLAYERS);
+        }
+    }
+
+    pub fn set_rounds(&mut self, rounds: u32) {
+        if matches!(self.phase, MatchPhase::Title | MatchPhase::Setup) {
+            self.rounds = rounds.clamp(1, MAX_ROUNDS);
+        }
+    }
+
+    // Players take turns opening the rounds
+    pub fn starting_player(&self) -> usize {
+        self.round.saturating_sub(1) as usize % self.player_count
+    }
+
+    // None while nobody leads on their own
+    pub fn match_winner(&self) -> Option<usize> {
+        let most = *self.wins.iter().max()?;
+        let mut leaders = self.wins.iter().enumerate().filter(|(_, wins)| **wins == most);
+
+        match (leaders.next(), leaders.next()) {
+            (Some((player, _)), None) if most > 0 => Some(player),
+            _ => None,
+        }
+    }
+
+    pub fn transition(&mut self, event: MatchEvent) -> Result<MatchPhase, String> {
+        self.phase = match (self.phase, event) {
+            (MatchPhase::Title, MatchEvent::OpenSetup) | (MatchPhase::Results, MatchEvent::OpenSetup) => {
+                MatchPhase::Setup
+            }
+            (MatchPhase::Setup, MatchEvent::StartMatch) => {
+                self.round = 1;
+                self.wins = vec![0; self.player_count];
+                self.round_winner = None;
+                MatchPhase::InRound
+            }
+            (MatchPhase::InRound, MatchEvent::RoundEnded(winner)) => {
+                self.round_winner = winner.filter(|player| *player < self.player_count);
+                if let Some(player) = self.round_winner {
+                    self.wins[player] += 1;
+                }
+
+                if self.is_decided() {
+                    MatchPhase::Results
+                } else {
+                    MatchPhase::BetweenRounds
+                }
+            }
+            (MatchPhase::BetweenRounds, MatchEvent::OpenShop) => MatchPhase::Shop,
+            (MatchPhase::Shop, MatchEvent::NextRound) => {
+                self.round += 1;
+                self.round_winner = None;
+                MatchPhase::InRound
+            }
+            (phase, event) => return Err(format!("Cannot handle {:?} during {:?}", event, phase)),
+        };
+
+        Ok(self.phase)
+    }
+
+    fn is_decided(&self) -> bool {
+        self.round >= self.rounds || self.wins.iter().any(|wins| 2 * wins > self.rounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{round_survivor, MatchEvent, MatchFlow, MatchPhase};
+
+    #[test]
+    fn a_match_goes_through_every_phase() {
+        let mut flow = MatchFlow::new(3, 3);
+        assert_eq!(MatchPhase::Title, flow.phase());
+
+        assert_eq!(Ok(MatchPhase::Setup), flow.transition(MatchEvent::OpenSetup));
+        assert_eq!(Ok(MatchPhase::InRound), flow.transition(MatchEvent::StartMatch));
+        assert_eq!(1, flow.round());
+        assert_eq!(0, flow.starting_player());
+
+        assert_eq!(Ok(MatchPhase::BetweenRounds), flow.transition(MatchEvent::RoundEnded(Some(2))));
+        assert_eq!(Some(2), flow.round_winner());
+        assert!(flow.transition(MatchEvent::NextRound).is_err());
+        assert_eq!(Ok(MatchPhase::Shop), flow.transition(MatchEvent::OpenShop));
+        assert_eq!(Ok(MatchPhase::InRound), flow.transition(MatchEvent::NextRound));
+        assert_eq!(1, flow.starting_player());
+
+        // A draw counts for nobody
+        assert_eq!(Ok(MatchPhase::BetweenRounds), flow.transition(MatchEvent::RoundEnded(None)));
+        flow.transition(MatchEvent::OpenShop).unwrap();
+        flow.transition(MatchEvent::NextRound).unwrap();
+        assert_eq!(Ok(MatchPhase::Results), flow.transition(MatchEvent::RoundEnded(Some(0))));
+
+        assert_eq!(&[1, 0, 1], flow.wins());
+        assert_eq!(None, flow.match_winner());
+
+        // Another match starts from scratch
+        flow.transition(MatchEvent::OpenSetup).unwrap();
+        flow.transition(MatchEvent::StartMatch).unwrap();
+        assert_eq!(1, flow.round());
+        assert_eq!(&[0, 0, 0], flow.wins());
+    }
+
+    #[test]
+    fn the_match_ends_once_nobody_can_catch_up() {
+        let mut flow = MatchFlow::new(2, 5);
+        flow.transition(MatchEvent::OpenSetup).unwrap();
+        flow.transition(MatchEvent::StartMatch).unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(Ok(MatchPhase::BetweenRounds), flow.transition(MatchEvent::RoundEnded(Some(1))));
+            flow.transition(MatchEvent::OpenShop).unwrap();
+            flow.transition(MatchEvent::NextRound).unwrap();
+        }
+        assert_eq!(Ok(MatchPhase::Results), flow.transition(MatchEvent::RoundEnded(Some(1))));
+        assert_eq!(Some(1), flow.match_winner());
+    }
+
+    #[test]
+    fn setup_is_clamped_and_out_of_order_events_are_refused() {
+        let mut flow = MatchFlow::new(7, 0);
+        assert_eq!(4, flow.player_count());
+        assert_eq!(1, flow.rounds());
+
+        assert!(flow.transition(MatchEvent::StartMatch).is_err());
+        assert!(flow.transition(MatchEvent::RoundEnded(Some(0))).is_err());
+        assert_eq!(MatchPhase::Title, flow.phase());
+
+        flow.transition(MatchEvent::OpenSetup).unwrap();
+        flow.set_player_count(1);
+        flow.set_rounds(3);
+        flow.transition(MatchEvent::StartMatch).unwrap();
+        assert_eq!(2, flow.player_count());
+
+        // Fixed once the match is on
+        flow.set_rounds(5);
+        assert_eq!(3, flow.rounds());
+    }
+
+    #[test]
+    fn the_round_ends_with_one_tank_left() {
+        assert_eq!(None, round_survivor(&[true, false, true]));
+        assert_eq!(Some(Some(2)), round_survivor(&[false, false, true]));
+
+        // The shooter took themselves out along with one of the other two
+        assert_eq!(Some(Some(1)), round_survivor(&[false, true, false]));
+        assert_eq!(Some(None), round_survivor(&[false, false, false]));
+    }
+}
@@ -0,0 +1,111 @@
+use std::rc::Rc;
+
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::{
+    camera::Camera, resources::Texture, shapes::Rectangle, sprite::Sprite, sprite_renderer::SpriteRenderer,
+    vector::Vec3,
+};
+
+pub struct MinimapMarker {
+    pub position: Vec3,
+    pub color: [f32; 4],
+}
+
+/*
+ * Overview of the whole world drawn in the top right corner
+ * of the viewport, in screen pixels.
+ */
+pub struct Minimap {
+    white_texture: Rc<Texture>,
+    world_width: f32,
+    world_height: f32,
+    pub width: f32,
+    pub margin: f32,
+    pub marker_size: f32,
+}
+
+impl Minimap {
+    pub fn new(white_texture: Rc<Texture>, world_width: f32, world_height: f32) -> Minimap {
+        Minimap {
+            white_texture,
+            world_width,
+            world_height,
+            width: 240.0,
+            margin: 10.0,
+            marker_size: 6.0,
+        }
+    }
+
+    pub fn bounds(&self, camera: &Camera) -> Rectangle {
+        let height = self.width * self.world_height / self.world_width;
+        Rectangle {
+            top_left: Vec3::new(camera.viewport_width() - self.width - self.margin, self.margin, 0.0),
+            width: self.width,
+            height,
+        }
+    }
+
+    pub fn world_to_minimap(&self, bounds: &Rectangle, position: Vec3) -> Vec3 {
+        let scale = bounds.width / self.world_width;
+        bounds.top_left + position.scaled(scale)
+    }
+
+    pub fn render(
+        &self,
+        gl: &WebGl2RenderingContext,
+        renderer: &SpriteRenderer,
+        camera: &Camera,
+        terrain: &Sprite,
+        markers: &[MinimapMarker],
+    ) -> Result<(), JsValue> {
+        let bounds = self.bounds(camera);
+
+        renderer.set_projection(gl, &camera.screen_projection());
+
+        let frame = Sprite::new_rectangle(
+            self.white_texture.clone(),
+            bounds.top_left,
+            bounds.width,
+            bounds.height,
+            [0.0, 0.0, 0.0, 0.5],
+        )?;
+        renderer.render(gl, &frame);
+
+        let mut terrain_sprite = Sprite::new_with_mask(terrain.texture(), terrain.mask())?;
+        terrain_sprite.global_position = bounds.top_left;
+        terrain_sprite.global_scale = Vec3::new(bounds.width, bounds.height, 1.0);
+        terrain_sprite.update();
+        renderer.render(gl, &terrain_sprite);
+
+        // Outline the area currently visible through the camera
+        let view_top_left = self.world_to_minimap(&bounds, camera.position);
+        let scale = bounds.width / self.world_width;
+        let view = Sprite::new_rectangle(
+            self.white_texture.clone(),
+            view_top_left,
+            camera.view_width() * scale,
+            camera.view_height() * scale,
+            [1.0, 1.0, 1.0, 0.2],
+        )?;
+        renderer.render(gl, &view);
+
+        for marker in markers {
+            let center = self.world_to_minimap(&bounds, marker.position);
+            let half = self.marker_size / 2.0;
+            let sprite = Sprite::new_rectangle(
+                self.white_texture.clone(),
+                center - Vec3::new(half, half, 0.0),
+                self.marker_size,
+                self.marker_size,
+                marker.color,
+            )?;
+            renderer.render(gl, &sprite);
+        }
+
+        renderer.set_projection(gl, &camera.projection());
+
+        Ok(())
+    }
+}
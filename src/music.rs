@@ -0,0 +1,253 @@
+use crate::{
+    audio::{AudioBackend, AudioEngine, PlayOptions, SoundCategory, VoiceId},
+    settings::MusicSettings,
+};
+
+// Seconds two playlist tracks overlap
+const CROSSFADE: f32 = 4.0;
+// The tension cue takes over a little faster
+const TENSION_FADE: f32 = 2.0;
+// The music makes way for the victory sting
+const STING_FADE: f32 = 0.5;
+
+// Only ever moves forward during a round
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MusicMood {
+    Playlist,
+    Tension,
+    Victory,
+}
+
+/*
+ * Picks the music from the state of the match: the playlist with
+ * crossfades between tracks, a looping tension cue once only two
+ * players are left, and a victory sting when the round is won.
+ * Every new round goes back to the playlist.
+ * Tracks that have not loaded yet are tried again every update, and
+ * the playlist keeps going until the tension cue actually plays.
+ */
+pub struct MusicPlayer {
+    settings: MusicSettings,
+    mood: MusicMood,
+    // Index of the next playlist track
+    next: usize,
+    track: Option<VoiceId>,
+}
+
+impl MusicPlayer {
+    pub fn new(settings: &MusicSettings) -> MusicPlayer {
+        MusicPlayer {
+            settings: settings.clone(),
+            mood: MusicMood::Playlist,
+            next: 0,
+            track: None,
+        }
+    }
+
+    pub fn restart<B: AudioBackend>(&mut self, audio: &mut AudioEngine<B>) {
+        if self.mood == MusicMood::Playlist {
+            return;
+        }
+
+        self.mood = MusicMood::Playlist;
+        if let Some(track) = self.track.take() {
+            audio.fade_out(track, TENSION_FADE);
+        }
+    }
+
+    pub fn update<B: AudioBackend>(&mut self, audio: &mut AudioEngine<B>, players_alive: usize, round_over: bool) {
+        let mood = if round_over {
+            MusicMood::Victory
+        } else if players_alive <= 2 && self.settings.tension.is_some() {
+            MusicMood::Tension
+        } else {
+            MusicMood::Playlist
+        };
+
+        if mood > self.mood {
+            match mood {
+                MusicMood::Victory => self.play_victory(audio),
+                MusicMood::Tension => {
+                    if let Some(tension) = self.play_tension(audio) {
+                        self.mood = MusicMood::Tension;
+                        if let Some(track) = self.track.replace(tension) {
+                            audio.fade_out(track, TENSION_FADE);
+                        }
+                    }
+                }
+                MusicMood::Playlist => {}
+            }
+        }
+
+        match self.mood {
+            MusicMood::Playlist => self.update_playlist(audio),
+            MusicMood::Tension => {
+                if self.track.and_then(|track| audio.remaining(track)).is_none() {
+                    self.track = self.play_tension(audio);
+                }
+            }
+            MusicMood::Victory => {}
+        }
+    }
+
+    fn play_tension<B: AudioBackend>(&self, audio: &mut AudioEngine<B>) -> Option<VoiceId> {
+        let options = PlayOptions {
+            fade_in: TENSION_FADE,
+            looping: true,
+            ..PlayOptions::default()
+        };
+        audio.play(self.settings.tension.as_ref()?, SoundCategory::Music, &options)
+    }
+
+    fn play_victory<B: AudioBackend>(&mut self, audio: &mut AudioEngine<B>) {
+        self.mood = MusicMood::Victory;
+        if let Some(track) = self.track.take() {
+            audio.fade_out(track, STING_FADE);
+        }
+
+        if let Some(victory) = &self.settings.victory {
+            audio.play(victory, SoundCategory::Music, &PlayOptions::default());
+        }
+    }
+
+    fn update_playlist<B: AudioBackend>(&mut self, audio: &mut AudioEngine<B>) {
+        match self.track.and_then(|track| audio.remaining(track)) {
+            Some(remaining) if remaining > CROSSFADE => {}
+            // Close to the end, the next track fades in over it
+            Some(remaining) => {
+                if let Some(track) = self.track.take() {
+                    audio.fade_out(track, remaining);
+                }
+                self.start_next_track(audio, CROSSFADE);
+            }
+            None => self.start_next_track(audio, 0.0),
+        }
+    }
+
+    fn start_next_track<B: AudioBackend>(&mut self, audio: &mut AudioEngine<B>, fade_in: f32) {
+        let playlist = &self.settings.playlist;
+        if playlist.is_empty() {
+            return;
+        }
+
+        let name = &playlist[self.next % playlist.len()];
+        self.next = (self.next + 1) % playlist.len();
+
+        let options = PlayOptions {
+            fade_in,
+            ..PlayOptions::default()
+        };
+        self.track = audio.play(name, SoundCategory::Music, &options);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{MusicMood, MusicPlayer};
+    use crate::audio::{AudioEngine, RecordingBackend};
+    use crate::settings::{AudioSettings, MusicSettings};
+
+    fn settings() -> MusicSettings {
+        MusicSettings {
+            playlist: vec![String::from("first"), String::from("second")],
+            tension: Some(String::from("tension")),
+            victory: Some(String::from("victory")),
+        }
+    }
+
+    #[test]
+    fn playlist_tracks_crossfade() {
+        let backend = RecordingBackend {
+            sound_length: 60.0,
+            ..RecordingBackend::default()
+        };
+        let clock = backend.time.clone();
+        let mut audio = AudioEngine::new(backend, &AudioSettings::default());
+        let mut music = MusicPlayer::new(&settings());
+
+        music.update(&mut audio, 4, false);
+        assert!(audio.backend().is_playing("first"));
+
+        clock.set(50.0);
+        music.update(&mut audio, 4, false);
+        assert!(!audio.backend().is_playing("second"));
+
+        clock.set(57.0);
+        music.update(&mut audio, 4, false);
+        assert!(audio.backend().is_playing("first") && audio.backend().is_playing("second"));
+        assert_eq!(3.0, audio.backend().fades[0].2);
+
+        clock.set(60.0);
+        audio.update(0.0);
+        assert!(!audio.backend().is_playing("first") && audio.backend().is_playing("second"));
+    }
+
+    #[test]
+    fn match_state_drives_the_mood() {
+        let mut audio = AudioEngine::new(RecordingBackend::default(), &AudioSettings::default());
+        let mut music = MusicPlayer::new(&settings());
+
+        music.update(&mut audio, 3, false);
+        assert_eq!(MusicMood::Playlist, music.mood);
+
+        music.update(&mut audio, 2, false);
+        assert_eq!(MusicMood::Tension, music.mood);
+        assert!(audio.backend().is_playing("tension"));
+
+        music.update(&mut audio, 1, true);
+        assert_eq!(MusicMood::Victory, music.mood);
+        assert!(audio.backend().is_playing("victory"));
+        assert_eq!(0.5, audio.backend().fades.last().unwrap().2);
+
+        // The sting plays once and nothing follows it
+        music.update(&mut audio, 1, true);
+        let stings = audio.backend().playing.iter().filter(|(_, name, _)| name == "victory").count();
+        assert_eq!(1, stings);
+
+        // The next round starts over with the playlist
+        music.restart(&mut audio);
+        music.update(&mut audio, 4, false);
+        assert_eq!(MusicMood::Playlist, music.mood);
+        assert!(audio.backend().is_playing("second"));
+    }
+
+    #[test]
+    fn no_tension_cue_keeps_the_playlist() {
+        let mut audio = AudioEngine::new(RecordingBackend::default(), &AudioSettings::default());
+        let mut music = MusicPlayer::new(&MusicSettings {
+            tension: None,
+            ..settings()
+        });
+
+        music.update(&mut audio, 2, false);
+        assert_eq!(MusicMood::Playlist, music.mood);
+        assert!(audio.backend().is_playing("first"));
+    }
+
+    #[test]
+    fn the_playlist_plays_on_until_the_tension_cue_starts() {
+        let backend = RecordingBackend {
+            sound_length: 60.0,
+            ..RecordingBackend::default()
+        };
+        let refusing = backend.refusing.clone();
+        let mut audio = AudioEngine::new(backend, &AudioSettings::default());
+        let mut music = MusicPlayer::new(&settings());
+
+        music.update(&mut audio, 3, false);
+
+        // Still decoding
+        refusing.set(true);
+        music.update(&mut audio, 2, false);
+        assert_eq!(MusicMood::Playlist, music.mood);
+        assert!(audio.backend().is_playing("first"));
+        assert!(audio.backend().fades.is_empty());
+
+        refusing.set(false);
+        music.update(&mut audio, 2, false);
+        assert_eq!(MusicMood::Tension, music.mood);
+        assert!(audio.backend().is_playing("tension"));
+        assert_eq!(2.0, audio.backend().fades[0].2);
+    }
+}
@@ -1,9 +1,14 @@
 use std::rc::Rc;
 
 use wasm_bindgen::JsValue;
-use web_sys::{WebGl2RenderingContext, WebGlTexture};
+use web_sys::WebGl2RenderingContext;
 
-use crate::{particle_shader::ParticleShader, vao::VAO, vector::Vec3};
+use crate::{
+    resources::Texture,
+    shader::Shader,
+    vao::{VertexLayout, VAO},
+    vector::Vec3,
+};
 
 pub struct Particle {
     pub life: f32,
@@ -14,7 +19,9 @@ pub struct Particle {
 
 pub struct ParticleEmitter {
     pub location: Vec3,
-    pub texture: Rc<WebGlTexture>,
+    pub texture: Rc<Texture>,
+    // Sub-rectangle of the texture as [u, v, width, height]
+    pub uv: [f32; 4],
     pub spawn_frequency_hz: f32,
     pub emitter_life_seconds: f32,
     pub initial_particle_life_seconds: f32,
@@ -22,7 +29,7 @@ pub struct ParticleEmitter {
     pub initial_particle_color: [f32; 4],
     pub max_particle_offset: Vec3,
     pub max_particles: usize,
-    shader: Rc<ParticleShader>,
+    shader: Rc<Shader>,
     vao: VAO,
     time: f32,
     last_spawn_time: f32,
@@ -32,12 +39,13 @@ pub struct ParticleEmitter {
 impl ParticleEmitter {
     pub fn new(
         gl: &WebGl2RenderingContext,
-        texture: Rc<WebGlTexture>,
-        shader: Rc<ParticleShader>,
+        texture: Rc<Texture>,
+        shader: Rc<Shader>,
     ) -> Result<ParticleEmitter, JsValue> {
         Ok(ParticleEmitter {
             location: Vec3::new(0., 0., 0.),
             texture,
+            uv: [0., 0., 1., 1.],
             spawn_frequency_hz: 1.,
             emitter_life_seconds: 0.,
             initial_particle_life_seconds: 0.,
@@ -46,7 +54,7 @@ impl ParticleEmitter {
             max_particle_offset: Vec3::new(0., 0., 0.),
             max_particles: 100,
             shader: shader.clone(),
-            vao: VAO::new_with_particle_shader(gl, shader)?,
+            vao: VAO::new(gl, &shader, &VertexLayout::unit_quad())?,
             time: 0.,
             last_spawn_time: 0.,
             particles: Vec::new(),
@@ -97,16 +105,17 @@ impl ParticleEmitter {
             return;
         }
 
-        gl.use_program(Some(&self.shader.program));
+        self.shader.use_program(gl);
 
         gl.active_texture(WebGl2RenderingContext::TEXTURE0);
-        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.texture));
-        gl.uniform1i(Some(&self.shader.texture_sampler_uniform), 0);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.texture.raw()));
+        self.shader.set_i32(gl, "uTextureSampler", 0);
+        self.shader.set_vec4(gl, "uTextureRect", &self.uv);
 
         for particle in &self.particles {
-            gl.uniform1f(Some(&self.shader.scale_uniform), particle.scale);
-            gl.uniform3fv_with_f32_array(Some(&self.shader.offset_uniform), &particle.offset.data);
-            gl.uniform4fv_with_f32_array(Some(&self.shader.color_uniform), &particle.color);
+            self.shader.set_f32(gl, "uScale", particle.scale);
+            self.shader.set_vec3(gl, "uOffset", &particle.offset.data);
+            self.shader.set_vec4(gl, "uColor", &particle.color);
 
             gl.bind_vertex_array(Some(&self.vao.vao));
             {
@@ -119,12 +128,19 @@ impl ParticleEmitter {
         }
     }
 
+    // Rebuilds the vertex array after a context loss, the particles carry on
+    pub fn restore(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        shader: Rc<Shader>,
+    ) -> Result<(), JsValue> {
+        self.vao = VAO::new(gl, &shader, &VertexLayout::unit_quad())?;
+        self.shader = shader;
+        Ok(())
+    }
+
     pub fn reset(&mut self) {
         self.time = 0.;
         self.last_spawn_time = 0.;
     }
-
-    pub fn delete(&self, gl: &WebGl2RenderingContext) {
-        self.vao.delete(gl);
-    }
 }
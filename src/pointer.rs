@@ -0,0 +1,286 @@
+use crate::{
+    input::{Action, AIM_STEP, POWER_STEP},
+    match_flow::MatchPhase,
+    vector::Vec3,
+};
+
+// A press this close to the cannon, in world units, starts aiming
+const GRAB_RADIUS: f32 = 90.0;
+// Cannon power for every world unit the slingshot is pulled back
+const POWER_PER_UNIT: f32 = 2.0;
+// Fire button in viewport pixels, in the bottom right corner
+const FIRE_BUTTON_SIZE: f32 = 96.0;
+const FIRE_BUTTON_MARGIN: f32 = 24.0;
+// Menu screens in viewport pixels, lines of text follow the title
+const MENU_TITLE_HEIGHT: f32 = 40.0;
+const MENU_LINE_HEIGHT: f32 = 36.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenRect {
+    pub top_left: Vec3,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ScreenRect {
+    pub fn contains(&self, point: Vec3) -> bool {
+        let offset = point - self.top_left;
+        (0.0..=self.width).contains(&offset.x()) && (0.0..=self.height).contains(&offset.y())
+    }
+}
+
+pub fn fire_button(viewport_width: f32, viewport_height: f32) -> ScreenRect {
+    ScreenRect {
+        top_left: Vec3::new(
+            viewport_width - FIRE_BUTTON_SIZE - FIRE_BUTTON_MARGIN,
+            viewport_height - FIRE_BUTTON_SIZE - FIRE_BUTTON_MARGIN,
+            0.0,
+        ),
+        width: FIRE_BUTTON_SIZE,
+        height: FIRE_BUTTON_SIZE,
+    }
+}
+
+// Top of the title of a menu screen
+pub fn menu_title_top(viewport_height: f32) -> f32 {
+    viewport_height / 3.0
+}
+
+// The band across the screen taken by a line of a menu screen, from its top edge
+pub fn menu_line(line: usize, viewport_width: f32, viewport_height: f32) -> ScreenRect {
+    ScreenRect {
+        top_left: Vec3::new(
+            0.0,
+            menu_title_top(viewport_height) + MENU_TITLE_HEIGHT + MENU_LINE_HEIGHT * (line + 1) as f32,
+            0.0,
+        ),
+        width: viewport_width,
+        height: MENU_LINE_HEIGHT,
+    }
+}
+
+/*
+ * The actions of a tap on `line` of a menu screen with `lines` lines,
+ * the last of which is the prompt to go on. The setup lines count down
+ * on their left half and up on their right half. The first tap on a
+ * shop entry selects it and the second buys it. Other lines do nothing.
+ */
+pub fn menu_actions(phase: MatchPhase, line: usize, lines: usize, left_half: bool, shop_selected: usize) -> Vec<Action> {
+    let step = |down: Action, up: Action| vec![if left_half { down } else { up }];
+
+    match (phase, line) {
+        _ if line + 1 == lines => vec![Action::Fire],
+        (MatchPhase::Setup, 0) => step(Action::AimLeft, Action::AimRight),
+        (MatchPhase::Setup, 1) => step(Action::PowerDown, Action::PowerUp),
+        // Below the money line
+        (MatchPhase::Shop, line) if line > 0 => {
+            let entry = line - 1;
+            if entry == shop_selected {
+                vec![Action::Fire]
+            } else if entry > shop_selected {
+                vec![Action::AimRight; entry - shop_selected]
+            } else {
+                vec![Action::AimLeft; shop_selected - entry]
+            }
+        }
+        _ => Vec::new(),
+    }
+}
+
+/*
+ * Angle and power of a slingshot pulled from `anchor` to `pointer`:
+ * the shot goes the opposite way of the pull, harder the further it
+ * is pulled. Angles are in degrees clockwise from straight up, like
+ * the cannon's.
+ */
+pub fn slingshot_aim(anchor: Vec3, pointer: Vec3, max_power: u32) -> (f32, u32) {
+    let pull = anchor - pointer;
+    let angle = pull.x().atan2(-pull.y()).to_degrees();
+    let power = (pull.length() * POWER_PER_UNIT).round() as u32;
+    (angle, power.min(max_power))
+}
+
+// The aim and power steps that bring the cannon closest to the target
+pub fn aim_actions(angle: f32, power: u32, target_angle: f32, target_power: u32) -> Vec<Action> {
+    // The short way around
+    let turn = (target_angle - angle + 180.0).rem_euclid(360.0) - 180.0;
+    let aim_steps = (turn / AIM_STEP).round() as i32;
+    let power_steps = ((target_power as f32 - power as f32) / POWER_STEP as f32).round() as i32;
+
+    let aim = if aim_steps > 0 { Action::AimRight } else { Action::AimLeft };
+    let power = if power_steps > 0 { Action::PowerUp } else { Action::PowerDown };
+
+    let mut actions = vec![aim; aim_steps.unsigned_abs() as usize];
+    actions.extend(vec![power; power_steps.unsigned_abs() as usize]);
+    actions
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Press {
+    Fire,
+    Aim,
+    Ignored,
+}
+
+struct Drag {
+    pointer_id: i32,
+    // In world units
+    position: Vec3,
+}
+
+/*
+ * Mouse and touch input. Dragging from the cannon aims it like a
+ * slingshot and the fire button shoots; both end up as the same
+ * actions the keyboard triggers. Only the pointer that started a drag
+ * can move it, so a second finger does not take over the aim.
+ */
+pub struct PointerInput {
+    drag: Option<Drag>,
+}
+
+impl PointerInput {
+    pub fn new() -> PointerInput {
+        PointerInput { drag: None }
+    }
+
+    // `cannon` is the pivot of the cannon that may be aimed, if any
+    pub fn pointer_down(
+        &mut self,
+        pointer_id: i32,
+        screen: Vec3,
+        world: Vec3,
+        fire_button: Option<ScreenRect>,
+        cannon: Option<Vec3>,
+    ) -> Press {
+        if fire_button.is_some_and(|button| button.contains(screen)) {
+            return Press::Fire;
+        }
+
+        match cannon {
+            Some(cannon) if self.drag.is_none() && (world - cannon).length() <= GRAB_RADIUS => {
+                self.drag = Some(Drag {
+                    pointer_id,
+                    position: world,
+                });
+                Press::Aim
+            }
+            _ => Press::Ignored,
+        }
+    }
+
+    pub fn pointer_move(&mut self, pointer_id: i32, world: Vec3) {
+        if let Some(drag) = self.drag.as_mut().filter(|drag| drag.pointer_id == pointer_id) {
+            drag.position = world;
+        }
+    }
+
+    // Also used for cancelled pointers
+    pub fn pointer_up(&mut self, pointer_id: i32) {
+        if self.drag.as_ref().is_some_and(|drag| drag.pointer_id == pointer_id) {
+            self.drag = None;
+        }
+    }
+
+    // Lets go of the slingshot, aiming again takes a new press on the cannon
+    pub fn cancel(&mut self) {
+        self.drag = None;
+    }
+
+    // Where the slingshot is pulled to while aiming
+    pub fn aim_target(&self) -> Option<Vec3> {
+        self.drag.as_ref().map(|drag| drag.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{aim_actions, fire_button, menu_actions, menu_line, slingshot_aim, PointerInput, Press};
+    use crate::input::Action;
+    use crate::match_flow::MatchPhase;
+    use crate::vector::Vec3;
+
+    #[test]
+    fn pulling_back_shoots_the_other_way() {
+        let anchor = Vec3::new(500.0, 300.0, 0.0);
+
+        // Pulled down and to the left, so up and to the right
+        let (angle, power) = slingshot_aim(anchor, anchor + Vec3::new(-60.0, 60.0, 0.0), 400);
+        assert!((angle - 45.0).abs() < 1e-4);
+        assert_eq!(170, power);
+
+        // Pulled straight down, as far as it goes
+        let (angle, power) = slingshot_aim(anchor, anchor + Vec3::new(0.0, 1000.0, 0.0), 400);
+        assert_eq!(0.0, angle);
+        assert_eq!(400, power);
+    }
+
+    #[test]
+    fn aiming_uses_the_keyboard_steps() {
+        assert_eq!(
+            vec![Action::AimRight, Action::AimRight, Action::PowerDown],
+            aim_actions(10.0, 200, 14.0, 195)
+        );
+        // Through 180 degrees rather than all the way around
+        assert_eq!(vec![Action::AimLeft; 3], aim_actions(-178.0, 200, 176.0, 200));
+        assert!(aim_actions(10.0, 200, 10.5, 201).is_empty());
+    }
+
+    #[test]
+    fn presses_start_a_drag_or_fire() {
+        let button = fire_button(800.0, 600.0);
+        let cannon = Vec3::new(100.0, 100.0, 0.0);
+        let mut pointer = PointerInput::new();
+
+        let inside_button = Vec3::new(750.0, 550.0, 0.0);
+        assert_eq!(Press::Fire, pointer.pointer_down(1, inside_button, inside_button, Some(button), Some(cannon)));
+        assert_eq!(Press::Ignored, pointer.pointer_down(1, inside_button, inside_button, None, Some(cannon)));
+
+        let near_cannon = Vec3::new(120.0, 110.0, 0.0);
+        assert_eq!(Press::Aim, pointer.pointer_down(2, near_cannon, near_cannon, Some(button), Some(cannon)));
+        assert_eq!(Press::Ignored, pointer.pointer_down(3, near_cannon, near_cannon, Some(button), Some(cannon)));
+
+        // Another finger cannot move the drag
+        pointer.pointer_move(3, Vec3::new(0.0, 0.0, 0.0));
+        pointer.pointer_move(2, Vec3::new(50.0, 150.0, 0.0));
+        assert_eq!(Some(Vec3::new(50.0, 150.0, 0.0)), pointer.aim_target());
+
+        pointer.pointer_up(2);
+        assert_eq!(None, pointer.aim_target());
+    }
+
+    #[test]
+    fn a_cancelled_drag_stays_let_go() {
+        let cannon = Vec3::new(100.0, 100.0, 0.0);
+        let mut pointer = PointerInput::new();
+        pointer.pointer_down(1, cannon, cannon, None, Some(cannon));
+
+        pointer.cancel();
+        pointer.pointer_move(1, Vec3::new(50.0, 150.0, 0.0));
+        assert_eq!(None, pointer.aim_target());
+
+        // The next turn's cannon needs a press of its own
+        assert_eq!(Press::Aim, pointer.pointer_down(2, cannon, cannon, None, Some(cannon)));
+        pointer.pointer_up(1);
+        assert_eq!(Some(cannon), pointer.aim_target());
+    }
+
+    #[test]
+    fn menu_lines_are_tapped_like_the_keys() {
+        let line = menu_line(1, 800.0, 600.0);
+        assert_eq!(200.0 + 40.0 + 72.0, line.top_left.y());
+        assert!(line.contains(Vec3::new(700.0, 330.0, 0.0)));
+
+        // Players then rounds, then the prompt
+        assert_eq!(vec![Action::AimLeft], menu_actions(MatchPhase::Setup, 0, 3, true, 0));
+        assert_eq!(vec![Action::PowerUp], menu_actions(MatchPhase::Setup, 1, 3, false, 0));
+        assert_eq!(vec![Action::Fire], menu_actions(MatchPhase::Setup, 2, 3, true, 0));
+        assert!(menu_actions(MatchPhase::Results, 0, 3, true, 0).is_empty());
+
+        // Money, two items, done and the prompt
+        assert_eq!(vec![Action::AimRight; 2], menu_actions(MatchPhase::Shop, 3, 5, true, 0));
+        assert_eq!(vec![Action::AimLeft], menu_actions(MatchPhase::Shop, 1, 5, true, 1));
+        assert_eq!(vec![Action::Fire], menu_actions(MatchPhase::Shop, 2, 5, false, 1));
+        assert!(menu_actions(MatchPhase::Shop, 0, 5, false, 1).is_empty());
+    }
+}
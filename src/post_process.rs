@@ -0,0 +1,291 @@
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::{
+    glsl::ShaderFile,
+    render_target::RenderTarget,
+    resources::Texture,
+    settings::GraphicsSettings,
+    shader::Shader,
+    vao::{VertexLayout, VAO},
+};
+
+pub const FULLSCREEN_VERTEX: ShaderFile = ShaderFile {
+    name: "fullscreen.vert.glsl",
+    source: include_str!("shaders/fullscreen.vert.glsl"),
+};
+
+pub const BRIGHT_PASS_FRAGMENT: ShaderFile = ShaderFile {
+    name: "bright_pass.frag.glsl",
+    source: include_str!("shaders/bright_pass.frag.glsl"),
+};
+
+pub const BLUR_FRAGMENT: ShaderFile = ShaderFile {
+    name: "blur.frag.glsl",
+    source: include_str!("shaders/blur.frag.glsl"),
+};
+
+pub const COMPOSITE_FRAGMENT: ShaderFile = ShaderFile {
+    name: "composite.frag.glsl",
+    source: include_str!("shaders/composite.frag.glsl"),
+};
+
+// Only explosions and other bright effects get past the bright pass
+const BLOOM_THRESHOLD: f32 = 0.7;
+const BLOOM_INTENSITY: f32 = 0.8;
+const VIGNETTE_STRENGTH: f32 = 0.45;
+// How fast the flash fades, per second
+const FLASH_DECAY: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGrade {
+    pub saturation: f32,
+    pub contrast: f32,
+    pub tint: [f32; 3],
+}
+
+impl Default for ColorGrade {
+    fn default() -> Self {
+        ColorGrade {
+            saturation: 1.1,
+            contrast: 1.05,
+            tint: [1.0, 0.98, 0.94],
+        }
+    }
+}
+
+// A full screen flash that fades out on its own
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct HitFlash {
+    pub color: [f32; 3],
+    pub strength: f32,
+}
+
+impl HitFlash {
+    // A weaker flash does not cut a stronger one short
+    pub fn trigger(&mut self, color: [f32; 3], strength: f32) {
+        if strength >= self.strength {
+            self.color = color;
+            self.strength = strength.min(1.0);
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.strength = (self.strength - FLASH_DECAY * dt * self.strength.max(0.1)).max(0.0);
+    }
+
+    pub fn uniform(&self) -> [f32; 4] {
+        [self.color[0], self.color[1], self.color[2], self.strength]
+    }
+}
+
+// Defines passed to the composite shader, one per enabled effect
+pub fn composite_defines(settings: &GraphicsSettings) -> Vec<&'static str> {
+    let effects = [
+        (settings.bloom, "BLOOM"),
+        (settings.hit_flash, "HIT_FLASH"),
+        (settings.color_grade, "COLOR_GRADE"),
+        (settings.vignette, "VIGNETTE"),
+    ];
+
+    effects
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, define)| *define)
+        .collect()
+}
+
+struct Bloom {
+    bright_pass: Shader,
+    blur: Shader,
+    // Half resolution, the blur ping-pongs between the two
+    targets: [RenderTarget; 2],
+}
+
+/*
+ * Renders the scene into an off-screen target and then runs it through
+ * a chain of full screen passes on the way to the canvas:
+ *
+ *   scene -> bright pass -> horizontal blur -> vertical blur   (bloom)
+ *   scene + bloom -> hit flash -> color grade -> vignette      (composite)
+ *
+ * Disabled effects are compiled out of the composite shader.
+ */
+pub struct PostProcessor {
+    settings: GraphicsSettings,
+    scene: RenderTarget,
+    bloom: Option<Bloom>,
+    composite: Shader,
+    // Fullscreen passes generate their vertices, the VAO has no buffers
+    vao: VAO,
+    pub flash: HitFlash,
+    pub grade: ColorGrade,
+}
+
+impl PostProcessor {
+    pub fn new(
+        gl: &WebGl2RenderingContext,
+        settings: &GraphicsSettings,
+        width: u32,
+        height: u32,
+    ) -> Result<PostProcessor, JsValue> {
+        let composite = Shader::new(gl, &FULLSCREEN_VERTEX, &COMPOSITE_FRAGMENT, &composite_defines(settings))?;
+        composite.require_uniforms(&["uTextureSampler"])?;
+
+        let bloom = if settings.bloom {
+            let bright_pass = Shader::new(gl, &FULLSCREEN_VERTEX, &BRIGHT_PASS_FRAGMENT, &[])?;
+            bright_pass.require_uniforms(&["uTextureSampler", "uThreshold"])?;
+            let blur = Shader::new(gl, &FULLSCREEN_VERTEX, &BLUR_FRAGMENT, &[])?;
+            blur.require_uniforms(&["uTextureSampler", "uDirection"])?;
+
+            Some(Bloom {
+                bright_pass,
+                blur,
+                targets: [
+                    RenderTarget::new(gl, width / 2, height / 2)?,
+                    RenderTarget::new(gl, width / 2, height / 2)?,
+                ],
+            })
+        } else {
+            None
+        };
+
+        let vao = VAO::new(gl, &composite, &VertexLayout::new())?;
+
+        Ok(PostProcessor {
+            settings: *settings,
+            scene: RenderTarget::new(gl, width, height)?,
+            bloom,
+            composite,
+            vao,
+            flash: HitFlash::default(),
+            grade: ColorGrade::default(),
+        })
+    }
+
+    // Recreates the render targets when the drawing buffer changed size
+    pub fn resize(&mut self, gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<(), JsValue> {
+        if self.scene.width == width.max(1) && self.scene.height == height.max(1) {
+            return Ok(());
+        }
+
+        // All or nothing, the old targets stay when one cannot be made
+        let scene = RenderTarget::new(gl, width, height)?;
+        if let Some(bloom) = &mut self.bloom {
+            bloom.targets = [
+                RenderTarget::new(gl, width / 2, height / 2)?,
+                RenderTarget::new(gl, width / 2, height / 2)?,
+            ];
+        }
+        self.scene = scene;
+
+        Ok(())
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.flash.update(dt);
+    }
+
+    // Everything drawn until `finish` ends up in the scene target
+    pub fn begin(&self, gl: &WebGl2RenderingContext) {
+        self.scene.bind(gl);
+        gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+    }
+
+    // Runs the chain and draws the result to the canvas
+    pub fn finish(&self, gl: &WebGl2RenderingContext) {
+        gl.disable(WebGl2RenderingContext::BLEND);
+        gl.bind_vertex_array(Some(&self.vao.vao));
+
+        if let Some(bloom) = &self.bloom {
+            let [first, second] = &bloom.targets;
+
+            bloom.bright_pass.use_program(gl);
+            bloom.bright_pass.set_f32(gl, "uThreshold", BLOOM_THRESHOLD);
+            self.draw_pass(gl, &bloom.bright_pass, self.scene.texture(), first);
+
+            bloom.blur.use_program(gl);
+            bloom.blur.set_vec2(gl, "uDirection", &[1.0 / first.width as f32, 0.0]);
+            self.draw_pass(gl, &bloom.blur, first.texture(), second);
+            bloom.blur.set_vec2(gl, "uDirection", &[0.0, 1.0 / first.height as f32]);
+            self.draw_pass(gl, &bloom.blur, second.texture(), first);
+        }
+
+        RenderTarget::unbind(gl);
+
+        let composite = &self.composite;
+        composite.use_program(gl);
+
+        if let Some(bloom) = &self.bloom {
+            gl.active_texture(WebGl2RenderingContext::TEXTURE1);
+            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&bloom.targets[0].texture().raw()));
+            composite.set_i32(gl, "uBloomSampler", 1);
+            composite.set_f32(gl, "uBloomIntensity", BLOOM_INTENSITY);
+        }
+
+        if self.settings.hit_flash {
+            composite.set_vec4(gl, "uFlash", &self.flash.uniform());
+        }
+
+        if self.settings.color_grade {
+            composite.set_f32(gl, "uSaturation", self.grade.saturation);
+            composite.set_f32(gl, "uContrast", self.grade.contrast);
+            composite.set_vec3(gl, "uTint", &self.grade.tint);
+        }
+
+        if self.settings.vignette {
+            composite.set_f32(gl, "uVignette", VIGNETTE_STRENGTH);
+        }
+
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.scene.texture().raw()));
+        composite.set_i32(gl, "uTextureSampler", 0);
+        gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+
+        gl.bind_vertex_array(None);
+    }
+
+    // Expects the shader to be in use and the fullscreen VAO bound
+    fn draw_pass(&self, gl: &WebGl2RenderingContext, shader: &Shader, source: &Texture, target: &RenderTarget) {
+        target.bind(gl);
+
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&source.raw()));
+        shader.set_i32(gl, "uTextureSampler", 0);
+        gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{composite_defines, HitFlash};
+    use crate::settings::GraphicsSettings;
+
+    #[test]
+    fn disabled_effects_are_left_out_of_the_composite() {
+        let settings = GraphicsSettings {
+            bloom: false,
+            vignette: false,
+            ..GraphicsSettings::default()
+        };
+
+        assert_eq!(vec!["HIT_FLASH", "COLOR_GRADE"], composite_defines(&settings));
+    }
+
+    #[test]
+    fn hit_flash_fades_out() {
+        let mut flash = HitFlash::default();
+        flash.trigger([1.0, 1.0, 1.0], 0.6);
+        flash.trigger([1.0, 0.0, 0.0], 0.2);
+        assert_eq!([1.0, 1.0, 1.0, 0.6], flash.uniform());
+
+        flash.update(0.05);
+        assert!(flash.strength < 0.6 && flash.strength > 0.0);
+
+        for _ in 0..60 {
+            flash.update(1.0 / 60.0);
+        }
+        assert_eq!(0.0, flash.strength);
+    }
+}
@@ -0,0 +1,165 @@
+use std::ops::RangeBounds;
+
+use web_sys::WebGl2RenderingContext;
+
+use crate::{particle_emitter::ParticleEmitter, sprite::Sprite, sprite_renderer::SpriteRenderer};
+
+// Drawn from first to last
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RenderLayer {
+    Background,
+    Terrain,
+    // Wrecks and other remains lying on the ground
+    Debris,
+    Units,
+    Projectiles,
+    Effects,
+    Hud,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BlendMode {
+    Opaque,
+    Alpha,
+    Additive,
+}
+
+impl BlendMode {
+    pub fn apply(self, gl: &WebGl2RenderingContext) {
+        match self {
+            BlendMode::Opaque => gl.disable(WebGl2RenderingContext::BLEND),
+            BlendMode::Alpha => {
+                gl.enable(WebGl2RenderingContext::BLEND);
+                gl.blend_func(
+                    WebGl2RenderingContext::SRC_ALPHA,
+                    WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+                );
+            }
+            BlendMode::Additive => {
+                gl.enable(WebGl2RenderingContext::BLEND);
+                gl.blend_func(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE);
+            }
+        }
+    }
+}
+
+/*
+ * Where an item is drawn: by layer, then by z order inside the layer.
+ * There is no depth buffer, so opaque items cannot simply be drawn
+ * first; they only go ahead of blended items sharing the same layer
+ * and z order, which also keeps blend state changes down.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderKey {
+    pub layer: RenderLayer,
+    pub z_order: f32,
+    pub blend: BlendMode,
+}
+
+impl RenderKey {
+    pub fn new(layer: RenderLayer, z_order: f32, blend: BlendMode) -> RenderKey {
+        RenderKey {
+            layer,
+            z_order,
+            blend,
+        }
+    }
+}
+
+pub struct RenderQueue<T> {
+    items: Vec<(RenderKey, T)>,
+}
+
+impl<T> RenderQueue<T> {
+    pub fn new() -> RenderQueue<T> {
+        RenderQueue { items: Vec::new() }
+    }
+
+    pub fn push(&mut self, key: RenderKey, item: T) {
+        self.items.push((key, item));
+    }
+
+    // Items with equal keys keep the order they were pushed in
+    pub fn sorted(&mut self) -> &[(RenderKey, T)] {
+        self.items.sort_by(|(a, _), (b, _)| {
+            a.layer
+                .cmp(&b.layer)
+                .then(a.z_order.total_cmp(&b.z_order))
+                .then(a.blend.cmp(&b.blend))
+        });
+        &self.items
+    }
+}
+
+pub enum Drawable<'a> {
+    Sprite(&'a Sprite),
+    Particles(&'a ParticleEmitter),
+    // Anything drawn by its own code, such as the HUD
+    Custom(&'a dyn Fn(&WebGl2RenderingContext)),
+}
+
+impl<'a> RenderQueue<Drawable<'a>> {
+    pub fn push_sprite(&mut self, sprite: &'a Sprite) {
+        self.push(sprite.render_key(), Drawable::Sprite(sprite));
+    }
+
+    // Draws the items on the given layers, so post-processing can run in between
+    pub fn render_layers<R>(&mut self, gl: &WebGl2RenderingContext, sprite_renderer: &SpriteRenderer, layers: R)
+    where
+        R: RangeBounds<RenderLayer>,
+    {
+        let mut blend = None;
+
+        for (key, item) in self.sorted() {
+            if !layers.contains(&key.layer) {
+                continue;
+            }
+
+            if blend != Some(key.blend) {
+                key.blend.apply(gl);
+                blend = Some(key.blend);
+            }
+
+            match item {
+                Drawable::Sprite(sprite) => sprite_renderer.render(gl, sprite),
+                Drawable::Particles(emitter) => emitter.render(gl),
+                Drawable::Custom(draw) => {
+                    draw(gl);
+                    // Custom drawing may have changed the blend state
+                    blend = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{BlendMode, RenderKey, RenderLayer, RenderQueue};
+
+    #[test]
+    fn items_are_sorted_by_layer_then_z_order() {
+        let mut queue = RenderQueue::new();
+        queue.push(RenderKey::new(RenderLayer::Effects, 0.0, BlendMode::Additive), "explosion");
+        queue.push(RenderKey::new(RenderLayer::Units, 1.0, BlendMode::Alpha), "carriage");
+        queue.push(RenderKey::new(RenderLayer::Background, 0.0, BlendMode::Opaque), "sky");
+        queue.push(RenderKey::new(RenderLayer::Units, 0.0, BlendMode::Alpha), "cannon");
+        queue.push(RenderKey::new(RenderLayer::Units, 0.0, BlendMode::Alpha), "tread");
+
+        let order: Vec<&str> = queue.sorted().iter().map(|(_, item)| *item).collect();
+        assert_eq!(vec!["sky", "cannon", "tread", "carriage", "explosion"], order);
+    }
+
+    #[test]
+    fn opaque_items_go_before_blended_ones_at_the_same_depth() {
+        let mut queue = RenderQueue::new();
+        queue.push(RenderKey::new(RenderLayer::Terrain, 0.0, BlendMode::Additive), "glow");
+        queue.push(RenderKey::new(RenderLayer::Terrain, 0.0, BlendMode::Alpha), "grass");
+        queue.push(RenderKey::new(RenderLayer::Terrain, 0.0, BlendMode::Opaque), "rock");
+        queue.push(RenderKey::new(RenderLayer::Background, 0.0, BlendMode::Alpha), "cloud");
+
+        let order: Vec<&str> = queue.sorted().iter().map(|(_, item)| *item).collect();
+        assert_eq!(vec!["cloud", "rock", "grass", "glow"], order);
+    }
+}
@@ -0,0 +1,68 @@
+use std::rc::Rc;
+
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::{
+    resources::{Framebuffer, Texture},
+    texture::create_render_texture,
+};
+
+/*
+ * An off-screen framebuffer with a single color texture attached.
+ * Like the other GPU objects it does not survive a context loss,
+ * its owner creates a new one when the graphics are restored.
+ */
+pub struct RenderTarget {
+    framebuffer: Framebuffer,
+    texture: Rc<Texture>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderTarget {
+    pub fn new(gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<RenderTarget, JsValue> {
+        // Zero sized attachments leave the framebuffer incomplete
+        let (width, height) = (width.max(1), height.max(1));
+        let texture = create_render_texture(gl, width, height)?;
+        let framebuffer = Framebuffer::new(gl)?;
+
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&framebuffer));
+        gl.framebuffer_texture_2d(
+            WebGl2RenderingContext::FRAMEBUFFER,
+            WebGl2RenderingContext::COLOR_ATTACHMENT0,
+            WebGl2RenderingContext::TEXTURE_2D,
+            Some(&texture.raw()),
+            0,
+        );
+        let status = gl.check_framebuffer_status(WebGl2RenderingContext::FRAMEBUFFER);
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+
+        if status != WebGl2RenderingContext::FRAMEBUFFER_COMPLETE {
+            return Err(format!("Framebuffer is incomplete, status {:#x}", status).into());
+        }
+
+        Ok(RenderTarget {
+            framebuffer,
+            texture,
+            width,
+            height,
+        })
+    }
+
+    // Draws go to this target until `unbind` is called
+    pub fn bind(&self, gl: &WebGl2RenderingContext) {
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.framebuffer));
+        gl.viewport(0, 0, self.width as i32, self.height as i32);
+    }
+
+    // Back to the canvas
+    pub fn unbind(gl: &WebGl2RenderingContext) {
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+        gl.viewport(0, 0, gl.drawing_buffer_width(), gl.drawing_buffer_height());
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+}
@@ -0,0 +1,284 @@
+use std::cell::RefCell;
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    WebGl2RenderingContext, WebGlBuffer, WebGlFramebuffer, WebGlProgram, WebGlTexture,
+    WebGlVertexArrayObject,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceKind {
+    Texture,
+    Buffer,
+    VertexArray,
+    Program,
+    Framebuffer,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct ResourceCounts {
+    pub textures: usize,
+    pub buffers: usize,
+    pub vertex_arrays: usize,
+    pub programs: usize,
+    pub framebuffers: usize,
+}
+
+impl ResourceCounts {
+    pub fn total(&self) -> usize {
+        self.textures + self.buffers + self.vertex_arrays + self.programs + self.framebuffers
+    }
+}
+
+// Number of live GPU objects of each kind
+#[derive(Default)]
+pub struct ResourceRegistry {
+    counts: ResourceCounts,
+}
+
+impl ResourceRegistry {
+    pub fn created(&mut self, kind: ResourceKind) {
+        *self.count_mut(kind) += 1;
+    }
+
+    pub fn deleted(&mut self, kind: ResourceKind) {
+        let count = self.count_mut(kind);
+        // A wrapper dropped twice, or an object deleted behind its back
+        debug_assert!(*count > 0, "Deleted more {:?} objects than were created", kind);
+        *count = count.saturating_sub(1);
+    }
+
+    pub fn counts(&self) -> ResourceCounts {
+        self.counts
+    }
+
+    fn count_mut(&mut self, kind: ResourceKind) -> &mut usize {
+        match kind {
+            ResourceKind::Texture => &mut self.counts.textures,
+            ResourceKind::Buffer => &mut self.counts.buffers,
+            ResourceKind::VertexArray => &mut self.counts.vertex_arrays,
+            ResourceKind::Program => &mut self.counts.programs,
+            ResourceKind::Framebuffer => &mut self.counts.framebuffers,
+        }
+    }
+}
+
+// WebAssembly runs on a single thread, so one registry covers every context
+thread_local! {
+    static REGISTRY: RefCell<ResourceRegistry> = RefCell::new(ResourceRegistry::default());
+    static TEXTURES: RefCell<Vec<Weak<Texture>>> = const { RefCell::new(Vec::new()) };
+}
+
+fn register(kind: ResourceKind) {
+    REGISTRY.with(|registry| registry.borrow_mut().created(kind));
+}
+
+fn unregister(kind: ResourceKind) {
+    REGISTRY.with(|registry| registry.borrow_mut().deleted(kind));
+}
+
+pub fn live_resources() -> ResourceCounts {
+    REGISTRY.with(|registry| registry.borrow().counts())
+}
+
+// Counts of GPU objects that have not been deleted yet, to spot leaks from the console
+#[wasm_bindgen]
+pub fn gpu_resource_counts() -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(&live_resources()).map_err(|e| JsValue::from(e.to_string()))?;
+    js_sys::JSON::parse(&json)
+}
+
+/*
+ * Owning wrappers around WebGL buffers, vertex arrays, programs and
+ * framebuffers. Each one keeps a handle to the context that created it
+ * and deletes the object when dropped. They dereference to the raw
+ * web-sys object so they can be passed straight to the context.
+ */
+macro_rules! gpu_resource {
+    ($name:ident, $raw:ty, $kind:expr, $create:ident, $delete:ident, $error:expr) => {
+        pub struct $name {
+            gl: WebGl2RenderingContext,
+            raw: $raw,
+        }
+
+        impl $name {
+            pub fn new(gl: &WebGl2RenderingContext) -> Result<$name, JsValue> {
+                let raw = gl.$create().ok_or_else(|| String::from($error))?;
+                register($kind);
+
+                Ok($name {
+                    gl: gl.clone(),
+                    raw,
+                })
+            }
+        }
+
+        impl Deref for $name {
+            type Target = $raw;
+
+            fn deref(&self) -> &$raw {
+                &self.raw
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                self.gl.$delete(Some(&self.raw));
+                unregister($kind);
+            }
+        }
+    };
+}
+
+gpu_resource!(
+    Buffer,
+    WebGlBuffer,
+    ResourceKind::Buffer,
+    create_buffer,
+    delete_buffer,
+    "Failed to create buffer"
+);
+gpu_resource!(
+    VertexArray,
+    WebGlVertexArrayObject,
+    ResourceKind::VertexArray,
+    create_vertex_array,
+    delete_vertex_array,
+    "Could not create VAO"
+);
+gpu_resource!(
+    Program,
+    WebGlProgram,
+    ResourceKind::Program,
+    create_program,
+    delete_program,
+    "Unable to create new gl program"
+);
+gpu_resource!(
+    Framebuffer,
+    WebGlFramebuffer,
+    ResourceKind::Framebuffer,
+    create_framebuffer,
+    delete_framebuffer,
+    "Could not create framebuffer"
+);
+
+type TextureUpload = Box<dyn Fn(&WebGl2RenderingContext, &WebGlTexture) -> Result<(), JsValue>>;
+
+/*
+ * Textures are shared by sprites all over the game state, so unlike
+ * the other resources they survive a context loss: `restore_textures`
+ * recreates each one in place and replays the upload set by whoever
+ * filled it. Textures without an upload come back empty.
+ */
+pub struct Texture {
+    gl: WebGl2RenderingContext,
+    raw: RefCell<WebGlTexture>,
+    upload: RefCell<Option<TextureUpload>>,
+}
+
+impl Texture {
+    pub fn new(gl: &WebGl2RenderingContext) -> Result<Rc<Texture>, JsValue> {
+        let raw = gl
+            .create_texture()
+            .ok_or_else(|| String::from("Could not make new webgl texture"))?;
+        register(ResourceKind::Texture);
+
+        let texture = Rc::new(Texture {
+            gl: gl.clone(),
+            raw: RefCell::new(raw),
+            upload: RefCell::new(None),
+        });
+
+        TEXTURES.with(|textures| {
+            let mut textures = textures.borrow_mut();
+            textures.retain(|texture| texture.strong_count() > 0);
+            textures.push(Rc::downgrade(&texture));
+        });
+
+        Ok(texture)
+    }
+
+    pub fn raw(&self) -> WebGlTexture {
+        self.raw.borrow().clone()
+    }
+
+    // Called with a freshly created texture object after a context restore
+    pub fn set_upload<F>(&self, upload: F)
+    where
+        F: Fn(&WebGl2RenderingContext, &WebGlTexture) -> Result<(), JsValue> + 'static,
+    {
+        *self.upload.borrow_mut() = Some(Box::new(upload));
+    }
+
+    fn restore(&self) -> Result<(), JsValue> {
+        let raw = self
+            .gl
+            .create_texture()
+            .ok_or_else(|| String::from("Could not make new webgl texture"))?;
+
+        if let Some(upload) = self.upload.borrow().as_ref() {
+            upload(&self.gl, &raw)?;
+        }
+
+        // Objects of the lost context are already gone, there is nothing to delete
+        *self.raw.borrow_mut() = raw;
+        Ok(())
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        self.gl.delete_texture(Some(&self.raw.borrow()));
+        unregister(ResourceKind::Texture);
+    }
+}
+
+// Recreates every live texture after the context has been restored
+pub fn restore_textures() -> Result<(), JsValue> {
+    let textures: Vec<Rc<Texture>> = TEXTURES.with(|textures| {
+        textures
+            .borrow()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect()
+    });
+
+    for texture in textures {
+        texture.restore()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{ResourceKind, ResourceRegistry};
+
+    #[test]
+    fn registry_counts_live_resources() {
+        let mut registry = ResourceRegistry::default();
+
+        registry.created(ResourceKind::Texture);
+        registry.created(ResourceKind::Texture);
+        registry.created(ResourceKind::Program);
+        registry.deleted(ResourceKind::Texture);
+
+        let counts = registry.counts();
+        assert_eq!(1, counts.textures);
+        assert_eq!(1, counts.programs);
+        assert_eq!(0, counts.buffers);
+        assert_eq!(2, counts.total());
+    }
+
+    #[test]
+    #[should_panic(expected = "Deleted more Buffer objects than were created")]
+    fn deleting_more_than_created_is_caught() {
+        let mut registry = ResourceRegistry::default();
+        registry.deleted(ResourceKind::Buffer);
+    }
+}
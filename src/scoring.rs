@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+
+use crate::vector::Vec3;
+
+const KILL_POINTS: i32 = 100;
+// For every point of health taken from an opponent
+const DAMAGE_POINTS: f32 = 1.0;
+// For every point of health a player takes from their own tank
+const SELF_DAMAGE_PENALTY: f32 = 2.0;
+const SURVIVAL_BONUS: i32 = 50;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerStats {
+    pub kills: u32,
+    pub damage_dealt: f32,
+    pub self_damage: f32,
+    pub shots: u32,
+    // Shots that damaged at least one opponent
+    pub hits: u32,
+    // Distance from the cannon to the farthest opponent hit, in world units
+    pub longest_hit: f32,
+    pub rounds_survived: u32,
+}
+
+impl PlayerStats {
+    pub fn score(&self) -> i32 {
+        let damage = self.damage_dealt * DAMAGE_POINTS - self.self_damage * SELF_DAMAGE_PENALTY;
+        self.kills as i32 * KILL_POINTS + damage.round() as i32 + self.rounds_survived as i32 * SURVIVAL_BONUS
+    }
+
+    // From 0 to 1, 0 before the first shot
+    pub fn accuracy(&self) -> f32 {
+        if self.shots == 0 {
+            0.0
+        } else {
+            self.hits as f32 / self.shots as f32
+        }
+    }
+
+    fn add(&mut self, other: &PlayerStats) {
+        self.kills += other.kills;
+        self.damage_dealt += other.damage_dealt;
+        self.self_damage += other.self_damage;
+        self.shots += other.shots;
+        self.hits += other.hits;
+        self.longest_hit = self.longest_hit.max(other.longest_hit);
+        self.rounds_survived += other.rounds_survived;
+    }
+
+    fn report(&self) -> StatsReport {
+        StatsReport {
+            stats: self.clone(),
+            score: self.score(),
+            accuracy: self.accuracy(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatsReport {
+    #[serde(flatten)]
+    pub stats: PlayerStats,
+    pub score: i32,
+    pub accuracy: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerResults {
+    pub player: usize,
+    pub round: StatsReport,
+    // Everything so far this match, this round included
+    pub total: StatsReport,
+}
+
+// Sent to the page when a round ends
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoundResults {
+    pub round: u32,
+    pub match_over: bool,
+    pub players: Vec<PlayerResults>,
+}
+
+struct Shot {
+    player: usize,
+    origin: Vec3,
+    hit: bool,
+}
+
+/*
+ * Keeps the statistics of every player for the current round and the
+ * match as a whole. Damage is credited to whoever fired the rocket
+ * that caused it, and hurting your own tank costs points instead.
+ * The round's numbers are added to the match when the round ends.
+ */
+pub struct Scoreboard {
+    round: Vec<PlayerStats>,
+    total: Vec<PlayerStats>,
+    // The rocket in the air, damage it does is measured from where it was fired
+    shot: Option<Shot>,
+}
+
+impl Scoreboard {
+    pub fn new(player_count: usize) -> Scoreboard {
+        Scoreboard {
+            round: vec![PlayerStats::default(); player_count],
+            total: vec![PlayerStats::default(); player_count],
+            shot: None,
+        }
+    }
+
+    pub fn start_round(&mut self) {
+        self.round = vec![PlayerStats::default(); self.total.len()];
+        self.shot = None;
+    }
+
+    pub fn round(&self, player: usize) -> &PlayerStats {
+        &self.round[player]
+    }
+
+    pub fn total(&self, player: usize) -> &PlayerStats {
+        &self.total[player]
+    }
+
+    pub fn shot_fired(&mut self, player: usize, origin: Vec3) {
+        self.round[player].shots += 1;
+        self.shot = Some(Shot {
+            player,
+            origin,
+            hit: false,
+        });
+    }
+
+    // `amount` is the health actually lost, `position` where the victim stands
+    pub fn damage(&mut self, attacker: Option<usize>, victim: usize, amount: f32, position: Vec3, killed: bool) {
+        let attacker = match attacker {
+            Some(attacker) => attacker,
+            None => return,
+        };
+
+        if attacker == victim {
+            self.round[attacker].self_damage += amount;
+            return;
+        }
+
+        let stats = &mut self.round[attacker];
+        stats.damage_dealt += amount;
+        if killed {
+            stats.kills += 1;
+        }
+
+        if let Some(shot) = self.shot.as_mut().filter(|shot| shot.player == attacker) {
+            // One rocket hitting several tanks is still one hit
+            if !shot.hit {
+                shot.hit = true;
+                stats.hits += 1;
+            }
+            stats.longest_hit = stats.longest_hit.max((position - shot.origin).length());
+        }
+    }
+
+    pub fn end_round(&mut self, survivor: Option<usize>) {
+        if let Some(survivor) = survivor {
+            self.round[survivor].rounds_survived += 1;
+        }
+
+        for (total, round) in self.total.iter_mut().zip(&self.round) {
+            total.add(round);
+        }
+        self.shot = None;
+    }
+
+    pub fn results(&self, round: u32, match_over: bool) -> RoundResults {
+        RoundResults {
+            round,
+            match_over,
+            players: (0..self.total.len())
+                .map(|player| PlayerResults {
+                    player,
+                    round: self.round[player].report(),
+                    total: self.total[player].report(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{PlayerStats, Scoreboard};
+    use crate::vector::Vec3;
+
+    #[test]
+    fn kills_and_damage_are_credited_to_the_shooter() {
+        let mut scoreboard = Scoreboard::new(3);
+        scoreboard.shot_fired(0, Vec3::new(100.0, 500.0, 0.0));
+
+        // Splash damage on two tanks from one rocket
+        scoreboard.damage(Some(0), 1, 50.0, Vec3::new(700.0, 500.0, 0.0), false);
+        scoreboard.damage(Some(0), 2, 20.0, Vec3::new(900.0, 500.0, 0.0), true);
+        // Falling out of the world is nobody's doing
+        scoreboard.damage(None, 1, 50.0, Vec3::new(700.0, 500.0, 0.0), true);
+        scoreboard.end_round(Some(0));
+
+        let stats = scoreboard.total(0);
+        assert_eq!(1, stats.kills);
+        assert_eq!(70.0, stats.damage_dealt);
+        assert_eq!(1, stats.hits);
+        assert_eq!(800.0, stats.longest_hit);
+        assert_eq!(100 + 70 + 50, stats.score());
+        assert_eq!(&PlayerStats::default(), scoreboard.total(1));
+    }
+
+    #[test]
+    fn hurting_yourself_costs_points() {
+        let mut scoreboard = Scoreboard::new(2);
+        scoreboard.shot_fired(1, Vec3::new(0.0, 0.0, 0.0));
+        scoreboard.damage(Some(1), 1, 15.0, Vec3::new(10.0, 0.0, 0.0), false);
+        scoreboard.shot_fired(1, Vec3::new(0.0, 0.0, 0.0));
+        scoreboard.damage(Some(1), 0, 10.0, Vec3::new(300.0, 0.0, 0.0), false);
+        scoreboard.end_round(None);
+
+        let stats = scoreboard.total(1);
+        assert_eq!(10 - 30, stats.score());
+        assert_eq!(0.5, stats.accuracy());
+    }
+
+    #[test]
+    fn rounds_add_up_over_the_match() {
+        let mut scoreboard = Scoreboard::new(2);
+        for distance in [400.0, 250.0] {
+            scoreboard.start_round();
+            scoreboard.shot_fired(0, Vec3::new(0.0, 0.0, 0.0));
+            scoreboard.damage(Some(0), 1, 100.0, Vec3::new(distance, 0.0, 0.0), true);
+            scoreboard.end_round(Some(0));
+        }
+
+        let results = scoreboard.results(2, true);
+        assert_eq!(250.0, results.players[0].round.stats.longest_hit);
+        assert_eq!(400.0, results.players[0].total.stats.longest_hit);
+        assert_eq!(2, results.players[0].total.stats.kills);
+        assert_eq!(2 * (100 + 100 + 50), results.players[0].total.score);
+
+        let json = serde_json::to_value(&results).unwrap();
+        assert_eq!(1.0, json["players"][0]["total"]["accuracy"]);
+        assert_eq!(2, json["players"][0]["total"]["rounds_survived"]);
+    }
+}
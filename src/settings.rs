@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+
+use crate::input::KeyBindings;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchSettings {
+    // Size of the playing field in world units
+    pub world_width: u32,
+    pub world_height: u32,
+    // Starting values of the match setup screen
+    pub players: usize,
+    pub rounds: u32,
+    pub show_minimap: bool,
+    pub graphics: GraphicsSettings,
+    pub map: MapSettings,
+    pub audio: AudioSettings,
+    pub music: MusicSettings,
+    pub key_bindings: KeyBindings,
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        MatchSettings {
+            world_width: 2400,
+            world_height: 1000,
+            players: 4,
+            rounds: 3,
+            show_minimap: true,
+            graphics: GraphicsSettings::default(),
+            map: MapSettings::default(),
+            audio: AudioSettings::default(),
+            music: MusicSettings::default(),
+            key_bindings: KeyBindings::default(),
+        }
+    }
+}
+
+impl MatchSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        // The terrain mask holds four bytes for every world unit and has to fit in a typed array
+        if self.world_width == 0 || self.world_height == 0 {
+            return Err(format!("World size {}x{} is empty", self.world_width, self.world_height));
+        }
+
+        self.world_width
+            .checked_mul(self.world_height)
+            .and_then(|area| area.checked_mul(4))
+            .ok_or_else(|| format!("World size {}x{} is too large", self.world_width, self.world_height))?;
+
+        // Held to the same rules as the bindings saved in local storage
+        self.key_bindings.validate()
+    }
+
+    // The terrain is drawn from one texture the size of the world
+    pub fn fits_texture(&self, max_texture_size: u32) -> Result<(), String> {
+        if self.world_width > max_texture_size || self.world_height > max_texture_size {
+            return Err(format!(
+                "World size {}x{} is larger than the {} pixel textures this device supports",
+                self.world_width, self.world_height, max_texture_size
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// Volumes from 0 to 1, each category is scaled by the master volume
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub ui_volume: f32,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            volume: 0.8,
+            music_volume: 0.6,
+            sfx_volume: 1.0,
+            ui_volume: 0.8,
+            muted: false,
+        }
+    }
+}
+
+// Names of audio assets, missing ones are simply not played
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MusicSettings {
+    // Played in order and repeated
+    pub playlist: Vec<String>,
+    // Replaces the playlist once only two players are left
+    pub tension: Option<String>,
+    // Played once the round is won. Neither cue ships with the game, both default to none
+    pub victory: Option<String>,
+}
+
+impl Default for MusicSettings {
+    fn default() -> Self {
+        MusicSettings {
+            playlist: vec![String::from("music_heroic_demise")],
+            tension: None,
+            victory: None,
+        }
+    }
+}
+
+// Post-processing effects, each one can be switched off on slow machines
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    pub bloom: bool,
+    // Brief flash of the screen on a direct hit
+    pub hit_flash: bool,
+    pub vignette: bool,
+    pub color_grade: bool,
+}
+
+impl GraphicsSettings {
+    pub fn post_processing(&self) -> bool {
+        self.bloom || self.hit_flash || self.vignette || self.color_grade
+    }
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        GraphicsSettings {
+            bloom: true,
+            hit_flash: true,
+            vignette: true,
+            color_grade: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeOfDay {
+    Day,
+    Dusk,
+    Night,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Weather {
+    Clear,
+    Overcast,
+    Storm,
+}
+
+// A range of hills drawn behind the terrain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HillLayer {
+    // 0 stays fixed on screen, 1 scrolls with the terrain
+    pub parallax: f32,
+    // Highest peaks as a fraction of the world height
+    pub height: f32,
+    // How bumpy the ridge line is, from 0 to 1
+    pub roughness: f32,
+    pub color: [f32; 4],
+}
+
+impl Default for HillLayer {
+    fn default() -> Self {
+        HillLayer {
+            parallax: 0.5,
+            height: 0.5,
+            roughness: 0.5,
+            color: [0.4, 0.45, 0.55, 1.0],
+        }
+    }
+}
+
+// Look of the backdrop of a map
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapSettings {
+    pub time_of_day: TimeOfDay,
+    pub weather: Weather,
+    // Clouds drift with the wind
+    pub clouds: bool,
+    // From the farthest to the nearest
+    pub hills: Vec<HillLayer>,
+}
+
+impl Default for MapSettings {
+    fn default() -> Self {
+        MapSettings {
+            time_of_day: TimeOfDay::Day,
+            weather: Weather::Clear,
+            clouds: true,
+            hills: vec![
+                HillLayer {
+                    parallax: 0.25,
+                    height: 0.6,
+                    roughness: 0.3,
+                    color: [0.55, 0.6, 0.7, 1.0],
+                },
+                HillLayer {
+                    parallax: 0.5,
+                    height: 0.45,
+                    roughness: 0.6,
+                    color: [0.3, 0.36, 0.42, 1.0],
+                },
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::MatchSettings;
+    use crate::input::KeyBindings;
+
+    #[test]
+    fn world_size_must_fit_the_terrain_mask() {
+        assert_eq!(Ok(()), MatchSettings::default().validate());
+
+        let empty = MatchSettings {
+            world_height: 0,
+            ..MatchSettings::default()
+        };
+        assert!(empty.validate().is_err());
+
+        let huge = MatchSettings {
+            world_width: 40_000,
+            world_height: 40_000,
+            ..MatchSettings::default()
+        };
+        assert_eq!(Err(String::from("World size 40000x40000 is too large")), huge.validate());
+    }
+
+    #[test]
+    fn world_size_must_fit_a_texture() {
+        let settings = MatchSettings::default();
+        assert_eq!(Ok(()), settings.fits_texture(4096));
+        assert_eq!(Ok(()), settings.fits_texture(2400));
+        assert!(settings.fits_texture(2048).is_err());
+    }
+
+    #[test]
+    fn keys_are_bound_once() {
+        let settings = MatchSettings {
+            key_bindings: KeyBindings::parse(r#"{ "Fire": ["x"] }"#).unwrap(),
+            ..MatchSettings::default()
+        };
+        assert_eq!(Ok(()), settings.validate());
+
+        let settings: MatchSettings =
+            serde_json::from_str(r#"{ "key_bindings": { "Fire": ["x"], "AimLeft": ["X"] } }"#).unwrap();
+        assert_eq!(Err(String::from("Key 'x' is bound twice")), settings.validate());
+    }
+}
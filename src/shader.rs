@@ -1,25 +1,174 @@
+use std::collections::HashMap;
+
 use wasm_bindgen::JsValue;
-use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlShader};
+use web_sys::{WebGl2RenderingContext, WebGlShader, WebGlUniformLocation};
+
+use crate::{
+    glsl::{preprocess, ShaderFile},
+    matrix::Mat4,
+    resources::Program,
+};
+
+pub struct ActiveAttribute {
+    pub location: u32,
+    // GLSL type as a WebGL enum, e.g. FLOAT_VEC2
+    pub kind: u32,
+}
+
+/*
+ * A linked program together with the attributes and uniforms the
+ * driver reports as active. Uniforms are set by name; names the
+ * compiler optimised away are skipped, just like WebGL ignores a
+ * null location.
+ */
+pub struct Shader {
+    pub program: Program,
+    attributes: HashMap<String, ActiveAttribute>,
+    uniforms: HashMap<String, WebGlUniformLocation>,
+}
+
+impl Shader {
+    pub fn new(
+        gl: &WebGl2RenderingContext,
+        vertex_shader: &ShaderFile,
+        fragment_shader: &ShaderFile,
+        defines: &[&str],
+    ) -> Result<Shader, JsValue> {
+        let program = new_shader_program(gl, vertex_shader, fragment_shader, defines)?;
+
+        let mut attributes = HashMap::new();
+        let attribute_count = gl
+            .get_program_parameter(&program, WebGl2RenderingContext::ACTIVE_ATTRIBUTES)
+            .as_f64()
+            .unwrap_or(0.0) as u32;
+
+        for i in 0..attribute_count {
+            let info = gl
+                .get_active_attrib(&program, i)
+                .ok_or_else(|| format!("Could not query attribute {}", i))?;
+            let location = gl.get_attrib_location(&program, &info.name());
+
+            // Built-in inputs such as gl_VertexID have no location
+            if location >= 0 {
+                attributes.insert(
+                    info.name(),
+                    ActiveAttribute {
+                        location: location as u32,
+                        kind: info.type_(),
+                    },
+                );
+            }
+        }
+
+        let mut uniforms = HashMap::new();
+        let uniform_count = gl
+            .get_program_parameter(&program, WebGl2RenderingContext::ACTIVE_UNIFORMS)
+            .as_f64()
+            .unwrap_or(0.0) as u32;
+
+        for i in 0..uniform_count {
+            let info = gl
+                .get_active_uniform(&program, i)
+                .ok_or_else(|| format!("Could not query uniform {}", i))?;
+
+            // Members of uniform blocks have no location of their own
+            if let Some(location) = gl.get_uniform_location(&program, &info.name()) {
+                uniforms.insert(String::from(uniform_base_name(&info.name())), location);
+            }
+        }
+
+        Ok(Shader {
+            program,
+            attributes,
+            uniforms,
+        })
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&ActiveAttribute> {
+        self.attributes.get(name)
+    }
+
+    // Fails with the first missing name, to catch typos when a shader is built
+    pub fn require_uniforms(&self, names: &[&str]) -> Result<(), JsValue> {
+        match names.iter().find(|name| !self.uniforms.contains_key(**name)) {
+            Some(name) => Err(format!("Shader has no active uniform '{}'", name).into()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn use_program(&self, gl: &WebGl2RenderingContext) {
+        gl.use_program(Some(&self.program));
+    }
+
+    // The setters expect the program to be in use
+
+    pub fn set_mat4(&self, gl: &WebGl2RenderingContext, name: &str, value: &Mat4) {
+        gl.uniform_matrix4fv_with_f32_array(self.location(name), false, value.data());
+    }
+
+    pub fn set_vec4(&self, gl: &WebGl2RenderingContext, name: &str, value: &[f32; 4]) {
+        gl.uniform4fv_with_f32_array(self.location(name), value);
+    }
+
+    pub fn set_vec3(&self, gl: &WebGl2RenderingContext, name: &str, value: &[f32; 3]) {
+        gl.uniform3fv_with_f32_array(self.location(name), value);
+    }
+
+    pub fn set_vec2(&self, gl: &WebGl2RenderingContext, name: &str, value: &[f32; 2]) {
+        gl.uniform2fv_with_f32_array(self.location(name), value);
+    }
+
+    pub fn set_f32(&self, gl: &WebGl2RenderingContext, name: &str, value: f32) {
+        gl.uniform1f(self.location(name), value);
+    }
 
+    // Also used to bind samplers to texture units
+    pub fn set_i32(&self, gl: &WebGl2RenderingContext, name: &str, value: i32) {
+        gl.uniform1i(self.location(name), value);
+    }
+
+    fn location(&self, name: &str) -> Option<&WebGlUniformLocation> {
+        self.uniforms.get(name)
+    }
+}
+
+// Arrays are reported as `name[0]`, they are looked up by their plain name
+pub fn uniform_base_name(name: &str) -> &str {
+    name.strip_suffix("[0]").unwrap_or(name)
+}
+
+// Number of floats in an attribute of the given GLSL type
+pub fn attribute_components(kind: u32) -> Option<i32> {
+    match kind {
+        WebGl2RenderingContext::FLOAT => Some(1),
+        WebGl2RenderingContext::FLOAT_VEC2 => Some(2),
+        WebGl2RenderingContext::FLOAT_VEC3 => Some(3),
+        WebGl2RenderingContext::FLOAT_VEC4 => Some(4),
+        _ => None,
+    }
+}
 
 pub fn new_shader_program(
     gl: &WebGl2RenderingContext,
-    vertex_shader_src: &str,
-    fragment_shader_src: &str,
-) -> Result<WebGlProgram, JsValue> {
+    vertex_shader: &ShaderFile,
+    fragment_shader: &ShaderFile,
+    defines: &[&str],
+) -> Result<Program, JsValue> {
     let vertex_shader = compile_shader(
-        &gl,
+        gl,
         WebGl2RenderingContext::VERTEX_SHADER,
-        vertex_shader_src,
+        vertex_shader,
+        defines,
     )?;
 
     let fragment_shader = compile_shader(
-        &gl,
+        gl,
         WebGl2RenderingContext::FRAGMENT_SHADER,
-        fragment_shader_src,
+        fragment_shader,
+        defines,
     )?;
 
-    let program = link_program(&gl, &vertex_shader, &fragment_shader)?;
+    let program = link_program(gl, &vertex_shader, &fragment_shader)?;
 
     Ok(program)
 }
@@ -27,12 +176,15 @@ pub fn new_shader_program(
 fn compile_shader(
     gl: &WebGl2RenderingContext,
     shader_type: u32,
-    source: &str,
+    file: &ShaderFile,
+    defines: &[&str],
 ) -> Result<WebGlShader, String> {
+    let source = preprocess(file, defines)?;
+
     let shader = gl
         .create_shader(shader_type)
         .ok_or_else(|| String::from("Unable to compile shader"))?;
-    gl.shader_source(&shader, source);
+    gl.shader_source(&shader, &source.source);
     gl.compile_shader(&shader);
 
     if gl
@@ -42,9 +194,11 @@ fn compile_shader(
     {
         Ok(shader)
     } else {
-        Err(gl
+        let log = gl
             .get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unknown error creating shader")))
+            .unwrap_or_else(|| String::from("Unknown error creating shader"));
+        gl.delete_shader(Some(&shader));
+        Err(format!("Could not compile {}:\n{}", file.name, source.translate_log(&log)))
     }
 }
 
@@ -52,10 +206,8 @@ fn link_program(
     gl: &WebGl2RenderingContext,
     vs: &WebGlShader,
     fs: &WebGlShader,
-) -> Result<WebGlProgram, String> {
-    let program = gl
-        .create_program()
-        .ok_or_else(|| String::from("Unable to create new gl program"))?;
+) -> Result<Program, JsValue> {
+    let program = Program::new(gl)?;
 
     gl.attach_shader(&program, vs);
     gl.attach_shader(&program, fs);
@@ -76,6 +228,27 @@ fn link_program(
     } else {
         Err(gl
             .get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error creating gl program")))
+            .unwrap_or_else(|| String::from("Unknown error creating gl program"))
+            .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{attribute_components, uniform_base_name};
+    use web_sys::WebGl2RenderingContext;
+
+    #[test]
+    fn array_uniforms_use_their_base_name() {
+        assert_eq!("uLights", uniform_base_name("uLights[0]"));
+        assert_eq!("uColor", uniform_base_name("uColor"));
+    }
+
+    #[test]
+    fn attribute_component_counts() {
+        assert_eq!(Some(2), attribute_components(WebGl2RenderingContext::FLOAT_VEC2));
+        assert_eq!(Some(4), attribute_components(WebGl2RenderingContext::FLOAT_VEC4));
+        assert_eq!(None, attribute_components(WebGl2RenderingContext::FLOAT_MAT4));
     }
 }
@@ -0,0 +1,24 @@
+#version 300 es
+
+precision mediump float;
+
+in highp vec2 vTextureCoord;
+
+uniform sampler2D uTextureSampler;
+// One texel along the blur axis
+uniform vec2 uDirection;
+
+out vec4 fragColor;
+
+// 9 tap gaussian folded into 5 linearly filtered samples
+const float OFFSETS[3] = float[](0.0, 1.3846153846, 3.2307692308);
+const float WEIGHTS[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+
+void main(void) {
+    vec3 color = texture(uTextureSampler, vTextureCoord).rgb * WEIGHTS[0];
+    for (int i = 1; i < 3; i++) {
+        color += texture(uTextureSampler, vTextureCoord + uDirection * OFFSETS[i]).rgb * WEIGHTS[i];
+        color += texture(uTextureSampler, vTextureCoord - uDirection * OFFSETS[i]).rgb * WEIGHTS[i];
+    }
+    fragColor = vec4(color, 1.0);
+}
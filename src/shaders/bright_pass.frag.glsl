@@ -0,0 +1,19 @@
+#version 300 es
+
+precision mediump float;
+
+in highp vec2 vTextureCoord;
+
+uniform sampler2D uTextureSampler;
+// Pixels darker than this do not bloom
+uniform float uThreshold;
+
+out vec4 fragColor;
+
+#include "luminance.glsl"
+
+void main(void) {
+    vec3 color = texture(uTextureSampler, vTextureCoord).rgb;
+    float weight = smoothstep(uThreshold, 1.0, luminance(color));
+    fragColor = vec4(color * weight, 1.0);
+}
@@ -0,0 +1,56 @@
+#version 300 es
+
+precision mediump float;
+
+in highp vec2 vTextureCoord;
+
+uniform sampler2D uTextureSampler;
+
+#ifdef BLOOM
+uniform sampler2D uBloomSampler;
+uniform float uBloomIntensity;
+#endif
+
+#ifdef HIT_FLASH
+// rgb is the flash color, a its current strength
+uniform vec4 uFlash;
+#endif
+
+#ifdef COLOR_GRADE
+uniform float uSaturation;
+uniform float uContrast;
+uniform vec3 uTint;
+#endif
+
+#ifdef VIGNETTE
+uniform float uVignette;
+#endif
+
+out vec4 fragColor;
+
+#include "luminance.glsl"
+
+void main(void) {
+    vec3 color = texture(uTextureSampler, vTextureCoord).rgb;
+
+#ifdef BLOOM
+    color += texture(uBloomSampler, vTextureCoord).rgb * uBloomIntensity;
+#endif
+
+#ifdef HIT_FLASH
+    color = mix(color, uFlash.rgb, uFlash.a);
+#endif
+
+#ifdef COLOR_GRADE
+    color = mix(vec3(luminance(color)), color, uSaturation);
+    color = (color - 0.5) * uContrast + 0.5;
+    color *= uTint;
+#endif
+
+#ifdef VIGNETTE
+    vec2 centered = vTextureCoord - 0.5;
+    color *= 1.0 - uVignette * smoothstep(0.3, 0.8, length(centered));
+#endif
+
+    fragColor = vec4(clamp(color, 0.0, 1.0), 1.0);
+}
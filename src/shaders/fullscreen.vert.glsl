@@ -0,0 +1,10 @@
+#version 300 es
+
+out highp vec2 vTextureCoord;
+
+// A single triangle covering the screen, drawn without any vertex buffers
+void main(void) {
+    vec2 position = vec2(float((gl_VertexID & 1) << 2) - 1.0, float((gl_VertexID & 2) << 1) - 1.0);
+    vTextureCoord = position * 0.5 + 0.5;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
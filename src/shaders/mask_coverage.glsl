@@ -0,0 +1,9 @@
+/*
+ * Masks are read as a distance to their edge centered on 0.5: the
+ * terrain mask stores a signed distance field, plain masks are 0 or 1.
+ * The transition is about one screen pixel wide at any zoom level.
+ */
+float maskCoverage(float value) {
+    float width = max(0.75 * fwidth(value), 1.0 / 255.0);
+    return smoothstep(0.5 - width, 0.5 + width, value);
+}
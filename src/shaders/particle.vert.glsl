@@ -0,0 +1,18 @@
+#version 300 es
+
+in vec4 aVertexPosition;
+in vec2 aTextureCoord;
+
+uniform mat4 uProjectionMatrix;
+uniform float uScale;
+uniform vec3 uOffset;
+uniform vec4 uTextureRect;
+
+out highp vec2 vTextureCoord;
+
+#include "texture_rect.glsl"
+
+void main(void) {
+    gl_Position = uProjectionMatrix * vec4((aVertexPosition.xy * uScale) + uOffset.xy, 0.0, 1.0);
+    vTextureCoord = textureRect(uTextureRect, aTextureCoord);
+}
@@ -0,0 +1,19 @@
+#version 300 es
+
+in vec4 aVertexPosition;
+in vec2 aTextureCoord;
+
+uniform mat4 uModelMatrix;
+uniform mat4 uProjectionMatrix;
+uniform vec4 uTextureRect;
+
+out highp vec2 vTextureCoord;
+out highp vec2 vMaskCoord;
+
+#include "texture_rect.glsl"
+
+void main(void) {
+    gl_Position = uProjectionMatrix * uModelMatrix * aVertexPosition;
+    vTextureCoord = textureRect(uTextureRect, aTextureCoord);
+    vMaskCoord = aTextureCoord;
+}
@@ -0,0 +1,58 @@
+#version 300 es
+
+precision mediump float;
+
+in highp vec2 vTextureCoord;
+in highp vec2 vMaskCoord;
+
+uniform sampler2D uTextureSampler;
+// r and a: distance to the surface, g: depth below the surface, b: scorch, see terrain.rs
+uniform sampler2D uMaskSampler;
+uniform sampler2D uSurfaceSampler;
+uniform vec4 uColor;
+
+uniform vec2 uWorldSize;
+// x: width of one tile of the surface texture in world units,
+// y: how many times the texture fits into the depth ramp
+uniform vec2 uSurfaceScale;
+// Points from the ground towards the light, y grows downwards
+uniform vec3 uLightDirection;
+uniform float uAmbient;
+
+out vec4 fragColor;
+
+#include "mask_coverage.glsl"
+
+float depthAt(vec2 offset) {
+    return texture(uMaskSampler, vMaskCoord + offset / uWorldSize).g;
+}
+
+void main(void) {
+    vec4 mask = texture(uMaskSampler, vMaskCoord);
+    float coverage = maskCoverage(mask.a);
+    if (coverage == 0.0) {
+        discard;
+    }
+
+    float depth = mask.g;
+    float scorch = mask.b;
+    vec2 world = vMaskCoord * uWorldSize;
+
+    vec3 color = texture(uTextureSampler, vTextureCoord).rgb;
+
+    // Grass along the top of the ground
+    vec4 surface = texture(uSurfaceSampler, vec2(world.x / uSurfaceScale.x, depth * uSurfaceScale.y));
+    color = mix(color, surface.rgb, surface.a);
+
+    // Burnt ground around craters, strongest right at the surface
+    color *= 1.0 - 0.7 * scorch * (1.0 - smoothstep(0.0, 0.8, depth));
+
+    // The depth ramp slopes away from the surface, its gradient gives the normal
+    vec2 gradient = vec2(depthAt(vec2(3.0, 0.0)) - depthAt(vec2(-3.0, 0.0)),
+                         depthAt(vec2(0.0, 3.0)) - depthAt(vec2(0.0, -3.0)));
+    vec3 normal = normalize(vec3(-gradient * 4.0, 1.0));
+    float diffuse = max(dot(normal, normalize(uLightDirection)), 0.0);
+    color *= uAmbient + (1.0 - uAmbient) * diffuse;
+
+    fragColor = vec4(color, coverage) * uColor;
+}
@@ -0,0 +1,5 @@
+// Maps a 0..1 coordinate into a sub-rectangle of a texture.
+// xy is the top left of the sub-rectangle, zw its size.
+highp vec2 textureRect(vec4 rect, vec2 coord) {
+    return rect.xy + coord * rect.zw;
+}
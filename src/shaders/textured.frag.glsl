@@ -0,0 +1,28 @@
+#version 300 es
+
+precision mediump float;
+
+in highp vec2 vTextureCoord;
+
+uniform sampler2D uTextureSampler;
+uniform vec4 uColor;
+
+// Sprites are cut out by a mask covering the whole quad, particles are not
+#ifdef MASKED
+in highp vec2 vMaskCoord;
+
+uniform sampler2D uMaskSampler;
+
+#include "mask_coverage.glsl"
+#endif
+
+out vec4 fragColor;
+
+void main(void) {
+    vec4 color = texture(uTextureSampler, vTextureCoord) * uColor;
+#ifdef MASKED
+    // Only alpha counts, the terrain mask keeps other data in its color
+    color.a *= maskCoverage(texture(uMaskSampler, vMaskCoord).a);
+#endif
+    fragColor = color;
+}
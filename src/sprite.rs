@@ -1,56 +1,86 @@
 use std::rc::Rc;
 
 use wasm_bindgen::JsValue;
-use web_sys::{WebGlTexture, WebGl2RenderingContext};
+use web_sys::WebGl2RenderingContext;
 
-use crate::{matrix::Mat4, vector::Vec3, texture::create_rgba_texture_from_u8_array};
+use crate::{atlas::TextureAtlas, matrix::Mat4, render_queue::{BlendMode, RenderKey, RenderLayer}, resources::Texture, vector::Vec3, texture::{create_rgba_texture_from_u8_array, TextureOptions}};
 
 pub struct Sprite {
-    texture: Rc<WebGlTexture>,
-    mask: Rc<WebGlTexture>,
+    texture: Rc<Texture>,
+    mask: Rc<Texture>,
     pub color: [f32; 4],
+    // Sub-rectangle of the texture as [u, v, width, height]
+    pub uv: [f32; 4],
     pub local_position: Vec3,
     pub global_scale: Vec3,
     pub global_position: Vec3,
     pub global_rotation: f32,
+    // Draw order, see RenderQueue
+    pub layer: RenderLayer,
+    pub z_order: f32,
+    pub blend: BlendMode,
     model: Mat4
 }
 
 impl Sprite {
-    pub fn new_with_mask(texture: Rc<WebGlTexture>, mask: Rc<WebGlTexture>) -> Result<Sprite, JsValue> {
+    pub fn new_with_mask(texture: Rc<Texture>, mask: Rc<Texture>) -> Result<Sprite, JsValue> {
         Ok(Sprite {
             texture,
             mask,
             color: [1.0, 1.0, 1.0, 1.0],
+            uv: [0.0, 0.0, 1.0, 1.0],
             local_position: Vec3::new(0.0, 0.0, 0.0),
             global_scale: Vec3::new(1.0, 1.0, 1.0),
             global_position: Vec3::new(0.0, 0.0, 0.0),
             global_rotation: 0.0,
+            layer: RenderLayer::Units,
+            z_order: 0.0,
+            blend: BlendMode::Alpha,
             model: Mat4::identity()
         })
     }
 
-    pub fn new_with_color(gl: &WebGl2RenderingContext, texture: Rc<WebGlTexture>, color: [f32; 4]) -> Result<Sprite, JsValue> {
+    pub fn new_with_color(gl: &WebGl2RenderingContext, texture: Rc<Texture>, color: [f32; 4]) -> Result<Sprite, JsValue> {
         let mut sprite = Sprite::new(gl, texture)?;
         sprite.color = color;
         Ok(sprite)
     }
 
-    pub fn new(gl: &WebGl2RenderingContext, texture: Rc<WebGlTexture>) -> Result<Sprite, JsValue> {
+    pub fn new_from_atlas(gl: &WebGl2RenderingContext, atlas: &TextureAtlas, frame: &str, color: [f32; 4]) -> Result<Sprite, JsValue> {
+        let mut sprite = Sprite::new_with_color(gl, atlas.texture(), color)?;
+        sprite.uv = atlas.uv(frame)?;
+        Ok(sprite)
+    }
+
+    pub fn new(gl: &WebGl2RenderingContext, texture: Rc<Texture>) -> Result<Sprite, JsValue> {
         let mask_array = [255, 255, 255, 255];
-        let mask = create_rgba_texture_from_u8_array(&gl, 1, 1, &mask_array)?;
+        let mask = create_rgba_texture_from_u8_array(gl, 1, 1, &mask_array, &TextureOptions::crisp())?;
         Ok(Sprite {
             texture,
             mask,
             color: [1.0, 1.0, 1.0, 1.0],
+            uv: [0.0, 0.0, 1.0, 1.0],
             local_position: Vec3::new(0.0, 0.0, 0.0),
             global_scale: Vec3::new(1.0, 1.0, 1.0),
             global_position: Vec3::new(0.0, 0.0, 0.0),
             global_rotation: 0.0,
+            layer: RenderLayer::Units,
+            z_order: 0.0,
+            blend: BlendMode::Alpha,
             model: Mat4::identity()
         })
     }
 
+    // Flat colored rectangle, `white` is expected to be a 1x1 white texture
+    pub fn new_rectangle(white: Rc<Texture>, top_left: Vec3, width: f32, height: f32, color: [f32; 4]) -> Result<Sprite, JsValue> {
+        let mut sprite = Sprite::new_with_mask(white.clone(), white)?;
+        sprite.color = color;
+        sprite.global_position = top_left;
+        sprite.global_scale = Vec3::new(width, height, 1.0);
+        sprite.update();
+        Ok(sprite)
+    }
+
     pub fn update(&mut self) {
         self.model =
             // Scale to the image size
@@ -63,19 +93,23 @@ impl Sprite {
             * Mat4::translation(self.global_position.x(), self.global_position.y(), self.global_position.z());
     }
 
-    pub fn texture(&self) -> Rc<WebGlTexture> {
+    pub fn texture(&self) -> Rc<Texture> {
         self.texture.clone()
     }
 
-    pub fn mask(&self) -> Rc<WebGlTexture> {
+    pub fn mask(&self) -> Rc<Texture> {
         self.mask.clone()
     }
 
-    pub fn set_mask(&mut self, mask: Rc<WebGlTexture>) {
+    pub fn set_mask(&mut self, mask: Rc<Texture>) {
         self.mask = mask;
     }
 
     pub fn model(&self) -> &Mat4 {
         &self.model
     }
+
+    pub fn render_key(&self) -> RenderKey {
+        RenderKey::new(self.layer, self.z_order, self.blend)
+    }
 }
@@ -0,0 +1,109 @@
+use std::rc::Rc;
+
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::{
+    matrix::Mat4,
+    resources::Texture,
+    shader::Shader,
+    vao::{VertexData, VertexLayout, VAO},
+    vector::Vec3,
+};
+
+/*
+ * A textured rectangle in world coordinates.
+ * `uv` is the sub-rectangle of the texture as [u, v, width, height],
+ * with the origin at the top left of the image.
+ */
+pub struct Quad {
+    pub top_left: Vec3,
+    pub width: f32,
+    pub height: f32,
+    pub uv: [f32; 4],
+}
+
+/*
+ * Draws many quads sharing one texture with a single draw call,
+ * using the sprite shader with an identity model matrix.
+ */
+pub struct SpriteBatch {
+    shader: Rc<Shader>,
+    vao: VAO,
+}
+
+// Same vertex order as the square buffer
+const CORNERS: [(f32, f32); 6] = [
+    (0.0, 1.0),
+    (1.0, 0.0),
+    (0.0, 0.0),
+    (0.0, 1.0),
+    (1.0, 1.0),
+    (1.0, 0.0),
+];
+
+impl SpriteBatch {
+    pub fn new(
+        gl: &WebGl2RenderingContext,
+        shader: Rc<Shader>,
+    ) -> Result<SpriteBatch, JsValue> {
+        let layout = VertexLayout::new()
+            .attribute("aVertexPosition", 2, VertexData::Dynamic)
+            .attribute("aTextureCoord", 2, VertexData::Dynamic);
+        let vao = VAO::new(gl, &shader, &layout)?;
+        Ok(SpriteBatch { shader, vao })
+    }
+
+    pub fn render(
+        &self,
+        gl: &WebGl2RenderingContext,
+        texture: &Texture,
+        mask: &Texture,
+        color: &[f32; 4],
+        quads: &[Quad],
+    ) {
+        if quads.is_empty() {
+            return;
+        }
+
+        let mut positions = Vec::with_capacity(quads.len() * CORNERS.len() * 2);
+        let mut texture_coordinates = Vec::with_capacity(quads.len() * CORNERS.len() * 2);
+
+        for quad in quads {
+            for (x, y) in CORNERS {
+                positions.push(quad.top_left.x() + x * quad.width);
+                positions.push(quad.top_left.y() + y * quad.height);
+                texture_coordinates.push(quad.uv[0] + x * quad.uv[2]);
+                texture_coordinates.push(quad.uv[1] + y * quad.uv[3]);
+            }
+        }
+
+        self.vao.upload(gl, &[&positions, &texture_coordinates]);
+
+        self.shader.use_program(gl);
+
+        self.shader.set_mat4(gl, "uModelMatrix", &Mat4::identity());
+
+        gl.bind_vertex_array(Some(&self.vao.vao));
+
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture.raw()));
+        self.shader.set_i32(gl, "uTextureSampler", 0);
+
+        gl.active_texture(WebGl2RenderingContext::TEXTURE1);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&mask.raw()));
+        self.shader.set_i32(gl, "uMaskSampler", 1);
+
+        self.shader.set_vec4(gl, "uColor", color);
+        // Texture coordinates are already baked into the vertices
+        self.shader.set_vec4(gl, "uTextureRect", &[0.0, 0.0, 1.0, 1.0]);
+
+        {
+            let offset = 0;
+            let vertex_count = (quads.len() * CORNERS.len()) as i32;
+            gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, offset, vertex_count);
+        }
+
+        gl.bind_vertex_array(None);
+    }
+}
@@ -1,46 +1,49 @@
 use std::rc::Rc;
 
+use crate::matrix::Mat4;
 use crate::sprite::Sprite;
-use crate::sprite_shader::SpriteShader;
-use crate::vao::VAO;
+use crate::shader::Shader;
+use crate::vao::{VertexLayout, VAO};
 use wasm_bindgen::JsValue;
 use web_sys::WebGl2RenderingContext;
 
 pub struct SpriteRenderer {
-    shader: Rc<SpriteShader>,
+    shader: Rc<Shader>,
     vao: VAO
 }
 
 impl SpriteRenderer {
     pub fn new(
         gl: &WebGl2RenderingContext,
-        shader: Rc<SpriteShader>,
+        shader: Rc<Shader>,
     ) -> Result<SpriteRenderer, JsValue> {
 
-        let vao = VAO::new_with_sprite_shader(gl, shader.clone())?;
+        let vao = VAO::new(gl, &shader, &VertexLayout::unit_quad())?;
         Ok(SpriteRenderer { shader, vao })
     }
 
+    pub fn set_projection(&self, gl: &WebGl2RenderingContext, projection: &Mat4) {
+        self.shader.use_program(gl);
+        self.shader.set_mat4(gl, "uProjectionMatrix", projection);
+    }
+
     pub fn render(&self, gl: &WebGl2RenderingContext, sprite: &Sprite) {
-        gl.use_program(Some(&self.shader.program));
+        self.shader.use_program(gl);
 
-        gl.uniform_matrix4fv_with_f32_array(
-            Some(&self.shader.model_matrix_uniform),
-            false,
-            sprite.model().data(),
-        );
+        self.shader.set_mat4(gl, "uModelMatrix", sprite.model());
 
         gl.bind_vertex_array(Some(&self.vao.vao));
 
         gl.active_texture(WebGl2RenderingContext::TEXTURE0);
-        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&sprite.texture()));
-        gl.uniform1i(Some(&self.shader.texture_sampler_uniform), 0);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&sprite.texture().raw()));
+        self.shader.set_i32(gl, "uTextureSampler", 0);
 
         gl.active_texture(WebGl2RenderingContext::TEXTURE1);
-        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&sprite.mask()));
-        gl.uniform1i(Some(&self.shader.mask_sampler_uniform), 1);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&sprite.mask().raw()));
+        self.shader.set_i32(gl, "uMaskSampler", 1);
 
-        gl.uniform4fv_with_f32_array(Some(&self.shader.color_uniform), &sprite.color);
+        self.shader.set_vec4(gl, "uColor", &sprite.color);
+        self.shader.set_vec4(gl, "uTextureRect", &sprite.uv);
 
         {
             let offset = 0;
@@ -50,8 +53,4 @@ impl SpriteRenderer {
 
         gl.bind_vertex_array(None);
     }
-
-    pub fn delete(&self, gl: &WebGl2RenderingContext) {
-        self.vao.delete(gl);
-    }
 }
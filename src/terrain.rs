@@ -2,31 +2,47 @@ use std::rc::Rc;
 
 use js_sys::Float32Array;
 use wasm_bindgen::JsValue;
-use web_sys::{WebGl2RenderingContext, WebGlTexture};
-
-use crate::{texture::create_rgba_texture_from_array_buffer_view, sprite::Sprite, vector::Vec3};
+use web_sys::WebGl2RenderingContext;
+
+use crate::{
+    render_queue::RenderLayer,
+    resources::Texture,
+    sprite::Sprite,
+    texture::{create_rgba_texture_from_array_buffer_view, TextureOptions},
+    vector::Vec3,
+};
+
+// Depth below the surface over which the surface band and the lighting fade out
+pub const SURFACE_BAND_DEPTH: f32 = 24.0;
+// Craters scorch the ground this many radii away from their center
+const SCORCH_REACH: f32 = 1.5;
+// World units on each side of the surface covered by the distance field
+pub const EDGE_DISTANCE_RANGE: f32 = 4.0;
 
 pub fn new_terrain_sprite(
     gl: &WebGl2RenderingContext,
-    foreground_texture: Rc<WebGlTexture>,
+    foreground_texture: Rc<Texture>,
     foreground_mask_buffer: &mut js_sys::Uint8Array,
     terrain_contour: &Float32Array,
-    client_width: u32,
-    client_height: u32)
+    terrain_scorch: &[f32],
+    world_width: u32,
+    world_height: u32)
 -> Result<Sprite, JsValue> {
 
     let foreground_mask_texture = generate_terrain_mask(
         gl,
         foreground_mask_buffer,
         terrain_contour,
-        client_width,
-        client_height
+        terrain_scorch,
+        world_width,
+        world_height
     )?;
 
     let mut terrain_sprite = Sprite::new_with_mask(foreground_texture, foreground_mask_texture)?;
+    terrain_sprite.layer = RenderLayer::Terrain;
     terrain_sprite.global_scale = Vec3::new(
-        client_width as f32,
-        client_height as f32,
+        world_width as f32,
+        world_height as f32,
         1.0,
     );
     terrain_sprite.update();
@@ -37,22 +53,26 @@ pub fn generate_terrain_mask(
     gl: &WebGl2RenderingContext,
     foreground_mask_buffer: &mut js_sys::Uint8Array,
     terrain_contour: &Float32Array,
-    client_width: u32,
-    client_height: u32
-) -> Result<Rc<WebGlTexture>, JsValue> {
-
-    generate_foreground_mask_buffer(
-        foreground_mask_buffer,
-        terrain_contour,
-        client_width,
-        client_height
+    terrain_scorch: &[f32],
+    world_width: u32,
+    world_height: u32
+) -> Result<Rc<Texture>, JsValue> {
+
+    let pixels = terrain_mask_pixels(
+        &terrain_contour.to_vec(),
+        terrain_scorch,
+        world_width,
+        world_height
     );
+    foreground_mask_buffer.copy_from(&pixels);
 
     create_rgba_texture_from_array_buffer_view(
         gl,
-        client_width,
-        client_height,
+        world_width,
+        world_height,
         foreground_mask_buffer,
+        // Filtering interpolates the distance field, the shader finds the edge in it
+        &TextureOptions::default(),
     )
 }
 
@@ -78,27 +98,126 @@ fn contour_function(x: f32, max_y: f32, a: f32, b: f32, c: f32) -> f32 {
         + offset
 }
 
-fn generate_foreground_mask_buffer(
-    buffer: &mut js_sys::Uint8Array,
-    contour: &js_sys::Float32Array,
-    width: u32,
-    height: u32,
-) {
-    for i in 0..width {
-        let contour_height = contour.get_index(i);
-        for j in 0..height {
-            let index = 4 * (j * width + i) as u32;
-
-            let color = match j >= contour_height as u32 {
-                true => 255u8,
-                false => 0u8,
-            };
-
-            buffer.set_index(index, color);
-            buffer.set_index(index + 1, color);
-            buffer.set_index(index + 2, color);
-            buffer.set_index(index + 3, color);
+/*
+ * The terrain mask holds one pixel per world unit:
+ *   r, a  signed distance to the surface, 128 on it, growing into the
+ *         ground and saturating EDGE_DISTANCE_RANGE units away
+ *   g     depth below the surface, reaching 255 at SURFACE_BAND_DEPTH
+ *   b     how scorched the column is by nearby craters
+ * The terrain shader builds the surface band, rims and lighting from it.
+ */
+pub fn terrain_mask_pixels(contour: &[f32], scorch: &[f32], width: u32, height: u32) -> Vec<u8> {
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+
+    for (i, &contour_height) in contour.iter().enumerate().take(width as usize) {
+        let scorched = (scorch.get(i).copied().unwrap_or(0.0).clamp(0.0, 1.0) * 255.0) as u8;
+
+        // Vertical distances shrink by the slope to approximate the distance to the surface
+        let left = contour[i.saturating_sub(1)];
+        let right = contour.get(i + 1).copied().unwrap_or(contour_height);
+        let slope = (right - left) / 2.0;
+        let slope_factor = 1.0 / (1.0 + slope * slope).sqrt();
+
+        let first_row = (contour_height - EDGE_DISTANCE_RANGE).max(0.0) as u32;
+        for j in first_row.min(height)..height {
+            // Measured from the pixel center
+            let distance = (j as f32 + 0.5 - contour_height) * slope_factor;
+            let edge = ((0.5 + 0.5 * distance / EDGE_DISTANCE_RANGE).clamp(0.0, 1.0) * 255.0).round() as u8;
+            let depth = (j as f32 - contour_height).max(0.0) / SURFACE_BAND_DEPTH;
+            let index = 4 * (j as usize * width as usize + i);
+
+            pixels[index..index + 4].copy_from_slice(&[edge, (depth.min(1.0) * 255.0) as u8, scorched, edge]);
+        }
+    }
+
+    pixels
+}
+
+// Burns the ground around a crater, stronger towards its center
+pub fn scorch_crater(scorch: &mut [f32], crater_center_x: f32, crater_radius: f32) {
+    let reach = crater_radius * SCORCH_REACH;
+    let start = (crater_center_x - reach).max(0.0) as usize;
+    let end = ((crater_center_x + reach).ceil() as usize).min(scorch.len());
+
+    for (x, value) in scorch.iter_mut().enumerate().take(end).skip(start) {
+        let amount = 1.0 - (crater_center_x - x as f32).abs() / reach;
+        *value = value.max(amount.clamp(0.0, 1.0));
+    }
+}
+
+// Grass along the surface band: blades on top fading into soil, tiles horizontally
+pub fn surface_pixels(width: u32, height: u32) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            // Blades of uneven length, cheap hash instead of a random generator so it tiles
+            let hash = ((x.wrapping_mul(2_654_435_761)) >> 16) % 256;
+            let blade = 0.25 + 0.35 * hash as f32 / 255.0;
+            let v = y as f32 / height as f32;
+
+            let grass = if v < blade { 1.0 } else { (1.0 - (v - blade) * 4.0).max(0.0) };
+            let shade = 0.8 + 0.2 * ((x * 7 % 13) as f32 / 12.0);
+            let color = [
+                (60.0 + 40.0 * (1.0 - grass)) * shade,
+                (140.0 * grass + 70.0 * (1.0 - grass)) * shade,
+                (40.0 * grass + 35.0 * (1.0 - grass)) * shade,
+            ];
+            // Only the grass is opaque, the soil below shows the ground texture
+            let alpha = (grass * 255.0) as u8;
+            pixels.extend_from_slice(&[color[0] as u8, color[1] as u8, color[2] as u8, alpha]);
         }
     }
+
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{scorch_crater, terrain_mask_pixels, EDGE_DISTANCE_RANGE, SURFACE_BAND_DEPTH};
+
+    #[test]
+    fn mask_encodes_distance_depth_and_scorch() {
+        let height = 64;
+        let pixels = terrain_mask_pixels(&[10.0, 10.0], &[0.0, 1.0], 2, height);
+        let pixel = |x: usize, y: usize| &pixels[(y * 2 + x) * 4..(y * 2 + x) * 4 + 4];
+
+        // Air, out of reach of the distance field
+        assert_eq!(&[0, 0, 0, 0], pixel(0, 2));
+        // Half a unit on either side of the surface
+        assert_eq!(&[112, 0, 0, 112], pixel(0, 9));
+        assert_eq!(&[143, 0, 0, 143], pixel(0, 10));
+        // Deeper than the band
+        assert_eq!(&[255, 255, 0, 255], pixel(0, 10 + SURFACE_BAND_DEPTH as usize));
+        // Half way down the band of a scorched column
+        assert_eq!(&[255, 127, 255, 255], pixel(1, 10 + SURFACE_BAND_DEPTH as usize / 2));
+    }
+
+    #[test]
+    fn slopes_shorten_the_distance_to_the_surface() {
+        let flat = terrain_mask_pixels(&[10.0, 10.0, 10.0], &[], 3, 32);
+        let steep = terrain_mask_pixels(&[0.0, 10.0, 20.0], &[], 3, 32);
+        let alpha = |pixels: &[u8], y: usize| pixels[(y * 3 + 1) * 4 + 3];
+
+        // Twice the range straight down, but close to the slope
+        let below = 10 + 2 * EDGE_DISTANCE_RANGE as usize;
+        assert_eq!(255, alpha(&flat, below));
+        assert!(alpha(&steep, below) > 128 && alpha(&steep, below) < 255);
+    }
+
+    #[test]
+    fn craters_scorch_their_surroundings() {
+        let mut scorch = vec![0.0; 100];
+        scorch_crater(&mut scorch, 50.0, 10.0);
+        scorch_crater(&mut scorch, 60.0, 2.0);
+
+        assert_eq!(1.0, scorch[50]);
+        assert_eq!(0.0, scorch[30]);
+        assert!(scorch[40] > 0.0 && scorch[40] < 1.0);
+        // A small crater does not clear the burn of a bigger one
+        assert!((scorch[60] - 1.0).abs() < 1e-6);
+        assert!(scorch[58] > 1.0 - 10.0 / 15.0);
+    }
 }
 
@@ -0,0 +1,87 @@
+use std::rc::Rc;
+
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::matrix::Mat4;
+use crate::resources::Texture;
+use crate::shader::Shader;
+use crate::sprite::Sprite;
+use crate::terrain::SURFACE_BAND_DEPTH;
+use crate::terrain_shader::new_terrain_shader;
+use crate::vao::{VertexLayout, VAO};
+
+// Light from the upper left, slightly in front of the terrain
+const LIGHT_DIRECTION: [f32; 3] = [-0.3, -0.5, 0.8];
+const AMBIENT: f32 = 0.6;
+// World units covered by one tile of the surface texture
+const SURFACE_TILE_WIDTH: f32 = 128.0;
+// Depth of the grass below the surface in world units
+const SURFACE_DEPTH: f32 = 14.0;
+
+/*
+ * Draws the terrain sprite with the terrain shader, which lights and
+ * decorates the ground using the data packed into the terrain mask.
+ */
+pub struct TerrainRenderer {
+    shader: Shader,
+    vao: VAO,
+    surface: Rc<Texture>,
+}
+
+impl TerrainRenderer {
+    pub fn new(gl: &WebGl2RenderingContext, surface: Rc<Texture>) -> Result<TerrainRenderer, JsValue> {
+        let shader = new_terrain_shader(gl)?;
+        let vao = VAO::new(gl, &shader, &VertexLayout::unit_quad())?;
+        Ok(TerrainRenderer { shader, vao, surface })
+    }
+
+    // Builds the shader and vertex array again after a context loss
+    pub fn restore(&mut self, gl: &WebGl2RenderingContext) -> Result<(), JsValue> {
+        self.shader = new_terrain_shader(gl)?;
+        self.vao = VAO::new(gl, &self.shader, &VertexLayout::unit_quad())?;
+        Ok(())
+    }
+
+    pub fn set_projection(&self, gl: &WebGl2RenderingContext, projection: &Mat4) {
+        self.shader.use_program(gl);
+        self.shader.set_mat4(gl, "uProjectionMatrix", projection);
+    }
+
+    // The sprite is expected to cover the world with the terrain mask
+    pub fn render(&self, gl: &WebGl2RenderingContext, terrain: &Sprite, world_width: f32, world_height: f32) {
+        let shader = &self.shader;
+        shader.use_program(gl);
+
+        shader.set_mat4(gl, "uModelMatrix", terrain.model());
+        shader.set_vec4(gl, "uColor", &terrain.color);
+        shader.set_vec4(gl, "uTextureRect", &terrain.uv);
+        shader.set_vec2(gl, "uWorldSize", &[world_width, world_height]);
+        shader.set_vec2(gl, "uSurfaceScale", &[SURFACE_TILE_WIDTH, SURFACE_BAND_DEPTH / SURFACE_DEPTH]);
+        shader.set_vec3(gl, "uLightDirection", &LIGHT_DIRECTION);
+        shader.set_f32(gl, "uAmbient", AMBIENT);
+
+        gl.bind_vertex_array(Some(&self.vao.vao));
+
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&terrain.texture().raw()));
+        shader.set_i32(gl, "uTextureSampler", 0);
+
+        gl.active_texture(WebGl2RenderingContext::TEXTURE1);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&terrain.mask().raw()));
+        shader.set_i32(gl, "uMaskSampler", 1);
+
+        gl.active_texture(WebGl2RenderingContext::TEXTURE2);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.surface.raw()));
+        shader.set_i32(gl, "uSurfaceSampler", 2);
+
+        {
+            let offset = 0;
+            let vertex_count = 6;
+            gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, offset, vertex_count);
+        }
+
+        gl.bind_vertex_array(None);
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+    }
+}
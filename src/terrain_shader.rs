@@ -0,0 +1,32 @@
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::{
+    glsl::ShaderFile,
+    shader::Shader,
+    sprite_shader::SPRITE_VERTEX,
+};
+
+pub const TERRAIN_FRAGMENT: ShaderFile = ShaderFile {
+    name: "terrain.frag.glsl",
+    source: include_str!("shaders/terrain.frag.glsl"),
+};
+
+pub fn new_terrain_shader(gl: &WebGl2RenderingContext) -> Result<Shader, JsValue> {
+    let shader = Shader::new(gl, &SPRITE_VERTEX, &TERRAIN_FRAGMENT, &[])?;
+    shader.require_uniforms(&[
+        "uModelMatrix",
+        "uProjectionMatrix",
+        "uTextureSampler",
+        "uMaskSampler",
+        "uSurfaceSampler",
+        "uColor",
+        "uTextureRect",
+        "uWorldSize",
+        "uSurfaceScale",
+        "uLightDirection",
+        "uAmbient",
+    ])?;
+
+    Ok(shader)
+}
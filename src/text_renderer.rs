@@ -0,0 +1,77 @@
+use std::rc::Rc;
+
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::{
+    font::BitmapFont, resources::Texture, shader::Shader, sprite_batch::SpriteBatch, vector::Vec3,
+};
+
+pub enum TextAlign {
+    Left,
+    Center,
+}
+
+pub struct TextRenderer {
+    font: BitmapFont,
+    texture: Rc<Texture>,
+    mask: Rc<Texture>,
+    batch: SpriteBatch,
+}
+
+impl TextRenderer {
+    // `texture` holds the font page image referenced by the descriptor
+    pub fn new(
+        gl: &WebGl2RenderingContext,
+        shader: Rc<Shader>,
+        descriptor: &str,
+        texture: Rc<Texture>,
+        mask: Rc<Texture>,
+    ) -> Result<TextRenderer, JsValue> {
+        let font = BitmapFont::parse(descriptor)?;
+        let batch = SpriteBatch::new(gl, shader)?;
+
+        Ok(TextRenderer {
+            font,
+            texture,
+            mask,
+            batch,
+        })
+    }
+
+    pub fn measure(&self, text: &str, scale: f32) -> (f32, f32) {
+        self.font.measure(text, scale)
+    }
+
+    // Draws `text` with its top edge at `position.y()`, aligned horizontally around `position.x()`
+    pub fn render(
+        &self,
+        gl: &WebGl2RenderingContext,
+        text: &str,
+        position: Vec3,
+        scale: f32,
+        align: TextAlign,
+        color: &[f32; 4],
+    ) {
+        let (width, _) = self.measure(text, scale);
+        let x = match align {
+            TextAlign::Left => position.x(),
+            TextAlign::Center => position.x() - width / 2.0,
+        };
+
+        let quads = self
+            .font
+            .layout(text, Vec3::new(x, position.y(), 0.0), scale);
+        self.batch.render(gl, &self.texture, &self.mask, color, &quads);
+    }
+
+    // Rebuilds the batch after a context loss
+    pub fn restore(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        shader: Rc<Shader>,
+    ) -> Result<(), JsValue> {
+        self.batch = SpriteBatch::new(gl, shader)?;
+        Ok(())
+    }
+}
@@ -1,23 +1,220 @@
+use std::cell::RefCell;
 use std::rc::Rc;
 
+use serde::Deserialize;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{HtmlImageElement, WebGl2RenderingContext, WebGlTexture};
 
+use crate::resources::Texture;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureWrap {
+    ClampToEdge,
+    Repeat,
+    MirroredRepeat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureFormat {
+    Rgba8,
+    // Decoded to linear when sampled
+    Srgb8Alpha8,
+}
+
+/*
+ * How a texture is uploaded and sampled. The defaults suit sprites
+ * and masks of any size: linear filtering, clamped edges and no
+ * mipmaps. Premultiplied textures must be drawn with the
+ * ONE, ONE_MINUS_SRC_ALPHA blend function.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TextureOptions {
+    pub min_filter: TextureFilter,
+    pub mag_filter: TextureFilter,
+    pub wrap_s: TextureWrap,
+    pub wrap_t: TextureWrap,
+    pub mipmaps: bool,
+    pub premultiply_alpha: bool,
+    pub flip_y: bool,
+    pub internal_format: TextureFormat,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions {
+            min_filter: TextureFilter::Linear,
+            mag_filter: TextureFilter::Linear,
+            wrap_s: TextureWrap::ClampToEdge,
+            wrap_t: TextureWrap::ClampToEdge,
+            mipmaps: false,
+            premultiply_alpha: false,
+            flip_y: false,
+            internal_format: TextureFormat::Rgba8,
+        }
+    }
+}
+
+impl TextureOptions {
+    // Hard pixel edges, for pixel art and masks
+    pub fn crisp() -> TextureOptions {
+        TextureOptions {
+            min_filter: TextureFilter::Nearest,
+            mag_filter: TextureFilter::Nearest,
+            ..TextureOptions::default()
+        }
+    }
+
+    pub fn min_filter_parameter(&self) -> u32 {
+        match (self.min_filter, self.mipmaps) {
+            (TextureFilter::Nearest, false) => WebGl2RenderingContext::NEAREST,
+            (TextureFilter::Linear, false) => WebGl2RenderingContext::LINEAR,
+            (TextureFilter::Nearest, true) => WebGl2RenderingContext::NEAREST_MIPMAP_NEAREST,
+            (TextureFilter::Linear, true) => WebGl2RenderingContext::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+
+    pub fn mag_filter_parameter(&self) -> u32 {
+        match self.mag_filter {
+            TextureFilter::Nearest => WebGl2RenderingContext::NEAREST,
+            TextureFilter::Linear => WebGl2RenderingContext::LINEAR,
+        }
+    }
+
+    pub fn internal_format_parameter(&self) -> u32 {
+        match self.internal_format {
+            TextureFormat::Rgba8 => WebGl2RenderingContext::RGBA8,
+            TextureFormat::Srgb8Alpha8 => WebGl2RenderingContext::SRGB8_ALPHA8,
+        }
+    }
+}
+
+fn wrap_parameter(wrap: TextureWrap) -> u32 {
+    match wrap {
+        TextureWrap::ClampToEdge => WebGl2RenderingContext::CLAMP_TO_EDGE,
+        TextureWrap::Repeat => WebGl2RenderingContext::REPEAT,
+        TextureWrap::MirroredRepeat => WebGl2RenderingContext::MIRRORED_REPEAT,
+    }
+}
+
+// Pixel store state is global to the context, so it is set before every upload
+fn set_unpack_parameters(gl: &WebGl2RenderingContext, options: &TextureOptions) {
+    gl.pixel_storei(
+        WebGl2RenderingContext::UNPACK_PREMULTIPLY_ALPHA_WEBGL,
+        options.premultiply_alpha as i32,
+    );
+    gl.pixel_storei(WebGl2RenderingContext::UNPACK_FLIP_Y_WEBGL, options.flip_y as i32);
+}
+
+// Expects the texture to be bound and its first level uploaded
+fn set_sampling_parameters(gl: &WebGl2RenderingContext, options: &TextureOptions) {
+    let target = WebGl2RenderingContext::TEXTURE_2D;
+
+    gl.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_S, wrap_parameter(options.wrap_s) as i32);
+    gl.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_T, wrap_parameter(options.wrap_t) as i32);
+    gl.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MIN_FILTER, options.min_filter_parameter() as i32);
+    gl.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MAG_FILTER, options.mag_filter_parameter() as i32);
+
+    if options.mipmaps {
+        gl.generate_mipmap(target);
+    }
+}
+
+// Uploads RGBA pixels into `texture`, replacing its contents
+fn upload_u8_array(
+    gl: &WebGl2RenderingContext,
+    texture: &WebGlTexture,
+    width: u32,
+    height: u32,
+    src_data: &[u8],
+    options: &TextureOptions,
+) -> Result<(), JsValue> {
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(texture));
+    set_unpack_parameters(gl, options);
+
+    let level = 0;
+    let internal_format = options.internal_format_parameter() as i32;
+    let border = 0;
+    let src_format = WebGl2RenderingContext::RGBA;
+    let src_type = WebGl2RenderingContext::UNSIGNED_BYTE;
+
+    //https://docs.rs/web-sys/latest/web_sys/struct.WebGl2RenderingContext.html#method.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array
+    let result = gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        WebGl2RenderingContext::TEXTURE_2D,
+        level,
+        internal_format,
+        width as i32,
+        height as i32,
+        border,
+        src_format,
+        src_type,
+        Some(src_data),
+    );
+
+    if result.is_ok() {
+        set_sampling_parameters(gl, options);
+    }
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+
+    result
+}
+
+fn upload_image(
+    gl: &WebGl2RenderingContext,
+    texture: &WebGlTexture,
+    image: &HtmlImageElement,
+    options: &TextureOptions,
+) -> Result<(), JsValue> {
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(texture));
+    set_unpack_parameters(gl, options);
+
+    let internal_format = options.internal_format_parameter() as i32;
+    let level = 0;
+    let src_format = WebGl2RenderingContext::RGBA;
+    let src_type = WebGl2RenderingContext::UNSIGNED_BYTE;
+
+    let result = gl.tex_image_2d_with_u32_and_u32_and_html_image_element(
+        WebGl2RenderingContext::TEXTURE_2D,
+        level,
+        internal_format,
+        src_format,
+        src_type,
+        image,
+    );
+
+    if result.is_ok() {
+        set_sampling_parameters(gl, options);
+    }
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+
+    result
+}
+
+// The data is not kept, after a context loss the owner has to upload it again
 pub fn create_rgba_texture_from_array_buffer_view(
     gl: &WebGl2RenderingContext,
     width: u32,
     height: u32,
     src_data: &js_sys::Uint8Array,
-) -> Result<Rc<WebGlTexture>, JsValue> {
-    let texture = gl
-        .create_texture()
-        .ok_or_else(|| String::from("Could not make new webgl texture"))?;
+    options: &TextureOptions,
+) -> Result<Rc<Texture>, JsValue> {
+    let texture = Texture::new(gl)?;
 
-    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture.raw()));
+    set_unpack_parameters(gl, options);
 
     let level = 0;
-    let internal_format = WebGl2RenderingContext::RGBA as i32;
+    let internal_format = options.internal_format_parameter() as i32;
     let border = 0;
     let src_format = WebGl2RenderingContext::RGBA;
     let src_type = WebGl2RenderingContext::UNSIGNED_BYTE;
@@ -35,38 +232,29 @@ pub fn create_rgba_texture_from_array_buffer_view(
         Some(src_data),
     )?;
 
-    gl.generate_mipmap(WebGl2RenderingContext::TEXTURE_2D);
-    /*
-        gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
-        gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
-
-        gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::NEAREST as i32);
-        gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::NEAREST as i32);
-    */
+    set_sampling_parameters(gl, options);
     gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
 
-    Ok(Rc::new(texture))
+    Ok(texture)
 }
 
-pub fn create_rgba_texture_from_u8_array(
+// Uninitialised texture to render into, see RenderTarget
+pub fn create_render_texture(
     gl: &WebGl2RenderingContext,
     width: u32,
     height: u32,
-    src_data: &[u8],
-) -> Result<Rc<WebGlTexture>, JsValue> {
-    let texture = gl
-        .create_texture()
-        .ok_or_else(|| String::from("Could not make new webgl texture"))?;
+) -> Result<Rc<Texture>, JsValue> {
+    let texture = Texture::new(gl)?;
+    let options = TextureOptions::default();
 
-    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture.raw()));
 
     let level = 0;
-    let internal_format = WebGl2RenderingContext::RGBA as i32;
+    let internal_format = options.internal_format_parameter() as i32;
     let border = 0;
     let src_format = WebGl2RenderingContext::RGBA;
     let src_type = WebGl2RenderingContext::UNSIGNED_BYTE;
 
-    //https://docs.rs/web-sys/latest/web_sys/struct.WebGl2RenderingContext.html#method.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array
     gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
         WebGl2RenderingContext::TEXTURE_2D,
         level,
@@ -76,67 +264,138 @@ pub fn create_rgba_texture_from_u8_array(
         border,
         src_format,
         src_type,
-        Some(&src_data),
+        None,
     )?;
 
+    set_sampling_parameters(gl, &options);
     gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
 
-    Ok(Rc::new(texture))
+    Ok(texture)
+}
+
+pub fn create_rgba_texture_from_u8_array(
+    gl: &WebGl2RenderingContext,
+    width: u32,
+    height: u32,
+    src_data: &[u8],
+    options: &TextureOptions,
+) -> Result<Rc<Texture>, JsValue> {
+    let texture = Texture::new(gl)?;
+    upload_u8_array(gl, &texture.raw(), width, height, src_data, options)?;
+
+    let src_data = src_data.to_vec();
+    let options = *options;
+    texture.set_upload(move |gl, raw| upload_u8_array(gl, raw, width, height, &src_data, &options));
+
+    Ok(texture)
 }
 
 // texture loading based off of
 // https://developer.mozilla.org/en-US/docs/Web/API/WebGL_API/Tutorial/Using_textures_in_WebGL
-pub fn load_image_as_texture(
+//
+// Returns a 1x1 placeholder texture straight away and uploads the image
+// into it once it has loaded. `on_complete` is called exactly once, with
+// an error if the image could not be fetched, decoded or uploaded.
+pub fn load_image_as_texture<F>(
     gl: &WebGl2RenderingContext,
     source: &str,
-) -> Result<Rc<WebGlTexture>, JsValue> {
+    options: &TextureOptions,
+    on_complete: F,
+) -> Result<Rc<Texture>, JsValue>
+where
+    F: FnOnce(Result<(), String>) + 'static,
+{
     let src_data = [0u8, 0u8, 255u8, 255u8]; // opaque blue
 
-    let texture = create_rgba_texture_from_u8_array(gl, 1, 1, &src_data)?;
+    let texture = create_rgba_texture_from_u8_array(gl, 1, 1, &src_data, options)?;
 
     let image = HtmlImageElement::new()?;
     let image_rc = Rc::new(image);
-    let texture_rc = texture.clone();
+    let on_complete = Rc::new(RefCell::new(Some(on_complete)));
 
     {
         let image = image_rc.clone();
-        let texture = texture_rc.clone();
+        // The callback is never freed, it must not keep the texture alive
+        let texture = Rc::downgrade(&texture);
         let gl = Rc::new(gl.clone());
+        let options = *options;
+        let on_complete = on_complete.clone();
 
         let on_load_callback = Closure::wrap(Box::new(move || {
-            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
-
-            let internal_format = WebGl2RenderingContext::RGBA as i32;
-            let level = 0;
-            let src_format = WebGl2RenderingContext::RGBA;
-            let src_type = WebGl2RenderingContext::UNSIGNED_BYTE;
-
-            let result = gl.tex_image_2d_with_u32_and_u32_and_html_image_element(
-                WebGl2RenderingContext::TEXTURE_2D,
-                level,
-                internal_format,
-                src_format,
-                src_type,
-                &image,
-            );
-
-            match result {
-                Err(e) => {
-                    web_sys::console::log_2(&"load_texture".into(), &e);
-                    return;
-                }
-                _ => (),
+            let texture = match texture.upgrade() {
+                Some(texture) => texture,
+                None => return,
             };
 
-            gl.generate_mipmap(WebGl2RenderingContext::TEXTURE_2D);
-            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+            let result = upload_image(&gl, &texture.raw(), &image, &options);
+
+            if let Err(e) = result {
+                if let Some(on_complete) = on_complete.borrow_mut().take() {
+                    on_complete(Err(format!("Could not upload image: {:?}", e)));
+                }
+                return;
+            }
+
+            // Keep the decoded image around to upload it again after a context loss
+            let image = image.clone();
+            texture.set_upload(move |gl, raw| upload_image(gl, raw, &image, &options));
+
+            if let Some(on_complete) = on_complete.borrow_mut().take() {
+                on_complete(Ok(()));
+            }
         }) as Box<dyn FnMut()>);
 
         image_rc.set_onload(Some(on_load_callback.as_ref().unchecked_ref()));
         on_load_callback.forget();
     }
 
+    {
+        let on_error_callback = Closure::wrap(Box::new(move || {
+            if let Some(on_complete) = on_complete.borrow_mut().take() {
+                on_complete(Err(String::from("Could not load image")));
+            }
+        }) as Box<dyn FnMut()>);
+
+        image_rc.set_onerror(Some(on_error_callback.as_ref().unchecked_ref()));
+        on_error_callback.forget();
+    }
+
     image_rc.set_src(source);
 
-    Ok(texture_rc)
+    Ok(texture)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{TextureFilter, TextureOptions};
+    use web_sys::WebGl2RenderingContext;
+
+    #[test]
+    fn mipmaps_select_a_mipmap_min_filter() {
+        let options = TextureOptions {
+            mipmaps: true,
+            ..TextureOptions::default()
+        };
+        assert_eq!(WebGl2RenderingContext::LINEAR_MIPMAP_LINEAR, options.min_filter_parameter());
+        assert_eq!(WebGl2RenderingContext::LINEAR, options.mag_filter_parameter());
+
+        let crisp = TextureOptions {
+            mipmaps: true,
+            ..TextureOptions::crisp()
+        };
+        assert_eq!(WebGl2RenderingContext::NEAREST_MIPMAP_NEAREST, crisp.min_filter_parameter());
+        assert_eq!(WebGl2RenderingContext::NEAREST, crisp.mag_filter_parameter());
+    }
+
+    #[test]
+    fn options_deserialize_with_defaults() {
+        let options: TextureOptions =
+            serde_json::from_str(r#"{ "mag_filter": "nearest", "wrap_s": "repeat", "mipmaps": true }"#).unwrap();
+
+        assert_eq!(TextureFilter::Nearest, options.mag_filter);
+        assert_eq!(TextureFilter::Linear, options.min_filter);
+        assert!(options.mipmaps);
+        assert!(!options.flip_y);
+    }
 }
@@ -1,17 +1,31 @@
 use serde::{Serialize, Deserialize};
 use wasm_bindgen::JsValue;
 
-use crate::{dom::post_message};
+use crate::{dom::post_message, economy::Account, match_flow::MatchPhase, scoring::RoundResults};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Ui {
     pub current_player: Option<usize>,
     pub player_color: Option<String>,
     pub cannon_power: Option<u32>,
-    pub game_over: Option<bool>,
+    pub phase: Option<MatchPhase>,
+    pub round: Option<u32>,
+    pub rounds: Option<u32>,
+    // Rounds won by every player this match
+    pub wins: Option<Vec<u32>>,
+    pub round_winner: Option<usize>,
+    pub match_winner: Option<usize>,
+    // Only once a round is over
+    pub results: Option<RoundResults>,
+    // Money and inventory of every player
+    pub accounts: Option<Vec<Account>>,
+    // Fraction of assets settled while loading, from 0 to 1
+    pub loading_progress: Option<f32>,
+    pub loading_errors: Option<Vec<String>>,
 }
 
 pub fn post_ui_state(state: &Ui) -> Result<(), JsValue> {
-    let serialized = JsValue::from_serde(state).unwrap();
+    let json = serde_json::to_string(state).map_err(|e| JsValue::from(e.to_string()))?;
+    let serialized = js_sys::JSON::parse(&json)?;
     post_message(&serialized)
 }
@@ -1,143 +1,149 @@
-use std::{rc::Rc, convert::TryInto};
-
 use wasm_bindgen::JsValue;
-use web_sys::{WebGlBuffer, WebGlVertexArrayObject, WebGl2RenderingContext};
-
-use crate::{sprite_shader::SpriteShader, buffer::{create_square_buffer, create_texture_buffer}, particle_shader::ParticleShader};
+use web_sys::WebGl2RenderingContext;
+
+use crate::{
+    buffer::{create_buffer, create_dynamic_buffer, UNIT_SQUARE},
+    resources::{Buffer, VertexArray},
+    shader::{attribute_components, Shader},
+};
+
+#[derive(Clone, Copy)]
+pub enum VertexData<'a> {
+    // Uploaded once when the VAO is built
+    Static(&'a [f32]),
+    // Filled later with `VAO::upload`
+    Dynamic,
+}
 
+pub struct VertexAttribute<'a> {
+    pub name: &'a str,
+    pub components: i32,
+    pub data: VertexData<'a>,
+}
 
 /*
- * Define a Vertex Array Object that
- * can be used with shaders that take
- * two attributes:
- *  * a vec2 of vertex position
- *  * a vec2 of vertex position
- *  */
+ * Describes the float attributes a VAO feeds to a shader, each one
+ * in its own buffer, in the order `VAO::upload` expects them.
+ */
+#[derive(Default)]
+pub struct VertexLayout<'a> {
+    pub attributes: Vec<VertexAttribute<'a>>,
+}
+
+impl<'a> VertexLayout<'a> {
+    pub fn new() -> VertexLayout<'a> {
+        VertexLayout::default()
+    }
+
+    pub fn attribute(mut self, name: &'a str, components: i32, data: VertexData<'a>) -> VertexLayout<'a> {
+        self.attributes.push(VertexAttribute {
+            name,
+            components,
+            data,
+        });
+        self
+    }
+
+    // The unit square positions and texture coordinates used by sprites and particles
+    pub fn unit_quad() -> VertexLayout<'static> {
+        VertexLayout::new()
+            .attribute("aVertexPosition", 2, VertexData::Static(&UNIT_SQUARE))
+            .attribute("aTextureCoord", 2, VertexData::Static(&UNIT_SQUARE))
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct VAO {
-    square_buffer: WebGlBuffer,
-    texture_buffer: WebGlBuffer,
-    pub vao: WebGlVertexArrayObject
+    buffers: Vec<Buffer>,
+    // Whether each buffer was made for `upload`, static ones keep their data
+    dynamic: Vec<bool>,
+    pub vao: VertexArray,
 }
 
 impl VAO {
-    pub fn new_with_sprite_shader(
-        gl: &WebGl2RenderingContext,
-        shader: Rc<SpriteShader>
-    ) -> Result<VAO, JsValue> {
-        let square_buffer = create_square_buffer(&gl)?;
-        let texture_buffer = create_texture_buffer(&gl)?;
+    pub fn new(gl: &WebGl2RenderingContext, shader: &Shader, layout: &VertexLayout) -> Result<VAO, JsValue> {
+        let mut locations = Vec::with_capacity(layout.attributes.len());
+        for attribute in &layout.attributes {
+            let active = shader
+                .attribute(attribute.name)
+                .ok_or_else(|| format!("Shader has no active attribute '{}'", attribute.name))?;
+
+            if let Some(components) = attribute_components(active.kind) {
+                if attribute.components > components {
+                    return Err(format!(
+                        "Attribute '{}' takes {} components, the layout gives {}",
+                        attribute.name, components, attribute.components
+                    )
+                    .into());
+                }
+            }
+
+            locations.push(active.location);
+        }
 
-        let vao = gl
-            .create_vertex_array()
-            .ok_or_else(|| String::from("Could not create VAO"))?;
+        let vao = VertexArray::new(gl)?;
+        let mut buffers = Vec::with_capacity(layout.attributes.len());
+        let mut dynamic = Vec::with_capacity(layout.attributes.len());
 
         gl.bind_vertex_array(Some(&vao));
 
-        {
-            let num_components = 2;
-            let buffer_type = WebGl2RenderingContext::FLOAT;
-            let normalized = false;
-            let stride = 0;
-            let offset = 0;
-            gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&square_buffer));
-            gl.vertex_attrib_pointer_with_i32(
-                shader.vertex_position_attrib.try_into().unwrap(),
-                num_components,
-                buffer_type,
-                normalized,
-                stride,
-                offset,
-            );
-            gl.enable_vertex_attrib_array(shader.vertex_position_attrib.try_into().unwrap());
-        }
+        for (attribute, location) in layout.attributes.iter().zip(locations) {
+            let buffer = match attribute.data {
+                VertexData::Static(data) => {
+                    let array = js_sys::Float32Array::new_with_length(data.len() as u32);
+                    array.copy_from(data);
+                    create_buffer(gl, array)?
+                }
+                VertexData::Dynamic => create_dynamic_buffer(gl)?,
+            };
 
-        {
-            let num_components = 2;
             let buffer_type = WebGl2RenderingContext::FLOAT;
             let normalized = false;
             let stride = 0;
             let offset = 0;
-            gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&texture_buffer));
+            gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
             gl.vertex_attrib_pointer_with_i32(
-                shader.vertex_texture_attrib.try_into().unwrap(),
-                num_components,
+                location,
+                attribute.components,
                 buffer_type,
                 normalized,
                 stride,
                 offset,
             );
-            gl.enable_vertex_attrib_array(shader.vertex_texture_attrib.try_into().unwrap());
+            gl.enable_vertex_attrib_array(location);
 
-            Ok(VAO {
-                square_buffer,
-                texture_buffer,
-                vao
-            })
+            buffers.push(buffer);
+            dynamic.push(matches!(attribute.data, VertexData::Dynamic));
         }
-    }
-
-    pub fn new_with_particle_shader(
-        gl: &WebGl2RenderingContext,
-        shader: Rc<ParticleShader>
-    ) -> Result<VAO, JsValue> {
-        let square_buffer = create_square_buffer(&gl)?;
-        let texture_buffer = create_texture_buffer(&gl)?;
 
-        let vao = gl
-            .create_vertex_array()
-            .ok_or_else(|| String::from("Could not create VAO"))?;
+        gl.bind_vertex_array(None);
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, None);
 
-        gl.bind_vertex_array(Some(&vao));
+        Ok(VAO { buffers, dynamic, vao })
+    }
 
-        {
-            let num_components = 2;
-            let buffer_type = WebGl2RenderingContext::FLOAT;
-            let normalized = false;
-            let stride = 0;
-            let offset = 0;
-            gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&square_buffer));
-            gl.vertex_attrib_pointer_with_i32(
-                shader.vertex_position_attrib.try_into().unwrap(),
-                num_components,
-                buffer_type,
-                normalized,
-                stride,
-                offset,
+    // One slice per attribute, in layout order. Only `VertexData::Dynamic` attributes can be uploaded to
+    pub fn upload(&self, gl: &WebGl2RenderingContext, attribute_data: &[&[f32]]) {
+        debug_assert!(
+            attribute_data.len() <= self.buffers.len(),
+            "Uploaded {} attributes to a VAO with {}",
+            attribute_data.len(),
+            self.buffers.len()
+        );
+
+        for ((buffer, dynamic), data) in self.buffers.iter().zip(&self.dynamic).zip(attribute_data) {
+            debug_assert!(*dynamic, "Uploaded to a static vertex buffer");
+            let array = js_sys::Float32Array::new_with_length(data.len() as u32);
+            array.copy_from(data);
+
+            gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(buffer));
+            gl.buffer_data_with_array_buffer_view(
+                WebGl2RenderingContext::ARRAY_BUFFER,
+                &array,
+                WebGl2RenderingContext::DYNAMIC_DRAW,
             );
-            gl.enable_vertex_attrib_array(shader.vertex_position_attrib.try_into().unwrap());
         }
 
-        {
-            let num_components = 2;
-            let buffer_type = WebGl2RenderingContext::FLOAT;
-            let normalized = false;
-            let stride = 0;
-            let offset = 0;
-            gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&texture_buffer));
-            gl.vertex_attrib_pointer_with_i32(
-                shader.vertex_texture_attrib.try_into().unwrap(),
-                num_components,
-                buffer_type,
-                normalized,
-                stride,
-                offset,
-            );
-            gl.enable_vertex_attrib_array(shader.vertex_texture_attrib.try_into().unwrap());
-
-            Ok(VAO {
-                square_buffer,
-                texture_buffer,
-                vao
-            })
-        }
-    }
-
-    pub fn delete(&self, gl: &WebGl2RenderingContext) {
-        gl.delete_buffer(Some(&self.square_buffer));
-        gl.delete_buffer(Some(&self.texture_buffer));
-        gl.delete_vertex_array(Some(&self.vao));
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, None);
     }
 }
-
-
-
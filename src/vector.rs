@@ -1,6 +1,6 @@
 use std::ops::{Add, AddAssign, Sub};
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Vec3 {
     pub data: [f32; 3],
 }
@@ -56,16 +56,6 @@ impl AddAssign for Vec3 {
     }
 }
 
-impl Copy for Vec3 {}
-
-impl Clone for Vec3 {
-    fn clone(&self) -> Vec3 {
-        Self {
-            data: [self.data[0], self.data[1], self.data[2]],
-        }
-    }
-}
-
 impl Sub for Vec3 {
     type Output = Self;
 